@@ -0,0 +1,202 @@
+use crate::{Decision, HostnameClass};
+use std::time::SystemTime;
+
+/// Receives an [`AuditEvent`] for every decision the middleware makes
+///
+/// [`record`](AuditSink::record) is called exactly once per request, from inside the request
+/// future, after the decision is made and before the inner service is called or the
+/// rejection is returned. Events for concurrent requests may arrive in any order.
+///
+/// Implementations should not block; hand events off to a channel or background task if
+/// writing to the audit store is slow.
+pub trait AuditSink: Send + Sync {
+    /// Record a single decision
+    fn record(&self, event: AuditEvent);
+}
+
+/// Audit sink that discards every event (default)
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoopAuditSink;
+
+impl AuditSink for NoopAuditSink {
+    fn record(&self, _event: AuditEvent) {}
+}
+
+/// A single decision made by the middleware
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct AuditEvent {
+    /// When the decision was made
+    pub timestamp: SystemTime,
+    /// [`TurnstileOutcome::verification_id`](crate::TurnstileOutcome::verification_id) of
+    /// the decision
+    pub verification_id: String,
+    /// The decision itself
+    pub decision: Decision,
+    /// Hex-encoded SHA-256 of the token, if one was present
+    pub token_hash: Option<String>,
+    /// Client IP resolved for `remoteip`, if any
+    pub remote_ip: Option<String>,
+    /// Client country, if [geo capture](crate::TurnstileConfig::with_geo_capture) is enabled
+    pub country: Option<String>,
+    /// `action` reported by Cloudflare, if verification got that far
+    pub action: Option<String>,
+    /// `hostname` reported by Cloudflare, if verification got that far
+    pub hostname: Option<String>,
+    /// Whether `hostname` is first- or third-party, for metric labels
+    pub hostname_class: Option<HostnameClass>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_support::MockSiteverify, RejectionReason, TurnstileConfig, TurnstileLayer};
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        routing::post,
+        Router,
+    };
+    use std::sync::{Arc, Mutex};
+    use tower::ServiceExt;
+
+    #[derive(Default)]
+    struct RecordingSink(Mutex<Vec<AuditEvent>>);
+
+    impl AuditSink for RecordingSink {
+        fn record(&self, event: AuditEvent) {
+            self.0.lock().unwrap().push(event);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sink_receives_pass_and_fail() {
+        let mock = MockSiteverify::respond_with(|request| {
+            if request.json()["response"] == "good-token" {
+                serde_json::json!({ "success": true, "action": "login", "hostname": "myapp.example" })
+            } else {
+                serde_json::json!({ "success": false, "error-codes": ["invalid-input-response"] })
+            }
+        })
+        .await;
+        let sink = Arc::new(RecordingSink::default());
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&mock.url)
+            .with_remote_ip_header("CF-Connecting-IP")
+            .with_audit_sink(sink.clone());
+        let app = Router::new()
+            .route("/submit", post(|| async { "OK" }))
+            .layer(TurnstileLayer::new(config));
+
+        for (token, expected) in [
+            ("good-token", StatusCode::OK),
+            ("bad-token", StatusCode::FORBIDDEN),
+        ] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::post("/submit")
+                        .header("CF-Turnstile-Token", token)
+                        .header("CF-Connecting-IP", "203.0.113.7")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), expected);
+        }
+
+        let events = sink.0.lock().unwrap();
+        assert_eq!(events.len(), 2);
+
+        assert_eq!(events[0].decision, Decision::Verified);
+        assert_eq!(events[0].action.as_deref(), Some("login"));
+        assert_eq!(events[0].hostname.as_deref(), Some("myapp.example"));
+        assert_eq!(events[0].remote_ip.as_deref(), Some("203.0.113.7"));
+        assert_eq!(
+            events[0].token_hash.as_deref(),
+            Some(crate::token::hash_token("good-token").as_str())
+        );
+
+        assert_eq!(
+            events[1].decision,
+            Decision::Rejected(RejectionReason::VerificationFailed(vec![
+                "invalid-input-response".to_string()
+            ]))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sink_receives_missing_token() {
+        let sink = Arc::new(RecordingSink::default());
+        let app = Router::new()
+            .route("/submit", post(|| async { "OK" }))
+            .layer(TurnstileLayer::new(
+                TurnstileConfig::new("secret").with_audit_sink(sink.clone()),
+            ));
+
+        app.oneshot(Request::post("/submit").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        let events = sink.0.lock().unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(
+            events[0].decision,
+            Decision::Rejected(RejectionReason::MissingToken)
+        );
+        assert_eq!(events[0].token_hash, None);
+    }
+
+    #[tokio::test]
+    async fn test_result_callback_fires_once_per_request() {
+        let mock = MockSiteverify::respond_with(|request| {
+            if request.json()["response"] == "good-token" {
+                serde_json::json!({ "success": true })
+            } else {
+                serde_json::json!({ "success": false, "error-codes": ["invalid-input-response"] })
+            }
+        })
+        .await;
+        let results = Arc::new(Mutex::new(Vec::new()));
+        let recorded = results.clone();
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&mock.url)
+            .with_result_callback(move |outcome| {
+                let label = match &outcome.decision {
+                    Decision::Verified => "success".to_string(),
+                    Decision::Rejected(RejectionReason::VerificationFailed(codes)) => {
+                        format!("failure:{}", codes.join(","))
+                    }
+                    decision => format!("{decision:?}"),
+                };
+                recorded
+                    .lock()
+                    .unwrap()
+                    .push((label, outcome.timings.is_some()));
+            });
+        let app = Router::new()
+            .route("/submit", post(|| async { "OK" }))
+            .layer(TurnstileLayer::new(config));
+
+        for token in [Some("good-token"), Some("bad-token"), None] {
+            let mut request = Request::post("/submit");
+            if let Some(token) = token {
+                request = request.header("CF-Turnstile-Token", token);
+            }
+            app.clone()
+                .oneshot(request.body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(
+            *results.lock().unwrap(),
+            [
+                ("success".to_string(), true),
+                ("failure:invalid-input-response".to_string(), true),
+                ("Rejected(MissingToken)".to_string(), false),
+            ]
+        );
+    }
+}
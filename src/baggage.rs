@@ -0,0 +1,90 @@
+use crate::TurnstileOutcome;
+use opentelemetry::{baggage::BaggageExt, Context, KeyValue};
+
+/// The current OpenTelemetry context with the outcome added as `turnstile.*` baggage
+///
+/// Sets `turnstile.verified` to `true` or `false`, and `turnstile.action` when Cloudflare
+/// reported one, so propagators pass them on with outgoing requests.
+pub(crate) fn context(outcome: &TurnstileOutcome) -> Context {
+    let mut entries = vec![KeyValue::new(
+        "turnstile.verified",
+        outcome.decision.is_verified().to_string(),
+    )];
+    if let Some(action) = &outcome.action {
+        entries.push(KeyValue::new("turnstile.action", action.clone()));
+    }
+    Context::current_with_baggage(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{test_support::MockSiteverify, Mode, TurnstileConfig, TurnstileLayer};
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        routing::post,
+        Router,
+    };
+    use opentelemetry::{baggage::BaggageExt, Context};
+    use tower::ServiceExt;
+
+    /// Baggage the handler sees, as `key=value` pairs sorted by key
+    async fn handler_baggage(config: TurnstileConfig, token: &str) -> String {
+        let app = Router::new()
+            .route(
+                "/submit",
+                post(|| async {
+                    let cx = Context::current();
+                    let mut entries: Vec<_> = cx
+                        .baggage()
+                        .iter()
+                        .map(|(key, (value, _))| format!("{key}={value}"))
+                        .collect();
+                    entries.sort();
+                    entries.join(",")
+                }),
+            )
+            .layer(TurnstileLayer::new(config));
+
+        let response = app
+            .oneshot(
+                Request::post("/submit")
+                    .header("CF-Turnstile-Token", token)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_outcome_set_as_baggage() {
+        let mock = MockSiteverify::respond_with(|request| {
+            serde_json::json!({
+                "success": request.json()["response"] == "good",
+                "action": "login",
+            })
+        })
+        .await;
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&mock.url)
+            .with_mode(Mode::Monitor);
+
+        assert_eq!(handler_baggage(config.clone(), "good").await, "");
+
+        let config = config.with_otel_baggage(true);
+        assert_eq!(
+            handler_baggage(config.clone(), "good").await,
+            "turnstile.action=login,turnstile.verified=true"
+        );
+        assert_eq!(
+            handler_baggage(config, "bad").await,
+            "turnstile.action=login,turnstile.verified=false"
+        );
+    }
+}
@@ -0,0 +1,309 @@
+use axum::{
+    body::{Body, Bytes},
+    http::{header, HeaderMap, HeaderValue},
+};
+use futures_util::{stream, StreamExt};
+use http_body::Frame;
+use http_body_util::{BodyExt, BodyStream, StreamBody};
+
+/// What to do when a body token source meets a body larger than the configured cap
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OversizePolicy {
+    /// Reject the request with `413 Payload Too Large` (default)
+    #[default]
+    Reject413,
+    /// Pass the request through untouched and unverified, without a [`VerifiedTurnstile`]
+    ///
+    /// [`VerifiedTurnstile`]: crate::VerifiedTurnstile
+    SkipVerification,
+}
+
+/// Why a body couldn't be buffered
+pub(crate) enum BufferError {
+    /// The body exceeds the limit; carries a body that replays everything read so far
+    /// followed by the unread remainder
+    TooLarge(Body),
+    /// Reading the body failed
+    Read,
+}
+
+/// Buffer a request body of at most `limit` bytes
+pub(crate) async fn buffer(
+    headers: &HeaderMap,
+    body: Body,
+    limit: usize,
+) -> Result<Bytes, BufferError> {
+    buffer_with_trailers(headers, body, limit)
+        .await
+        .map(|(bytes, _)| bytes)
+}
+
+/// Buffer a request body of at most `limit` bytes, along with its trailers if it has any
+pub(crate) async fn buffer_with_trailers(
+    headers: &HeaderMap,
+    mut body: Body,
+    limit: usize,
+) -> Result<(Bytes, Option<HeaderMap>), BufferError> {
+    // Don't read anything if the client already told us the body is too large
+    let content_length = headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<usize>().ok());
+    if content_length.is_some_and(|len| len > limit) {
+        return Err(BufferError::TooLarge(body));
+    }
+
+    let mut chunks = Vec::new();
+    let mut len = 0;
+    let mut trailers: Option<HeaderMap> = None;
+
+    while let Some(frame) = body.frame().await {
+        let frame = match frame.map_err(|_| BufferError::Read)?.into_data() {
+            Ok(chunk) => {
+                len += chunk.len();
+                chunks.push(chunk);
+
+                if len > limit {
+                    let replay = stream::iter(chunks.into_iter().map(|c| Ok(Frame::data(c))));
+                    return Err(BufferError::TooLarge(Body::new(StreamBody::new(
+                        replay.chain(BodyStream::new(body)),
+                    ))));
+                }
+                continue;
+            }
+            Err(frame) => frame,
+        };
+        if let Ok(frame_trailers) = frame.into_trailers() {
+            trailers.get_or_insert_default().extend(frame_trailers);
+        }
+    }
+
+    let bytes = match chunks.len() {
+        1 => chunks.remove(0),
+        _ => Bytes::from(chunks.concat()),
+    };
+    Ok((bytes, trailers))
+}
+
+/// The body to hand on after buffering: empty if
+/// [`TurnstileConfig::consume_body`](crate::TurnstileConfig::consume_body) is set, otherwise
+/// the buffered one with its trailers
+///
+/// A replayed body has the exact bytes that were read, so the request's own
+/// `Content-Length` or `Transfer-Encoding` still describe it and are left alone. An empty
+/// body gets `Content-Length: 0` instead.
+pub(crate) fn restore(
+    headers: &mut HeaderMap,
+    bytes: Bytes,
+    trailers: Option<HeaderMap>,
+    consume: bool,
+) -> Body {
+    if consume {
+        headers.remove(header::TRANSFER_ENCODING);
+        headers.insert(header::CONTENT_LENGTH, HeaderValue::from(0));
+        Body::empty()
+    } else {
+        replay(bytes, trailers)
+    }
+}
+
+/// Rebuild a buffered body, trailers included
+fn replay(bytes: Bytes, trailers: Option<HeaderMap>) -> Body {
+    let Some(trailers) = trailers else {
+        return Body::from(bytes);
+    };
+    let frames = [Frame::data(bytes), Frame::trailers(trailers)];
+    Body::new(StreamBody::new(stream::iter(
+        frames.map(Ok::<_, std::convert::Infallible>),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        test_support::MockSiteverify, OversizePolicy, TokenSource, TurnstileConfig, TurnstileLayer,
+        VerifiedTurnstile,
+    };
+    use axum::{
+        body::{Body, Bytes},
+        extract::Request,
+        http::{header, HeaderMap, StatusCode},
+        routing::post,
+        Router,
+    };
+    use tower::ServiceExt;
+
+    const PAYLOAD: &str = r#"{"token":"body-token","padding":"xxxxxxxxxxxxxxxxxxxxxxxx"}"#;
+
+    async fn send(policy: OversizePolicy, body: Body) -> (StatusCode, String) {
+        let mock = MockSiteverify::success().await;
+        let config = TurnstileConfig::new("test-secret")
+            .with_verify_url(&mock.url)
+            .with_token_source(TokenSource::JsonPointer("/token".into()))
+            .with_max_body_bytes(16)
+            .with_oversize_policy(policy);
+
+        let app = Router::new()
+            .route(
+                "/upload",
+                post(|req: Request| async move {
+                    let verified = req.extensions().get::<VerifiedTurnstile>().is_some();
+                    let body = axum::body::to_bytes(req.into_body(), usize::MAX)
+                        .await
+                        .unwrap();
+                    format!("verified={verified} {}", String::from_utf8_lossy(&body))
+                }),
+            )
+            .layer(TurnstileLayer::new(config));
+
+        let response = app
+            .oneshot(Request::post("/upload").body(body).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    /// A body without a `Content-Length`, delivered in small chunks
+    fn chunked(payload: &'static str) -> Body {
+        let chunks = payload
+            .as_bytes()
+            .chunks(10)
+            .map(|chunk| Ok::<_, std::io::Error>(Bytes::from_static(chunk)));
+        Body::from_stream(futures_util::stream::iter(chunks))
+    }
+
+    #[tokio::test]
+    async fn test_oversize_rejected_by_default() {
+        let (status, _) = send(OversizePolicy::default(), Body::from(PAYLOAD)).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+
+        let (status, _) = send(OversizePolicy::default(), chunked(PAYLOAD)).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn test_oversize_skips_verification() {
+        let (status, body) = send(OversizePolicy::SkipVerification, chunked(PAYLOAD)).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, format!("verified=false {PAYLOAD}"));
+    }
+
+    #[tokio::test]
+    async fn test_body_limits_by_verification() {
+        let mock = MockSiteverify::respond_with(
+            |request| serde_json::json!({ "success": request.json()["response"] == "good" }),
+        )
+        .await;
+        // Monitor mode lets the failing token through unverified
+        let config = TurnstileConfig::new("test-secret")
+            .with_verify_url(&mock.url)
+            .with_mode(crate::Mode::Monitor)
+            .with_body_limits(16, 64);
+        let app = Router::new()
+            .route("/upload", post(|body: String| async move { body }))
+            .layer(TurnstileLayer::new(config));
+
+        for (token, len, expected) in [
+            ("bad", 8, StatusCode::OK),
+            ("bad", 32, StatusCode::PAYLOAD_TOO_LARGE),
+            ("good", 32, StatusCode::OK),
+            ("good", 100, StatusCode::PAYLOAD_TOO_LARGE),
+        ] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::post("/upload")
+                        .header("CF-Turnstile-Token", token)
+                        .body(Body::from("x".repeat(len)))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), expected, "{token} token, {len} bytes");
+        }
+    }
+
+    /// Echoes the body it got, and its framing headers as `x-received-*` headers
+    async fn echo(config: TurnstileConfig, request: Request<Body>) -> (HeaderMap, Bytes) {
+        let app = Router::new()
+            .route(
+                "/echo",
+                post(|req: Request| async move {
+                    let mut headers = HeaderMap::new();
+                    for (name, echoed) in [
+                        (header::CONTENT_LENGTH, "x-received-content-length"),
+                        (header::TRANSFER_ENCODING, "x-received-transfer-encoding"),
+                    ] {
+                        if let Some(value) = req.headers().get(name) {
+                            headers.insert(echoed, value.clone());
+                        }
+                    }
+                    let body = axum::body::to_bytes(req.into_body(), usize::MAX)
+                        .await
+                        .unwrap();
+                    (headers, body)
+                }),
+            )
+            .layer(TurnstileLayer::new(config));
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let headers = response.headers().clone();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (headers, body)
+    }
+
+    #[tokio::test]
+    async fn test_buffered_body_is_handed_on_byte_for_byte() {
+        let mock = MockSiteverify::success().await;
+        let config = TurnstileConfig::new("test-secret")
+            .with_verify_url(&mock.url)
+            .with_token_source(TokenSource::FormField("cf-turnstile-response".into()));
+
+        // Every byte value, including invalid UTF-8 and a stray `%` the form parser decodes
+        let mut payload = b"cf-turnstile-response=tok%2Fen&blob=".to_vec();
+        payload.extend(0..=255u8);
+        let payload = Bytes::from(payload);
+
+        let request = Request::post("/echo")
+            .header(header::CONTENT_LENGTH, payload.len())
+            .body(Body::from(payload.clone()))
+            .unwrap();
+        let (headers, body) = echo(config.clone(), request).await;
+        assert_eq!(body, payload);
+        assert_eq!(
+            headers["x-received-content-length"],
+            payload.len().to_string()
+        );
+        assert_eq!(mock.requests()[0].json()["response"], "tok/en");
+
+        let chunks: Vec<_> = payload
+            .chunks(7)
+            .map(|chunk| Ok::<_, std::io::Error>(Bytes::copy_from_slice(chunk)))
+            .collect();
+        let request = Request::post("/echo")
+            .header(header::TRANSFER_ENCODING, "chunked")
+            .body(Body::from_stream(futures_util::stream::iter(chunks)))
+            .unwrap();
+        let (headers, body) = echo(config.clone(), request).await;
+        assert_eq!(body, payload);
+        assert_eq!(headers["x-received-transfer-encoding"], "chunked");
+        assert!(!headers.contains_key("x-received-content-length"));
+
+        // A consumed body doesn't keep claiming its old length
+        let request = Request::post("/echo")
+            .header(header::TRANSFER_ENCODING, "chunked")
+            .body(Body::from(payload.clone()))
+            .unwrap();
+        let (headers, body) = echo(config.with_consume_body(true), request).await;
+        assert!(body.is_empty());
+        assert_eq!(headers["x-received-content-length"], "0");
+        assert!(!headers.contains_key("x-received-transfer-encoding"));
+    }
+}
@@ -0,0 +1,159 @@
+use axum::http::HeaderMap;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use ring::hmac;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Header carrying a break-glass token
+pub const BREAK_GLASS_HEADER: &str = "X-Break-Glass";
+
+/// Validates break-glass tokens set up with
+/// [`TurnstileConfig::with_break_glass`](crate::TurnstileConfig::with_break_glass)
+pub(crate) struct BreakGlass {
+    key: hmac::Key,
+    ttl: Duration,
+}
+
+impl BreakGlass {
+    pub(crate) fn new(ops_key: &[u8], ttl: Duration) -> Self {
+        Self {
+            key: hmac::Key::new(hmac::HMAC_SHA256, ops_key),
+            ttl,
+        }
+    }
+
+    /// Whether the request carries a valid, unexpired break-glass token
+    pub(crate) fn accepts(&self, headers: &HeaderMap) -> bool {
+        headers
+            .get(BREAK_GLASS_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|token| self.accepts_at(token, SystemTime::now()))
+    }
+
+    fn accepts_at(&self, token: &str, now: SystemTime) -> bool {
+        let Some((issued, signature)) = token.trim().split_once('.') else {
+            return false;
+        };
+        let Ok(signature) = URL_SAFE_NO_PAD.decode(signature) else {
+            return false;
+        };
+        if hmac::verify(&self.key, issued.as_bytes(), &signature).is_err() {
+            return false;
+        }
+
+        let Ok(issued) = issued.parse::<u64>() else {
+            return false;
+        };
+        let now = unix_secs(now);
+        issued <= now && now - issued < self.ttl.as_secs()
+    }
+}
+
+/// Mint a break-glass token for the `X-Break-Glass` header, issued now
+///
+/// Tokens are `<issued unix seconds>.<base64url HMAC-SHA256 of the issue time>`, so ops
+/// tooling without this crate can mint them too. They're accepted until the `ttl` given to
+/// [`TurnstileConfig::with_break_glass`](crate::TurnstileConfig::with_break_glass) has
+/// passed, by any server holding the same key.
+pub fn mint_break_glass_token(ops_key: impl AsRef<[u8]>) -> String {
+    mint_at(ops_key.as_ref(), SystemTime::now())
+}
+
+fn mint_at(ops_key: &[u8], now: SystemTime) -> String {
+    let issued = unix_secs(now).to_string();
+    let key = hmac::Key::new(hmac::HMAC_SHA256, ops_key);
+    let signature = hmac::sign(&key, issued.as_bytes());
+    format!("{issued}.{}", URL_SAFE_NO_PAD.encode(signature.as_ref()))
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_support::MockSiteverify, AuditEvent, AuditSink, Decision, TurnstileConfig,
+        TurnstileLayer,
+    };
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        routing::post,
+        Router,
+    };
+    use std::sync::{Arc, Mutex};
+    use tower::ServiceExt;
+
+    const OPS_KEY: &[u8] = b"ops-key";
+
+    #[derive(Default)]
+    struct RecordingSink(Mutex<Vec<AuditEvent>>);
+
+    impl AuditSink for RecordingSink {
+        fn record(&self, event: AuditEvent) {
+            self.0.lock().unwrap().push(event);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_valid_break_glass_bypasses_and_is_audited() {
+        let mock = MockSiteverify::success().await;
+        let sink = Arc::new(RecordingSink::default());
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&mock.url)
+            .with_break_glass(OPS_KEY, Duration::from_secs(600))
+            .with_audit_sink(sink.clone());
+        let app = Router::new()
+            .route("/submit", post(|| async { "OK" }))
+            .layer(TurnstileLayer::new(config));
+
+        let response = app
+            .oneshot(
+                Request::post("/submit")
+                    .header(BREAK_GLASS_HEADER, mint_break_glass_token(OPS_KEY))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(mock.requests().is_empty());
+        let events = sink.0.lock().unwrap();
+        assert_eq!(events[0].decision, Decision::BypassedByBreakGlass);
+    }
+
+    #[tokio::test]
+    async fn test_expired_or_forged_break_glass_is_ignored() {
+        let ttl = Duration::from_secs(600);
+        let config = TurnstileConfig::new("secret").with_break_glass(OPS_KEY, ttl);
+        let app = Router::new()
+            .route("/submit", post(|| async { "OK" }))
+            .layer(TurnstileLayer::new(config));
+
+        let valid = mint_break_glass_token(OPS_KEY);
+        let (_, signature) = valid.split_once('.').unwrap();
+        let tampered = format!("{}.{signature}", unix_secs(SystemTime::now()) + 1);
+        for token in [
+            mint_at(OPS_KEY, SystemTime::now() - ttl),
+            mint_break_glass_token(b"other-key"),
+            tampered,
+            "garbage".to_string(),
+        ] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::post("/submit")
+                        .header(BREAK_GLASS_HEADER, token)
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        }
+    }
+}
@@ -0,0 +1,339 @@
+use crate::{expiring::ExpiringMap, timestamp, TurnstileErrorCode, VerifyResponse};
+use std::{
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+/// Which failed verifications the result cache keeps
+///
+/// A cached failure rejects resubmissions of the same token without asking Cloudflare
+/// again, so caching failures that might not recur, like transient ones, turns them into
+/// lasting rejections.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NegativeCaching {
+    /// Never cache failures
+    None,
+    /// Only cache `timeout-or-duplicate`, which can't succeed on a retry (default)
+    #[default]
+    DuplicatesOnly,
+    /// Cache every failure
+    All,
+}
+
+impl NegativeCaching {
+    fn caches(self, response: &VerifyResponse) -> bool {
+        match self {
+            NegativeCaching::None => false,
+            NegativeCaching::DuplicatesOnly => response.error_codes.as_ref().is_some_and(|codes| {
+                TurnstileErrorCode::parse_all(codes)
+                    .contains(&TurnstileErrorCode::TimeoutOrDuplicate)
+            }),
+            NegativeCaching::All => true,
+        }
+    }
+}
+
+/// How long a cached verification result stays valid
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum CacheTtl {
+    /// Until the token's `challenge_ts` plus this window
+    FromChallenge(Duration),
+    /// For this long after the result came in
+    Fixed(Duration),
+}
+
+/// In-memory cache of verification results keyed by token hash
+#[derive(Debug)]
+pub(crate) struct ResultCache {
+    ttl: CacheTtl,
+    max_age: Option<Duration>,
+    entries: Mutex<ExpiringMap<String, (SystemTime, VerifyResponse)>>,
+}
+
+impl ResultCache {
+    pub fn new(ttl: CacheTtl) -> Self {
+        Self {
+            ttl,
+            max_age: None,
+            entries: Mutex::new(ExpiringMap::new()),
+        }
+    }
+
+    /// An empty cache with the same TTL whose entries never live longer than `max_age`
+    pub fn with_max_age(&self, max_age: Duration) -> Self {
+        Self {
+            max_age: Some(max_age),
+            ..Self::new(self.ttl)
+        }
+    }
+
+    /// Look up a cached result for the token hash
+    pub fn get(&self, key: &str) -> Option<VerifyResponse> {
+        self.get_at(key, SystemTime::now())
+    }
+
+    /// Cache a result for the token hash, if its expiry can be determined and `negative`
+    /// allows caching it
+    pub fn insert(&self, key: String, response: &VerifyResponse, negative: NegativeCaching) {
+        if response.success || negative.caches(response) {
+            self.insert_at(key, response, SystemTime::now());
+        }
+    }
+
+    fn get_at(&self, key: &str, now: SystemTime) -> Option<VerifyResponse> {
+        let entries = self.entries.lock().unwrap();
+        let (expires_at, response) = entries.get(key)?;
+        (now < *expires_at).then(|| response.clone())
+    }
+
+    fn insert_at(&self, key: String, response: &VerifyResponse, now: SystemTime) {
+        let Some(mut expires_at) = self.expiry(response, now) else {
+            return;
+        };
+        if let Some(capped) = self.max_age.and_then(|max_age| now.checked_add(max_age)) {
+            expires_at = expires_at.min(capped);
+        }
+        if expires_at <= now {
+            return;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        entries.prune(|_, (expires_at, _)| now < *expires_at);
+        entries.insert(key, (expires_at, response.clone()));
+    }
+
+    fn expiry(&self, response: &VerifyResponse, now: SystemTime) -> Option<SystemTime> {
+        match self.ttl {
+            CacheTtl::FromChallenge(window) => {
+                // Failures don't report a challenge, so they're kept for the window from now
+                let issued_at = match response.challenge_ts.as_deref() {
+                    Some(challenge_ts) => timestamp::parse_rfc3339(challenge_ts)?,
+                    None if !response.success => now,
+                    None => return None,
+                };
+                issued_at.checked_add(window)
+            }
+            CacheTtl::Fixed(ttl) => now.checked_add(ttl),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_support::MockSiteverify, TurnstileConfig, TurnstileLayer};
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        routing::post,
+        Router,
+    };
+    use tower::ServiceExt;
+
+    fn response(challenge_ts: &str) -> VerifyResponse {
+        serde_json::from_value(serde_json::json!({
+            "success": true,
+            "challenge_ts": challenge_ts,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_entry_expires_relative_to_challenge_ts() {
+        let cache = ResultCache::new(CacheTtl::FromChallenge(Duration::from_secs(300)));
+        let issued_at = timestamp::parse_rfc3339("2024-01-01T00:00:00Z").unwrap();
+
+        // Inserted 200s after the challenge, so 100s of validity remain
+        let inserted_at = issued_at + Duration::from_secs(200);
+        cache.insert_at("key".into(), &response("2024-01-01T00:00:00Z"), inserted_at);
+
+        let before_expiry = issued_at + Duration::from_secs(299);
+        assert!(cache.get_at("key", before_expiry).is_some());
+
+        let after_expiry = issued_at + Duration::from_secs(300);
+        assert!(cache.get_at("key", after_expiry).is_none());
+    }
+
+    #[test]
+    fn test_max_age_caps_challenge_ttl() {
+        let cache = ResultCache::new(CacheTtl::FromChallenge(Duration::from_secs(300)))
+            .with_max_age(Duration::from_secs(60));
+        let issued_at = timestamp::parse_rfc3339("2024-01-01T00:00:00Z").unwrap();
+
+        // The challenge would allow caching until +300s, the cap only until +70s
+        let inserted_at = issued_at + Duration::from_secs(10);
+        cache.insert_at("key".into(), &response("2024-01-01T00:00:00Z"), inserted_at);
+
+        assert!(cache
+            .get_at("key", issued_at + Duration::from_secs(69))
+            .is_some());
+        assert!(cache
+            .get_at("key", issued_at + Duration::from_secs(70))
+            .is_none());
+    }
+
+    #[test]
+    fn test_result_without_challenge_ts_not_cached() {
+        let cache = ResultCache::new(CacheTtl::FromChallenge(Duration::from_secs(300)));
+        let success: VerifyResponse =
+            serde_json::from_value(serde_json::json!({ "success": true })).unwrap();
+
+        cache.insert("key".into(), &success, NegativeCaching::All);
+
+        assert!(cache.get("key").is_none());
+    }
+
+    #[test]
+    fn test_fixed_ttl_ignores_challenge_ts() {
+        let cache = ResultCache::new(CacheTtl::Fixed(Duration::from_secs(2)));
+        let inserted_at = timestamp::parse_rfc3339("2024-01-01T00:00:00Z").unwrap();
+        let success: VerifyResponse =
+            serde_json::from_value(serde_json::json!({ "success": true })).unwrap();
+
+        cache.insert_at("key".into(), &success, inserted_at);
+
+        assert!(cache
+            .get_at("key", inserted_at + Duration::from_millis(1999))
+            .is_some());
+        assert!(cache
+            .get_at("key", inserted_at + Duration::from_secs(2))
+            .is_none());
+    }
+
+    #[test]
+    fn test_expired_entries_pruned_in_batches() {
+        let cache = ResultCache::new(CacheTtl::Fixed(Duration::from_secs(1)));
+        let success = response("2024-01-01T00:00:00Z");
+        let inserted_at = SystemTime::now();
+        for i in 0..1023 {
+            cache.insert_at(i.to_string(), &success, inserted_at);
+        }
+
+        // Expired entries linger until the cache is large enough to prune, but aren't served
+        let later = inserted_at + Duration::from_secs(2);
+        cache.insert_at("late".into(), &success, later);
+        assert_eq!(cache.entries.lock().unwrap().len(), 1024);
+        assert!(cache.get_at("0", later).is_none());
+
+        cache.insert_at("later".into(), &success, later);
+        assert_eq!(cache.entries.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_result_cache_absorbs_double_submit() {
+        let mock = MockSiteverify::success().await;
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&mock.url)
+            .with_result_cache(Duration::from_secs(5));
+        let app = Router::new()
+            .route("/submit", post(|| async { "OK" }))
+            .layer(TurnstileLayer::new(config));
+
+        let submit = || {
+            app.clone().oneshot(
+                Request::post("/submit")
+                    .header("CF-Turnstile-Token", "same-token")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+        };
+        let (first, second) = (submit().await.unwrap(), submit().await.unwrap());
+
+        assert_eq!(first.status(), StatusCode::OK);
+        assert_eq!(second.status(), StatusCode::OK);
+        assert_eq!(mock.requests().len(), 1);
+    }
+
+    /// Upstream calls for two submissions of one token failing with `error_code`
+    async fn failed_upstream_calls(negative: NegativeCaching, error_code: &str) -> usize {
+        let mock = MockSiteverify::start(serde_json::json!({
+            "success": false,
+            "error-codes": [error_code],
+        }))
+        .await;
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&mock.url)
+            .with_cache_ttl_from_challenge(Duration::from_secs(300))
+            .with_negative_caching(negative);
+        let app = Router::new()
+            .route("/submit", post(|| async { "OK" }))
+            .layer(TurnstileLayer::new(config));
+
+        for _ in 0..2 {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::post("/submit")
+                        .header("CF-Turnstile-Token", "same-token")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        }
+
+        mock.requests().len()
+    }
+
+    #[tokio::test]
+    async fn test_negative_caching_modes() {
+        const DUPLICATE: &str = "timeout-or-duplicate";
+        const INVALID: &str = "invalid-input-response";
+
+        for (negative, error_code, expected_calls) in [
+            (NegativeCaching::None, DUPLICATE, 2),
+            (NegativeCaching::DuplicatesOnly, DUPLICATE, 1),
+            (NegativeCaching::DuplicatesOnly, INVALID, 2),
+            (NegativeCaching::All, INVALID, 1),
+        ] {
+            assert_eq!(
+                failed_upstream_calls(negative, error_code).await,
+                expected_calls,
+                "{negative:?} with {error_code}"
+            );
+        }
+    }
+
+    async fn upstream_calls(challenge_ts: &str) -> usize {
+        let mock = MockSiteverify::start(serde_json::json!({
+            "success": true,
+            "challenge_ts": challenge_ts,
+        }))
+        .await;
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&mock.url)
+            .with_cache_ttl_from_challenge(Duration::from_secs(300));
+        let app = Router::new()
+            .route("/submit", post(|| async { "OK" }))
+            .layer(TurnstileLayer::new(config));
+
+        for _ in 0..2 {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::post("/submit")
+                        .header("CF-Turnstile-Token", "same-token")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+
+        mock.requests().len()
+    }
+
+    #[tokio::test]
+    async fn test_fresh_challenge_is_cached() {
+        let challenge_ts = timestamp::format_rfc3339(SystemTime::now());
+
+        assert_eq!(upstream_calls(&challenge_ts).await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_expired_challenge_is_not_cached() {
+        assert_eq!(upstream_calls("2020-01-01T00:00:00Z").await, 2);
+    }
+}
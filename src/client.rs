@@ -0,0 +1,314 @@
+use crate::{middleware::check_token, TurnstileConfig, TurnstileOutcome, VerifyError};
+use futures_util::StreamExt;
+
+/// Verifies tokens outside the middleware, e.g. in queue workers or handlers
+///
+/// Runs the same verification and validators as the middleware, including the result
+/// cache.
+///
+/// For tokens that only a handler can get at, like a field of a `multipart/form-data` body
+/// read with axum's `Multipart`, keep a `Turnstile` in the router state and verify inside
+/// the handler instead of using the layer:
+///
+/// ```rust,ignore
+/// use axum::{extract::{Multipart, State}, http::{HeaderMap, StatusCode}};
+/// use axum_turnstile::Turnstile;
+///
+/// async fn upload(
+///     State(turnstile): State<Turnstile>,
+///     headers: HeaderMap,
+///     mut multipart: Multipart,
+/// ) -> StatusCode {
+///     while let Ok(Some(field)) = multipart.next_field().await {
+///         if field.name() == Some("cf-turnstile-response") {
+///             let token = field.text().await.unwrap_or_default();
+///             let remote_ip = turnstile.config().resolve_client_ip(&headers).map(|ip| ip.0);
+///             return match turnstile.verify(&token, remote_ip).await {
+///                 Ok(outcome) if outcome.decision.is_verified() => StatusCode::OK,
+///                 Ok(_) => StatusCode::FORBIDDEN,
+///                 Err(_) => StatusCode::INTERNAL_SERVER_ERROR,
+///             };
+///         }
+///     }
+///     StatusCode::BAD_REQUEST
+/// }
+/// ```
+#[derive(Clone, Debug)]
+pub struct Turnstile {
+    config: TurnstileConfig,
+}
+
+impl Turnstile {
+    /// Create a verifier with the given config
+    pub fn new(config: TurnstileConfig) -> Self {
+        Self { config }
+    }
+
+    /// The config tokens are verified with
+    pub fn config(&self) -> &TurnstileConfig {
+        &self.config
+    }
+
+    /// Verify a single token
+    ///
+    /// Rejections by Cloudflare or the validators are an `Ok` outcome with a
+    /// [`Decision::Rejected`](crate::Decision::Rejected) decision; `Err` means the token
+    /// couldn't be verified at all.
+    pub async fn verify(
+        &self,
+        token: &str,
+        remote_ip: Option<String>,
+    ) -> Result<TurnstileOutcome, VerifyError> {
+        self.check(token, TurnstileOutcome::new(remote_ip)).await
+    }
+
+    /// Verify a single token with a caller-supplied `idempotency_key`
+    ///
+    /// The key must be the same for every retry of one logical verification, e.g. derived
+    /// from the form submission, and different for every other one: Cloudflare answers a
+    /// repeated key with the result it already has.
+    pub async fn verify_idempotent(
+        &self,
+        token: &str,
+        remote_ip: Option<String>,
+        idempotency_key: impl Into<String>,
+    ) -> Result<TurnstileOutcome, VerifyError> {
+        let mut outcome = TurnstileOutcome::new(remote_ip);
+        outcome.idempotency_key = Some(idempotency_key.into());
+        self.check(token, outcome).await
+    }
+
+    async fn check(
+        &self,
+        token: &str,
+        outcome: TurnstileOutcome,
+    ) -> Result<TurnstileOutcome, VerifyError> {
+        check(token, outcome, &self.config).await
+    }
+
+    /// Verify many tokens with at most `concurrency` calls in flight
+    ///
+    /// Results are in the same order as `tokens`.
+    pub async fn verify_batch(
+        &self,
+        tokens: Vec<String>,
+        concurrency: usize,
+    ) -> Vec<Result<TurnstileOutcome, VerifyError>> {
+        futures_util::stream::iter(tokens)
+            .map(|token| async move { self.verify(&token, None).await })
+            .buffered(concurrency.max(1))
+            .collect()
+            .await
+    }
+}
+
+/// Verify a token with `config`, for handlers that decide themselves when to check it
+///
+/// Runs the same verification and validators as the middleware, and shares its HTTP client
+/// and result cache with every layer and [`Turnstile`] built from a clone of `config`. The
+/// result isn't acted on: a token Cloudflare or the validators rejected is an `Ok` outcome
+/// whose [`decision`](TurnstileOutcome::decision) isn't
+/// [verified](crate::Decision::is_verified), and the caller has to reject the request
+/// itself. `Err` means the token couldn't be verified at all.
+///
+/// No `remoteip` is sent; use [`Turnstile::verify`] to pass one.
+///
+/// ```rust,no_run
+/// use axum::{extract::State, http::StatusCode};
+/// use axum_turnstile::TurnstileConfig;
+///
+/// async fn transfer(State(config): State<TurnstileConfig>, token: String) -> StatusCode {
+///     // ... checks that don't need Turnstile come first
+///     match axum_turnstile::verify(&token, &config).await {
+///         Ok(outcome) if outcome.decision.is_verified() => StatusCode::OK,
+///         Ok(_) => StatusCode::FORBIDDEN,
+///         Err(_) => StatusCode::SERVICE_UNAVAILABLE,
+///     }
+/// }
+/// ```
+pub async fn verify(
+    token: &str,
+    config: &TurnstileConfig,
+) -> Result<TurnstileOutcome, VerifyError> {
+    check(token, TurnstileOutcome::new(None), config).await
+}
+
+async fn check(
+    token: &str,
+    mut outcome: TurnstileOutcome,
+    config: &TurnstileConfig,
+) -> Result<TurnstileOutcome, VerifyError> {
+    outcome.sitekey = config.sitekey.clone();
+    let expected_action = config.expected_action.as_deref();
+    match check_token(token, outcome, expected_action, config).await {
+        (_, Some(error)) => Err(error),
+        (outcome, None) => Ok(outcome),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_support::MockSiteverify, Decision, RejectionReason};
+    use axum::{
+        body::{Body, Bytes},
+        extract::State,
+        http::{HeaderMap, Request, StatusCode},
+        response::IntoResponse,
+        routing::post,
+        Router,
+    };
+    use tower::ServiceExt;
+
+    const BOUNDARY: &str = "turnstile-boundary";
+
+    /// Read a text field from a `multipart/form-data` body
+    fn multipart_field(body: &str, name: &str) -> Option<String> {
+        let disposition = format!("Content-Disposition: form-data; name=\"{name}\"");
+        body.split(&format!("--{BOUNDARY}"))
+            .find(|part| part.contains(&disposition))?
+            .split_once("\r\n\r\n")
+            .map(|(_, value)| value.trim_end_matches("\r\n").to_string())
+    }
+
+    #[tokio::test]
+    async fn test_verify_token_from_multipart_in_handler() {
+        let mock = MockSiteverify::success().await;
+        let turnstile = Turnstile::new(
+            TurnstileConfig::new("secret")
+                .with_verify_url(&mock.url)
+                .with_remote_ip_header("CF-Connecting-IP"),
+        );
+        let app = Router::new()
+            .route(
+                "/upload",
+                post(
+                    |State(turnstile): State<Turnstile>, headers: HeaderMap, body: Bytes| async move {
+                        let body = String::from_utf8_lossy(&body);
+                        let Some(token) = multipart_field(&body, "cf-turnstile-response") else {
+                            return StatusCode::BAD_REQUEST;
+                        };
+                        let remote_ip = turnstile.config().resolve_client_ip(&headers).map(|ip| ip.0);
+                        match turnstile.verify(&token, remote_ip).await {
+                            Ok(outcome) if outcome.decision.is_verified() => StatusCode::OK,
+                            Ok(_) => StatusCode::FORBIDDEN,
+                            Err(_) => StatusCode::INTERNAL_SERVER_ERROR,
+                        }
+                    },
+                ),
+            )
+            .with_state(turnstile);
+
+        let body = format!(
+            "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"a.txt\"\r\n\
+             Content-Type: text/plain\r\n\r\ncontents\r\n\
+             --{BOUNDARY}\r\nContent-Disposition: form-data; name=\"cf-turnstile-response\"\r\n\r\n\
+             multipart-token\r\n--{BOUNDARY}--\r\n"
+        );
+        let response = app
+            .oneshot(
+                Request::post("/upload")
+                    .header(
+                        "Content-Type",
+                        format!("multipart/form-data; boundary={BOUNDARY}"),
+                    )
+                    .header("CF-Connecting-IP", "203.0.113.7")
+                    .body(Body::from(body))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let sent = mock.requests()[0].json();
+        assert_eq!(sent["response"], "multipart-token");
+        assert_eq!(sent["remoteip"], "203.0.113.7");
+    }
+
+    #[tokio::test]
+    async fn test_verify_batch() {
+        let mock = MockSiteverify::respond_raw(|request| {
+            match request.json()["response"].as_str().unwrap() {
+                "broken" => StatusCode::OK.into_response(),
+                token => axum::Json(serde_json::json!({
+                    "success": token.starts_with("good"),
+                    "error-codes": if token.starts_with("good") {
+                        vec![]
+                    } else {
+                        vec!["invalid-input-response"]
+                    },
+                }))
+                .into_response(),
+            }
+        })
+        .await;
+        let turnstile = Turnstile::new(TurnstileConfig::new("secret").with_verify_url(&mock.url));
+
+        let tokens = ["good-1", "bad-1", "broken", "good-2"].map(String::from);
+        let results = turnstile.verify_batch(tokens.to_vec(), 2).await;
+
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[0].as_ref().unwrap().decision, Decision::Verified);
+        assert_eq!(
+            results[1].as_ref().unwrap().decision,
+            Decision::Rejected(RejectionReason::VerificationFailed(vec![
+                "invalid-input-response".to_string()
+            ]))
+        );
+        assert!(matches!(results[2], Err(VerifyError::EmptyResponse)));
+        assert_eq!(results[3].as_ref().unwrap().decision, Decision::Verified);
+        assert_eq!(mock.requests().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_idempotency_key() {
+        let mock = MockSiteverify::success().await;
+        // Without any retries, nothing is resent and no key is needed
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&mock.url)
+            .with_internal_error_retries(0);
+
+        let turnstile = Turnstile::new(config.clone());
+        turnstile.verify("token", None).await.unwrap();
+        let outcome = turnstile
+            .verify_idempotent("token", None, "submission-42")
+            .await
+            .unwrap();
+        assert_eq!(outcome.idempotency_key.as_deref(), Some("submission-42"));
+
+        let turnstile = Turnstile::new(config.with_idempotency(true));
+        let outcome = turnstile.verify("token", None).await.unwrap();
+
+        let sent: Vec<_> = mock.requests().iter().map(|r| r.json()).collect();
+        assert!(sent[0].get("idempotency_key").is_none());
+        assert_eq!(sent[1]["idempotency_key"], "submission-42");
+        assert_eq!(
+            sent[2]["idempotency_key"].as_str(),
+            outcome.idempotency_key.as_deref()
+        );
+        assert_eq!(outcome.idempotency_key.unwrap().len(), 36);
+    }
+
+    #[tokio::test]
+    async fn test_free_verify_leaves_rejection_to_caller() {
+        let mock = MockSiteverify::respond_with(|request| {
+            let success = request.json()["response"] == "good-token";
+            serde_json::json!({ "success": success })
+        })
+        .await;
+        let config = TurnstileConfig::new("secret").with_verify_url(&mock.url);
+
+        let outcome = verify("good-token", &config).await.unwrap();
+        assert_eq!(outcome.decision, Decision::Verified);
+
+        let outcome = verify("bad-token", &config).await.unwrap();
+        assert!(!outcome.decision.is_verified());
+        assert!(matches!(
+            outcome.decision,
+            Decision::Rejected(RejectionReason::VerificationFailed(_))
+        ));
+
+        let unreachable = TurnstileConfig::new("secret").with_verify_url("http://127.0.0.1:9/");
+        assert!(verify("good-token", &unreachable).await.is_err());
+    }
+}
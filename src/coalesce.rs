@@ -0,0 +1,152 @@
+use crate::VerifyResponse;
+use std::{collections::HashMap, future::Future, sync::Mutex, time::Duration};
+use tokio::sync::watch;
+
+/// What followers of an in-flight verification receive; `None` while it's still running
+type Slot = watch::Receiver<Option<Option<VerifyResponse>>>;
+
+/// Single-flight for verifications of the same token
+///
+/// While one request (the leader) verifies a token, identical concurrent requests (the
+/// followers) wait for its result instead of calling Cloudflare themselves.
+#[derive(Debug, Default)]
+pub(crate) struct Coalescer {
+    inflight: Mutex<HashMap<String, Slot>>,
+}
+
+enum Role {
+    Leader(watch::Sender<Option<Option<VerifyResponse>>>),
+    Follower(Slot),
+}
+
+/// Removes the leader's entry even if its request is dropped mid-verification
+struct LeaderGuard<'a> {
+    coalescer: &'a Coalescer,
+    key: &'a str,
+}
+
+impl Drop for LeaderGuard<'_> {
+    fn drop(&mut self) {
+        self.coalescer.inflight.lock().unwrap().remove(self.key);
+    }
+}
+
+impl Coalescer {
+    /// Run `verify` for `key`, or share the result of an identical verification in flight
+    ///
+    /// Followers stop waiting after `timeout` and verify on their own, as they do when the
+    /// leader fails or is dropped.
+    pub async fn run<F, E>(
+        &self,
+        key: &str,
+        timeout: Option<Duration>,
+        verify: impl FnOnce() -> F,
+    ) -> Result<VerifyResponse, E>
+    where
+        F: Future<Output = Result<VerifyResponse, E>>,
+    {
+        let role = {
+            let mut inflight = self.inflight.lock().unwrap();
+            match inflight.get(key) {
+                Some(slot) => Role::Follower(slot.clone()),
+                None => {
+                    let (tx, rx) = watch::channel(None);
+                    inflight.insert(key.to_string(), rx);
+                    Role::Leader(tx)
+                }
+            }
+        };
+
+        match role {
+            Role::Leader(tx) => {
+                let _guard = LeaderGuard {
+                    coalescer: self,
+                    key,
+                };
+                let result = verify().await;
+                tx.send_replace(Some(result.as_ref().ok().cloned()));
+                result
+            }
+            Role::Follower(mut slot) => {
+                let shared = async {
+                    let result = slot.wait_for(Option::is_some).await.ok()?;
+                    result.clone().flatten()
+                };
+                let shared = match timeout {
+                    Some(timeout) => tokio::time::timeout(timeout, shared).await.ok().flatten(),
+                    None => shared.await,
+                };
+                match shared {
+                    Some(response) => Ok(response),
+                    None => verify().await,
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{test_support::MockSiteverify, TurnstileConfig, TurnstileLayer};
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        routing::post,
+        Router,
+    };
+    use std::time::{Duration, Instant};
+    use tower::ServiceExt;
+
+    /// Send two identical requests 50ms apart while the first verification takes 500ms
+    async fn leader_and_follower(config: TurnstileConfig) -> (usize, Duration) {
+        let mock = MockSiteverify::success().await;
+        mock.delay_with(|index| match index {
+            0 => Duration::from_millis(500),
+            _ => Duration::ZERO,
+        });
+        let app = Router::new()
+            .route("/submit", post(|| async { "OK" }))
+            .layer(TurnstileLayer::new(config.with_verify_url(&mock.url)));
+        let send = |app: Router| async move {
+            let response = app
+                .oneshot(
+                    Request::post("/submit")
+                        .header("CF-Turnstile-Token", "same-token")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        };
+
+        let leader = tokio::spawn(send(app.clone()));
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let started = Instant::now();
+        send(app).await;
+        let follower_took = started.elapsed();
+        leader.await.unwrap();
+
+        (mock.requests().len(), follower_took)
+    }
+
+    #[tokio::test]
+    async fn test_follower_shares_leader_result() {
+        let config = TurnstileConfig::new("secret").with_request_coalescing(true);
+
+        let (upstream_calls, follower_took) = leader_and_follower(config).await;
+        assert_eq!(upstream_calls, 1);
+        assert!(follower_took >= Duration::from_millis(300));
+    }
+
+    #[tokio::test]
+    async fn test_follower_gives_up_on_stalled_leader() {
+        let config = TurnstileConfig::new("secret")
+            .with_request_coalescing(true)
+            .with_coalesce_timeout(Duration::from_millis(100));
+
+        let (upstream_calls, follower_took) = leader_and_follower(config).await;
+        assert_eq!(upstream_calls, 2);
+        assert!(follower_took < Duration::from_millis(400));
+    }
+}
@@ -0,0 +1,506 @@
+use crate::{
+    HostnameClass, RiskLevel, TurnstileErrorCode, TurnstileVerification, ValidatorKind,
+    VerifyTimings,
+};
+use axum::{
+    extract::FromRequestParts,
+    http::{request::Parts, StatusCode},
+};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::time::{Duration, SystemTime};
+
+/// What the middleware decided to do with a request
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Decision {
+    /// Cloudflare verified the token and every configured validator passed
+    Verified,
+    /// The body was too large to look for a token and
+    /// [`OversizePolicy::SkipVerification`](crate::OversizePolicy::SkipVerification) let it
+    /// through unverified
+    SkippedOversizeBody,
+    /// The token matched the configured
+    /// [E2E bypass token](crate::TurnstileConfig::with_e2e_bypass_token), so Cloudflare
+    /// wasn't asked
+    BypassedByE2e,
+    /// The connection presented a [`VerifiedClientCert`](crate::VerifiedClientCert) and
+    /// [client-cert skipping](crate::TurnstileConfig::with_skip_on_client_cert) is enabled
+    BypassedByMtls,
+    /// The [alternative guard](crate::TurnstileConfig::with_alternative_guard) accepted the
+    /// request
+    BypassedByGuard,
+    /// The request carried a valid
+    /// [break-glass token](crate::TurnstileConfig::with_break_glass)
+    BypassedByBreakGlass,
+    /// The [global rate limit](crate::TurnstileConfig::with_global_rate_limit) was reached and
+    /// [`RateLimitPolicy::SkipVerification`](crate::RateLimitPolicy::SkipVerification) let the
+    /// request through unverified
+    SkippedRateLimit,
+    /// The request's method or path matched a
+    /// [skip rule](crate::TurnstileConfig::with_skip_paths)
+    SkippedByRule,
+    /// [`HeadPolicy::Skip`](crate::HeadPolicy::Skip) let a `HEAD` request through unverified
+    SkippedHead,
+    /// A CORS preflight was let through unverified, see
+    /// [`with_cors_preflight_passthrough`](crate::TurnstileConfig::with_cors_preflight_passthrough)
+    SkippedPreflight,
+    /// The client IP passed verification within the
+    /// [trust window](crate::TurnstileConfig::with_ip_trust_window), so no token was needed
+    TrustedIp,
+    /// Cloudflare couldn't be asked or gave an unusable answer, and
+    /// [`FailurePolicy::Open`](crate::FailurePolicy::Open) let the request through
+    Degraded,
+    /// [`Mode::Passthrough`](crate::Mode::Passthrough) skipped every check
+    Passthrough,
+    /// [`Mode::Monitor`](crate::Mode::Monitor) let through a request that would have been
+    /// rejected for this reason
+    Monitored(RejectionReason),
+    /// The request was rejected before reaching the inner service
+    Rejected(RejectionReason),
+}
+
+impl Decision {
+    /// Whether the request is allowed through to the inner service
+    pub fn is_allowed(&self) -> bool {
+        !matches!(self, Decision::Rejected(_))
+    }
+
+    /// Whether handlers get a [`VerifiedTurnstile`](crate::VerifiedTurnstile) for the request
+    pub fn is_verified(&self) -> bool {
+        matches!(
+            self,
+            Decision::Verified
+                | Decision::BypassedByE2e
+                | Decision::BypassedByMtls
+                | Decision::BypassedByGuard
+                | Decision::BypassedByBreakGlass
+                | Decision::TrustedIp
+                | Decision::Degraded
+                | Decision::Passthrough
+        )
+    }
+
+    /// Why an allowed request skipped verification, or `None` if it was verified or rejected
+    ///
+    /// Every allowed decision other than [`Decision::Verified`] has one, so audits can
+    /// confirm that nothing got through without a recorded reason.
+    pub fn bypass_reason(&self) -> Option<&'static str> {
+        match self {
+            Decision::Verified | Decision::Rejected(_) => None,
+            Decision::SkippedOversizeBody => Some("oversize_body"),
+            Decision::SkippedRateLimit => Some("global_rate_limit"),
+            Decision::SkippedByRule => Some("skip_rule"),
+            Decision::SkippedHead => Some("head_request"),
+            Decision::SkippedPreflight => Some("cors_preflight"),
+            Decision::BypassedByE2e => Some("e2e_bypass_token"),
+            Decision::BypassedByMtls => Some("client_cert"),
+            Decision::BypassedByGuard => Some("alternative_guard"),
+            Decision::BypassedByBreakGlass => Some("break_glass"),
+            Decision::TrustedIp => Some("trusted_ip"),
+            Decision::Degraded => Some("upstream_failure"),
+            Decision::Passthrough => Some("passthrough_mode"),
+            Decision::Monitored(_) => Some("monitor_mode"),
+        }
+    }
+}
+
+/// Why a request was rejected
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RejectionReason {
+    /// The `Origin`/`Referer` isn't in the configured allowlist
+    OriginNotAllowed,
+    /// No token was found in the configured source
+    MissingToken,
+    /// The token header or trailer was there, but empty or not valid UTF-8
+    MalformedToken,
+    /// The body exceeded the configured cap while looking for the token
+    PayloadTooLarge,
+    /// The [token store](crate::TurnstileConfig::with_token_store) had already seen the
+    /// token
+    Replay,
+    /// Cloudflare accepted the token, but its challenge was solved longer ago than
+    /// [`TurnstileConfig::max_token_age`](crate::TurnstileConfig::max_token_age)
+    Stale,
+    /// Cloudflare rejected the token, with the returned error codes
+    VerificationFailed(Vec<String>),
+    /// Cloudflare rejected the token as `timeout-or-duplicate`: it expired or was already
+    /// redeemed, usually after a double submit or a page left open too long
+    ///
+    /// Benign, so worth a friendlier answer than other failures, such as asking the user to
+    /// refresh and try again.
+    ExpiredOrUsed,
+    /// Cloudflare accepted the token but configured validators failed
+    ValidationFailed(Vec<ValidatorKind>),
+    /// Cloudflare reported that the siteverify request itself was malformed, with the
+    /// returned error codes
+    ///
+    /// This points at a bug in the integration rather than a problem with the user's token.
+    /// Also used for a refused secret with
+    /// [`with_secret_errors_as_server_error`](crate::TurnstileConfig::with_secret_errors_as_server_error).
+    MalformedRequest(Vec<String>),
+    /// Cloudflare's API couldn't be reached or returned an unusable response
+    ApiError,
+    /// Cloudflare's API (or a proxy in front of it) answered with an empty body
+    EmptyResponse,
+    /// Cloudflare's API didn't answer within the
+    /// [timeout](crate::TurnstileConfig::with_timeout)
+    Timeout,
+    /// The [global rate limit](crate::TurnstileConfig::with_global_rate_limit) was reached
+    RateLimited,
+    /// Cloudflare's API answered `429 Too Many Requests`
+    UpstreamRateLimited,
+    /// [`TurnstileConfig::require_https`](crate::TurnstileConfig::require_https) is set and
+    /// the request didn't arrive over HTTPS
+    HttpsRequired,
+    /// The client's `CF-IPCountry` is in
+    /// [`TurnstileConfig::blocked_countries`](crate::TurnstileConfig::blocked_countries)
+    CountryBlocked,
+    /// The route's [verification budget](crate::TurnstileConfig::with_route_verification_budget)
+    /// was used up
+    RouteBudgetExhausted,
+    /// The verified identity exceeded its
+    /// [rate limit](crate::TurnstileConfig::with_identity_rate_limit)
+    IdentityRateLimited,
+    /// A [decision override](crate::TurnstileConfig::with_decision_override) rejected the request
+    Overridden,
+}
+
+impl RejectionReason {
+    /// The error codes Cloudflare returned, for rejections that came from Cloudflare
+    pub fn error_codes(&self) -> Vec<TurnstileErrorCode> {
+        match self {
+            RejectionReason::VerificationFailed(codes)
+            | RejectionReason::MalformedRequest(codes) => TurnstileErrorCode::parse_all(codes),
+            RejectionReason::ExpiredOrUsed => vec![TurnstileErrorCode::TimeoutOrDuplicate],
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Everything the middleware learned while deciding on a request
+///
+/// Every request the middleware lets through carries its outcome in the request extensions,
+/// and handlers can extract it directly, e.g. to act on a [`Decision::Monitored`] outcome
+/// themselves. Extracting it on a route without the middleware fails with
+/// `500 Internal Server Error`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct TurnstileOutcome {
+    /// Random UUID identifying this decision, for correlating it with later events
+    pub verification_id: String,
+    /// What the middleware decided
+    pub decision: Decision,
+    /// [Sitekey](crate::TurnstileConfig::with_sitekey) of the config the token was
+    /// verified with, if it has one
+    pub sitekey: Option<String>,
+    /// Hex-encoded SHA-256 of the token, if one was present
+    pub token_hash: Option<String>,
+    /// Client IP resolved for `remoteip`, if any
+    pub remote_ip: Option<String>,
+    /// Client country from `CF-IPCountry`, if
+    /// [geo capture](crate::TurnstileConfig::with_geo_capture) is enabled
+    pub country: Option<String>,
+    /// Client ASN from the configured [ASN header](crate::TurnstileConfig::with_asn_header)
+    pub asn: Option<String>,
+    /// `challenge_ts` reported by Cloudflare (RFC 3339)
+    pub challenge_ts: Option<String>,
+    /// `hostname` reported by Cloudflare
+    pub hostname: Option<String>,
+    /// Whether `hostname` is first- or third-party, for metric labels
+    pub hostname_class: Option<HostnameClass>,
+    /// `action` reported by Cloudflare
+    pub action: Option<String>,
+    /// `cdata` reported by Cloudflare
+    pub cdata: Option<String>,
+    /// What follows [`TurnstileConfig::cdata_prefix`](crate::TurnstileConfig::cdata_prefix)
+    /// in `cdata`, if a prefix is configured and matched
+    pub cdata_suffix: Option<String>,
+    /// `error-codes` reported by Cloudflare
+    pub error_codes: Vec<String>,
+    /// How much longer the token stays valid, from `challenge_ts` and
+    /// [`TurnstileConfig::token_validity`](crate::TurnstileConfig::token_validity)
+    ///
+    /// `None` if Cloudflare didn't report `challenge_ts` or the token has already expired,
+    /// so a cached result shouldn't be reused.
+    pub expires_in: Option<Duration>,
+    /// Fingerprint of the request from
+    /// [`with_request_fingerprint`](crate::TurnstileConfig::with_request_fingerprint), if
+    /// configured
+    pub request_fingerprint: Option<String>,
+    /// `idempotency_key` sent to Cloudflare, if
+    /// [idempotency](crate::TurnstileConfig::with_idempotency) is enabled, the call may be
+    /// retried or the caller supplied one
+    pub idempotency_key: Option<String>,
+    /// `cf-ray` of Cloudflare's siteverify response, which Cloudflare support asks for
+    pub cf_ray: Option<String>,
+    /// How long the call to Cloudflare took, if one was made
+    pub timings: Option<VerifyTimings>,
+    /// How risky the verification looks, if Cloudflare accepted the token and every
+    /// validator passed
+    pub risk_level: Option<RiskLevel>,
+    /// Cloudflare's answer, if it was asked or a cached answer was used
+    pub verification: Option<TurnstileVerification>,
+}
+
+impl TurnstileOutcome {
+    /// [`error_codes`](Self::error_codes), parsed
+    pub fn typed_error_codes(&self) -> Vec<TurnstileErrorCode> {
+        TurnstileErrorCode::parse_all(&self.error_codes)
+    }
+
+    /// Remaining validity of the token as of `now`
+    pub(crate) fn remaining_validity(
+        &self,
+        validity: Duration,
+        now: SystemTime,
+    ) -> Option<Duration> {
+        let issued = crate::timestamp::parse_rfc3339(self.challenge_ts.as_deref()?)?;
+        issued.checked_add(validity)?.duration_since(now).ok()
+    }
+
+    pub(crate) fn new(remote_ip: Option<String>) -> Self {
+        Self {
+            verification_id: new_verification_id(),
+            decision: Decision::Verified,
+            sitekey: None,
+            token_hash: None,
+            remote_ip,
+            country: None,
+            asn: None,
+            challenge_ts: None,
+            hostname: None,
+            hostname_class: None,
+            action: None,
+            cdata: None,
+            cdata_suffix: None,
+            error_codes: Vec::new(),
+            expires_in: None,
+            request_fingerprint: None,
+            idempotency_key: None,
+            cf_ray: None,
+            timings: None,
+            risk_level: None,
+            verification: None,
+        }
+    }
+
+    pub(crate) fn reject(mut self, reason: RejectionReason) -> Self {
+        self.decision = Decision::Rejected(reason);
+        self
+    }
+}
+
+impl<S> FromRequestParts<S> for TurnstileOutcome
+where
+    S: Send + Sync,
+{
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .extensions
+            .get::<TurnstileOutcome>()
+            .cloned()
+            .ok_or(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+}
+
+/// Random (version 4) UUID
+pub(crate) fn new_verification_id() -> String {
+    let mut bytes = [0u8; 16];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .expect("system random number generator failed");
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+/// Returned by a [decision override](crate::TurnstileConfig::with_decision_override)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecisionOverride {
+    /// Keep the middleware's decision
+    Keep,
+    /// Reject the request with [`RejectionReason::Overridden`], even if it passed
+    Reject,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_support::MockSiteverify, Mode, TurnstileConfig, TurnstileLayer};
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        routing::post,
+        Router,
+    };
+    use std::sync::{Arc, Mutex};
+    use tower::ServiceExt;
+
+    async fn status(config: TurnstileConfig) -> StatusCode {
+        Router::new()
+            .route("/submit", post(|| async { "OK" }))
+            .layer(TurnstileLayer::new(config))
+            .oneshot(
+                Request::post("/submit")
+                    .header("CF-Turnstile-Token", "token")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+            .status()
+    }
+
+    #[tokio::test]
+    async fn test_override_rejects_valid_verification() {
+        let mock = MockSiteverify::start(serde_json::json!({
+            "success": true,
+            "hostname": "myapp.example",
+            "action": "login",
+        }))
+        .await;
+        let seen = Arc::new(Mutex::new(None));
+        let seen_by_override = seen.clone();
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&mock.url)
+            .with_decision_override(move |outcome| {
+                *seen_by_override.lock().unwrap() = Some(outcome.clone());
+                DecisionOverride::Reject
+            });
+
+        assert_eq!(status(config).await, StatusCode::FORBIDDEN);
+
+        let seen = seen.lock().unwrap().clone().unwrap();
+        assert_eq!(seen.decision, Decision::Verified);
+        assert_eq!(seen.action.as_deref(), Some("login"));
+        assert_eq!(seen.hostname.as_deref(), Some("myapp.example"));
+    }
+
+    /// Every request that reaches a handler must carry an outcome that is either verified or
+    /// names the reason it skipped verification
+    #[tokio::test]
+    async fn test_nothing_reaches_handler_without_reason() {
+        let mock = MockSiteverify::respond_with(
+            |request| serde_json::json!({ "success": request.json()["response"] == "good" }),
+        )
+        .await;
+        let base = || {
+            TurnstileConfig::new("secret")
+                .with_verify_url(&mock.url)
+                .with_e2e_bypass_token("e2e-secret")
+        };
+        let scenarios: Vec<(TurnstileConfig, Request<Body>)> = vec![
+            (base(), request(Some("good"))),
+            (base(), request(Some("bad"))),
+            (base(), request(None)),
+            (base(), request(Some("e2e-secret"))),
+            (
+                base().with_skip_on_client_cert(true),
+                request_with(None, |req| {
+                    req.extensions_mut().insert(crate::VerifiedClientCert);
+                }),
+            ),
+            (
+                base().with_alternative_guard(|parts| parts.headers.contains_key("X-CSRF")),
+                request_with(None, |req| {
+                    req.headers_mut().insert("X-CSRF", "1".parse().unwrap());
+                }),
+            ),
+            (base().with_mode(Mode::Passthrough), request(None)),
+            (base().with_mode(Mode::Monitor), request(Some("bad"))),
+            (
+                base()
+                    .with_token_source(crate::TokenSource::JsonPointer("/token".into()))
+                    .with_max_body_bytes(4)
+                    .with_oversize_policy(crate::OversizePolicy::SkipVerification),
+                Request::post("/submit")
+                    .body(Body::from(r#"{"token":"good"}"#))
+                    .unwrap(),
+            ),
+            (
+                base()
+                    .with_global_rate_limit(1, 0)
+                    .with_rate_limit_policy(crate::RateLimitPolicy::SkipVerification),
+                request(Some("good")),
+            ),
+        ];
+
+        let mut reached = Vec::new();
+        for (config, request) in scenarios {
+            let seen = Arc::new(Mutex::new(None));
+            let seen_by_handler = seen.clone();
+            let app = Router::new()
+                .route(
+                    "/submit",
+                    post(move |req: axum::extract::Request| async move {
+                        *seen_by_handler.lock().unwrap() =
+                            Some(req.extensions().get::<TurnstileOutcome>().cloned());
+                        "OK"
+                    }),
+                )
+                .layer(TurnstileLayer::new(config));
+            app.oneshot(request).await.unwrap();
+
+            if let Some(outcome) = seen.lock().unwrap().take() {
+                let outcome = outcome.expect("handler reached without an outcome");
+                assert!(
+                    outcome.decision == Decision::Verified
+                        || outcome.decision.bypass_reason().is_some(),
+                    "unexplained pass: {:?}",
+                    outcome.decision
+                );
+                reached.push(outcome.decision);
+            }
+        }
+
+        assert_eq!(
+            reached,
+            vec![
+                Decision::Verified,
+                Decision::BypassedByE2e,
+                Decision::BypassedByMtls,
+                Decision::BypassedByGuard,
+                Decision::Passthrough,
+                Decision::Monitored(RejectionReason::VerificationFailed(vec![])),
+                Decision::SkippedOversizeBody,
+                Decision::SkippedRateLimit,
+            ]
+        );
+    }
+
+    fn request(token: Option<&str>) -> Request<Body> {
+        request_with(token, |_| {})
+    }
+
+    fn request_with(token: Option<&str>, modify: impl FnOnce(&mut Request<Body>)) -> Request<Body> {
+        let mut builder = Request::post("/submit");
+        if let Some(token) = token {
+            builder = builder.header("CF-Turnstile-Token", token);
+        }
+        let mut request = builder.body(Body::empty()).unwrap();
+        modify(&mut request);
+        request
+    }
+
+    #[tokio::test]
+    async fn test_override_keep() {
+        let mock = MockSiteverify::success().await;
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&mock.url)
+            .with_decision_override(|_| DecisionOverride::Keep);
+
+        assert_eq!(status(config).await, StatusCode::OK);
+    }
+}
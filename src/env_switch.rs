@@ -0,0 +1,101 @@
+use crate::{ip, TurnstileConfig};
+use axum::http::HeaderMap;
+use std::{collections::HashMap, net::IpAddr};
+
+/// Alternate configs selected by a request header, for allowlisted clients only
+#[derive(Debug)]
+pub(crate) struct EnvSwitch {
+    pub header: String,
+    pub configs: HashMap<String, TurnstileConfig>,
+    pub allowed_ips: Vec<IpAddr>,
+}
+
+/// The config to handle a request with: an alternate one if the request asks for it from an
+/// allowlisted IP, otherwise `config` itself
+///
+/// The client IP is resolved like `remoteip`, so switching needs
+/// [`TurnstileConfig::remote_ip_header`].
+pub(crate) fn select<'a>(headers: &HeaderMap, config: &'a TurnstileConfig) -> &'a TurnstileConfig {
+    let Some(switch) = &config.env_switch else {
+        return config;
+    };
+    let Some(alternate) = headers
+        .get(&switch.header)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|env| switch.configs.get(env.trim()))
+    else {
+        return config;
+    };
+
+    let allowed = ip::resolve_remote_ip(headers, config)
+        .and_then(|ip| ip.parse::<IpAddr>().ok())
+        .is_some_and(|ip| switch.allowed_ips.contains(&ip));
+    if allowed {
+        alternate
+    } else {
+        warn!(
+            header = %switch.header,
+            "ignoring config switch from a client that isn't allowlisted"
+        );
+        config
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{test_support::MockSiteverify, TurnstileConfig, TurnstileLayer};
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        routing::post,
+        Router,
+    };
+    use std::collections::HashMap;
+    use tower::ServiceExt;
+
+    async fn send(client_ip: &str) -> (usize, usize) {
+        let production = MockSiteverify::success().await;
+        let staging = MockSiteverify::success().await;
+        let config = TurnstileConfig::new("production-secret")
+            .with_verify_url(&production.url)
+            .with_remote_ip_header("CF-Connecting-IP")
+            .with_env_switch(
+                "X-Turnstile-Env",
+                HashMap::from([(
+                    "staging".to_string(),
+                    TurnstileConfig::new("staging-secret").with_verify_url(&staging.url),
+                )]),
+                vec!["198.51.100.1".parse().unwrap()],
+            );
+
+        let response = Router::new()
+            .route("/submit", post(|| async { "OK" }))
+            .layer(TurnstileLayer::new(config))
+            .oneshot(
+                Request::post("/submit")
+                    .header("CF-Turnstile-Token", "token")
+                    .header("CF-Connecting-IP", client_ip)
+                    .header("X-Turnstile-Env", "staging")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        if let Some(request) = staging.requests().first() {
+            assert_eq!(request.json()["secret"], "staging-secret");
+        }
+        (production.requests().len(), staging.requests().len())
+    }
+
+    #[tokio::test]
+    async fn test_allowlisted_client_switches_env() {
+        assert_eq!(send("198.51.100.1").await, (0, 1));
+    }
+
+    #[tokio::test]
+    async fn test_spoofed_switch_is_ignored() {
+        assert_eq!(send("203.0.113.7").await, (1, 0));
+    }
+}
@@ -0,0 +1,225 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+/// An `error-codes` entry of a siteverify response
+///
+/// Parsed from the strings in [`TurnstileOutcome::error_codes`](crate::TurnstileOutcome::error_codes)
+/// and [`RejectionReason`](crate::RejectionReason) with
+/// [`TurnstileOutcome::typed_error_codes`](crate::TurnstileOutcome::typed_error_codes) and
+/// [`RejectionReason::error_codes`](crate::RejectionReason::error_codes). Codes Cloudflare
+/// adds later are kept as [`Unknown`](Self::Unknown).
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum TurnstileErrorCode {
+    /// `missing-input-secret`: no secret was sent, a configuration bug
+    MissingInputSecret,
+    /// `invalid-input-secret`: the secret is wrong or doesn't exist, a configuration bug
+    InvalidInputSecret,
+    /// `missing-input-response`: no token was sent
+    MissingInputResponse,
+    /// `invalid-input-response`: the token is invalid or has expired
+    InvalidInputResponse,
+    /// `bad-request`: the call to siteverify was malformed
+    BadRequest,
+    /// `timeout-or-duplicate`: the token was already spent or is too old; the client needs
+    /// a new one
+    TimeoutOrDuplicate,
+    /// `internal-error`: Cloudflare failed to check the token and the call can be retried
+    InternalError,
+    /// Any other code, as sent
+    Unknown(String),
+}
+
+impl TurnstileErrorCode {
+    /// The code as Cloudflare sends it
+    pub fn as_str(&self) -> &str {
+        match self {
+            TurnstileErrorCode::MissingInputSecret => "missing-input-secret",
+            TurnstileErrorCode::InvalidInputSecret => "invalid-input-secret",
+            TurnstileErrorCode::MissingInputResponse => "missing-input-response",
+            TurnstileErrorCode::InvalidInputResponse => "invalid-input-response",
+            TurnstileErrorCode::BadRequest => "bad-request",
+            TurnstileErrorCode::TimeoutOrDuplicate => "timeout-or-duplicate",
+            TurnstileErrorCode::InternalError => "internal-error",
+            TurnstileErrorCode::Unknown(code) => code,
+        }
+    }
+
+    /// Whether the code points at the configured secret, which only an operator can fix
+    pub fn is_secret_error(&self) -> bool {
+        matches!(
+            self,
+            TurnstileErrorCode::MissingInputSecret | TurnstileErrorCode::InvalidInputSecret
+        )
+    }
+
+    /// Parse every code in `codes`
+    pub(crate) fn parse_all(codes: &[String]) -> Vec<Self> {
+        codes.iter().map(|code| Self::from(code.as_str())).collect()
+    }
+}
+
+impl From<&str> for TurnstileErrorCode {
+    fn from(code: &str) -> Self {
+        match code {
+            "missing-input-secret" => TurnstileErrorCode::MissingInputSecret,
+            "invalid-input-secret" => TurnstileErrorCode::InvalidInputSecret,
+            "missing-input-response" => TurnstileErrorCode::MissingInputResponse,
+            "invalid-input-response" => TurnstileErrorCode::InvalidInputResponse,
+            "bad-request" => TurnstileErrorCode::BadRequest,
+            "timeout-or-duplicate" => TurnstileErrorCode::TimeoutOrDuplicate,
+            "internal-error" => TurnstileErrorCode::InternalError,
+            code => TurnstileErrorCode::Unknown(code.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for TurnstileErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for TurnstileErrorCode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for TurnstileErrorCode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(|code| Self::from(code.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_support::MockSiteverify, TurnstileConfig, TurnstileLayer};
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        response::IntoResponse,
+        routing::post,
+        Router,
+    };
+    use tower::ServiceExt;
+
+    #[test]
+    fn test_documented_codes() {
+        for (code, expected) in [
+            (
+                "missing-input-secret",
+                TurnstileErrorCode::MissingInputSecret,
+            ),
+            (
+                "invalid-input-secret",
+                TurnstileErrorCode::InvalidInputSecret,
+            ),
+            (
+                "missing-input-response",
+                TurnstileErrorCode::MissingInputResponse,
+            ),
+            (
+                "invalid-input-response",
+                TurnstileErrorCode::InvalidInputResponse,
+            ),
+            ("bad-request", TurnstileErrorCode::BadRequest),
+            (
+                "timeout-or-duplicate",
+                TurnstileErrorCode::TimeoutOrDuplicate,
+            ),
+            ("internal-error", TurnstileErrorCode::InternalError),
+        ] {
+            let parsed = TurnstileErrorCode::from(code);
+            assert_eq!(parsed, expected);
+            assert_eq!(parsed.as_str(), code);
+            let json = serde_json::to_value(&parsed).unwrap();
+            assert_eq!(json, code);
+            assert_eq!(
+                serde_json::from_value::<TurnstileErrorCode>(json).unwrap(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_unknown_code_kept_as_sent() {
+        let parsed: TurnstileErrorCode = serde_json::from_str(r#""new-code""#).unwrap();
+        assert_eq!(parsed, TurnstileErrorCode::Unknown("new-code".to_string()));
+        assert_eq!(parsed.to_string(), "new-code");
+    }
+
+    async fn status(error_code: &str, secret_errors_as_server_error: bool) -> StatusCode {
+        let mock = MockSiteverify::start(serde_json::json!({
+            "success": false,
+            "error-codes": [error_code],
+        }))
+        .await;
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&mock.url)
+            .with_secret_errors_as_server_error(secret_errors_as_server_error);
+        Router::new()
+            .route("/submit", post(|| async { "OK" }))
+            .layer(TurnstileLayer::new(config))
+            .oneshot(
+                Request::post("/submit")
+                    .header("CF-Turnstile-Token", "token")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+            .status()
+    }
+
+    #[tokio::test]
+    async fn test_secret_errors_elevated_to_server_error() {
+        for code in ["invalid-input-secret", "missing-input-secret"] {
+            assert_eq!(status(code, false).await, StatusCode::FORBIDDEN, "{code}");
+            assert_eq!(
+                status(code, true).await,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "{code}"
+            );
+        }
+        // The token's own problems stay the client's
+        assert_eq!(
+            status("invalid-input-response", true).await,
+            StatusCode::FORBIDDEN
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rejection_handler_branches_on_code() {
+        let mock = MockSiteverify::start(serde_json::json!({
+            "success": false,
+            "error-codes": ["timeout-or-duplicate"],
+        }))
+        .await;
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&mock.url)
+            .with_rejection(|reason| {
+                if reason
+                    .error_codes()
+                    .contains(&TurnstileErrorCode::TimeoutOrDuplicate)
+                {
+                    (StatusCode::CONFLICT, "Please solve the challenge again").into_response()
+                } else {
+                    StatusCode::FORBIDDEN.into_response()
+                }
+            });
+        let response = Router::new()
+            .route("/submit", post(|| async { "OK" }))
+            .layer(TurnstileLayer::new(config))
+            .oneshot(
+                Request::post("/submit")
+                    .header("CF-Turnstile-Token", "token")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+    }
+}
@@ -0,0 +1,83 @@
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    ops::{Deref, DerefMut},
+};
+
+/// How many entries an [`ExpiringMap`] holds before it first drops expired ones
+const PRUNE_THRESHOLD: usize = 1024;
+
+/// A map whose expired entries are dropped in batches rather than on every access
+///
+/// Pruning scans the whole map, so it only runs once the map has grown past 1024 entries,
+/// and then waits for the map to double. Expired entries linger until then, so lookups
+/// must still check whether what they find has expired.
+#[derive(Debug)]
+pub(crate) struct ExpiringMap<K, V> {
+    entries: HashMap<K, V>,
+    /// Size at which the next prune scans the map
+    prune_at: usize,
+}
+
+impl<K: Eq + Hash, V> ExpiringMap<K, V> {
+    pub fn new() -> Self {
+        Self {
+            entries: HashMap::new(),
+            prune_at: PRUNE_THRESHOLD,
+        }
+    }
+
+    /// Drop the entries `live` returns false for, once the map has grown large enough
+    pub fn prune(&mut self, live: impl FnMut(&K, &mut V) -> bool) {
+        if self.entries.len() < self.prune_at {
+            return;
+        }
+        self.entries.retain(live);
+        // Live entries are kept, so wait for the map to double before scanning again
+        self.prune_at = PRUNE_THRESHOLD.max(self.entries.len() * 2);
+    }
+}
+
+impl<K, V> Deref for ExpiringMap<K, V> {
+    type Target = HashMap<K, V>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.entries
+    }
+}
+
+impl<K, V> DerefMut for ExpiringMap<K, V> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.entries
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prunes_once_past_threshold_then_waits_to_double() {
+        let mut map = ExpiringMap::new();
+        for i in 0..PRUNE_THRESHOLD - 1 {
+            map.insert(i, i % 2 == 0);
+        }
+        map.prune(|_, live| *live);
+        assert_eq!(map.len(), PRUNE_THRESHOLD - 1);
+
+        map.insert(PRUNE_THRESHOLD, true);
+        map.prune(|_, live| *live);
+        assert_eq!(map.len(), PRUNE_THRESHOLD / 2 + 1);
+
+        // Nothing expired, so the next scan waits for twice the live entries
+        let mut map = ExpiringMap::new();
+        for i in 0..PRUNE_THRESHOLD {
+            map.insert(i, true);
+        }
+        map.prune(|_, live| *live);
+        assert_eq!(map.prune_at, PRUNE_THRESHOLD * 2);
+        map.insert(PRUNE_THRESHOLD, false);
+        map.prune(|_, live| *live);
+        assert_eq!(map.len(), PRUNE_THRESHOLD + 1);
+    }
+}
@@ -0,0 +1,91 @@
+/// What to do when Cloudflare can't be asked or gives an unusable answer
+///
+/// Covers transport errors, timeouts, error statuses, unreadable responses and persistent
+/// `internal-error`s, but never a clean `success: false`, which always rejects.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FailurePolicy {
+    /// Reject the request, with `500` for most errors (default)
+    #[default]
+    Closed,
+    /// Let the request through with [`Decision::Degraded`] and a [`VerifiedTurnstile`] whose
+    /// `degraded` flag is set
+    ///
+    /// [`Decision::Degraded`]: crate::Decision::Degraded
+    /// [`VerifiedTurnstile`]: crate::VerifiedTurnstile
+    Open,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_support::MockSiteverify, TurnstileConfig, TurnstileLayer, VerifiedTurnstile};
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        response::IntoResponse,
+        routing::post,
+        Router,
+    };
+    use tower::ServiceExt;
+
+    async fn send(mock: &MockSiteverify, policy: FailurePolicy) -> (StatusCode, String) {
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&mock.url)
+            .with_failure_policy(policy);
+        let response = Router::new()
+            .route(
+                "/submit",
+                post(|verified: VerifiedTurnstile| async move {
+                    format!("degraded={}", verified.degraded)
+                }),
+            )
+            .layer(TurnstileLayer::new(config))
+            .oneshot(
+                Request::post("/submit")
+                    .header("CF-Turnstile-Token", "token")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_outage_fails_closed_by_default() {
+        let mock =
+            MockSiteverify::respond_raw(|_| StatusCode::SERVICE_UNAVAILABLE.into_response()).await;
+
+        let (status, _) = send(&mock, FailurePolicy::default()).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[tokio::test]
+    async fn test_outage_fails_open_degraded() {
+        let mock =
+            MockSiteverify::respond_raw(|_| StatusCode::SERVICE_UNAVAILABLE.into_response()).await;
+        assert_eq!(
+            send(&mock, FailurePolicy::Open).await,
+            (StatusCode::OK, "degraded=true".to_string())
+        );
+
+        // A clean rejection still rejects
+        let mock = MockSiteverify::start(serde_json::json!({
+            "success": false,
+            "error-codes": ["invalid-input-response"],
+        }))
+        .await;
+        let (status, _) = send(&mock, FailurePolicy::Open).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        let mock = MockSiteverify::success().await;
+        assert_eq!(
+            send(&mock, FailurePolicy::Open).await,
+            (StatusCode::OK, "degraded=false".to_string())
+        );
+    }
+}
@@ -0,0 +1,122 @@
+use crate::{VerifyError, VerifyResponse};
+use std::time::Duration;
+
+/// Latency and failures to inject instead of calling the verification endpoint
+///
+/// Set with [`TurnstileConfig::with_fault_injection`](crate::TurnstileConfig::with_fault_injection),
+/// for testing how your app copes with a slow or failing Cloudflare without running a mock
+/// server.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FaultSpec {
+    /// How long every verification call takes
+    pub delay: Duration,
+    /// How the call fails after `delay`; `None` reports a successful verification
+    pub fail_with: Option<InjectedFault>,
+}
+
+/// A failure injected by a [`FaultSpec`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum InjectedFault {
+    /// The endpoint answers with this status and no body, reported as [`VerifyError::Http`]
+    Http(reqwest::StatusCode),
+    /// The endpoint answers with an empty body, reported as [`VerifyError::EmptyResponse`]
+    EmptyResponse,
+    /// Cloudflare answers `success: false` with these error codes, which are handled like
+    /// real ones, e.g. `internal-error` is retried
+    ErrorCodes(Vec<String>),
+}
+
+impl FaultSpec {
+    /// Wait for `delay`, then answer as the endpoint would have
+    pub(crate) async fn respond(&self) -> Result<VerifyResponse, VerifyError> {
+        tokio::time::sleep(self.delay).await;
+        let error_codes = match &self.fail_with {
+            None => None,
+            Some(InjectedFault::Http(status)) => {
+                return Err(VerifyError::Http(*status, String::new()))
+            }
+            Some(InjectedFault::EmptyResponse) => return Err(VerifyError::EmptyResponse),
+            Some(InjectedFault::ErrorCodes(codes)) => Some(codes.clone()),
+        };
+        Ok(VerifyResponse {
+            success: error_codes.is_none(),
+            error_codes,
+            challenge_ts: None,
+            hostname: None,
+            action: None,
+            cdata: None,
+            metadata: None,
+            cf_ray: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{TurnstileConfig, TurnstileLayer};
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        routing::post,
+        Router,
+    };
+    use tower::ServiceExt;
+
+    fn app(spec: FaultSpec) -> Router {
+        Router::new()
+            .route("/submit", post(|| async { "OK" }))
+            .layer(TurnstileLayer::new(
+                TurnstileConfig::new("secret").with_fault_injection(spec),
+            ))
+    }
+
+    fn request() -> Request<Body> {
+        Request::post("/submit")
+            .header("CF-Turnstile-Token", "token")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_injected_delay_trips_timeout() {
+        let app = app(FaultSpec {
+            delay: Duration::from_secs(5),
+            fail_with: None,
+        });
+
+        let timeout = Duration::from_secs(2);
+        let result = tokio::time::timeout(timeout, app.clone().oneshot(request())).await;
+        assert!(result.is_err());
+
+        let result = tokio::time::timeout(Duration::from_secs(10), app.oneshot(request())).await;
+        assert_eq!(result.unwrap().unwrap().status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_injected_failures() {
+        for (fault, expected) in [
+            (
+                InjectedFault::Http(StatusCode::SERVICE_UNAVAILABLE),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+            (InjectedFault::EmptyResponse, StatusCode::BAD_GATEWAY),
+            (
+                InjectedFault::ErrorCodes(vec!["invalid-input-response".to_string()]),
+                StatusCode::FORBIDDEN,
+            ),
+            (
+                InjectedFault::ErrorCodes(vec!["internal-error".to_string()]),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            ),
+        ] {
+            let spec = FaultSpec {
+                delay: Duration::ZERO,
+                fail_with: Some(fault.clone()),
+            };
+            let response = app(spec).oneshot(request()).await.unwrap();
+            assert_eq!(response.status(), expected, "{fault:?}");
+        }
+    }
+}
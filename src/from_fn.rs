@@ -0,0 +1,101 @@
+use crate::{TurnstileConfig, TurnstileMiddleware};
+use axum::{extract::Request, middleware::Next, response::Response};
+use futures_util::future::BoxFuture;
+use tower_service::Service;
+
+/// Turnstile verification as a function for [`axum::middleware::from_fn`]
+///
+/// Behaves exactly like [`TurnstileLayer`](crate::TurnstileLayer) with the same config: the
+/// same rejections, and the same extensions for handlers. Use it to compose Turnstile with
+/// other `from_fn` middleware.
+///
+/// ```rust
+/// use axum::{middleware, routing::post, Router};
+/// use axum_turnstile::{turnstile_middleware, TurnstileConfig, VerifiedTurnstile};
+///
+/// async fn submit(verified: VerifiedTurnstile) -> String {
+///     format!("Solved for {:?}", verified.action)
+/// }
+///
+/// let config = TurnstileConfig::new("your-secret-key").with_expected_action("submit");
+/// let app: Router = Router::new()
+///     .route("/submit", post(submit))
+///     .layer(middleware::from_fn(turnstile_middleware(config)));
+/// ```
+pub fn turnstile_middleware(
+    config: TurnstileConfig,
+) -> impl Fn(Request, Next) -> BoxFuture<'static, Response> + Clone + Send + Sync + 'static {
+    move |req, next| {
+        let mut middleware = TurnstileMiddleware::new(next, config.clone());
+        Box::pin(async move {
+            // `Next` never fails, so neither does the middleware around it
+            let Ok(()) =
+                std::future::poll_fn(|cx| Service::<Request>::poll_ready(&mut middleware, cx))
+                    .await;
+            let Ok(response) = middleware.call(req).await;
+            response
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_support::MockSiteverify, VerifiedTurnstile};
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        middleware,
+        routing::post,
+        Router,
+    };
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_from_fn_matches_layer() {
+        let mock = MockSiteverify::start(serde_json::json!({
+            "success": true,
+            "action": "submit",
+        }))
+        .await;
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&mock.url)
+            .with_expected_action("submit");
+        let app =
+            Router::new()
+                .route(
+                    "/submit",
+                    post(|verified: VerifiedTurnstile| async move {
+                        verified.action.unwrap_or_default()
+                    }),
+                )
+                .layer(middleware::from_fn(turnstile_middleware(config)));
+        let send = |token: Option<&str>| {
+            let mut request = Request::post("/submit");
+            if let Some(token) = token {
+                request = request.header("CF-Turnstile-Token", token);
+            }
+            let response = app.clone().oneshot(request.body(Body::empty()).unwrap());
+            async move {
+                let response = response.await.unwrap();
+                let status = response.status();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                (status, String::from_utf8(body.to_vec()).unwrap())
+            }
+        };
+
+        assert_eq!(
+            send(Some("token")).await,
+            (StatusCode::OK, "submit".to_string())
+        );
+        assert_eq!(
+            send(None).await,
+            (
+                StatusCode::BAD_REQUEST,
+                "Missing Turnstile token".to_string()
+            )
+        );
+    }
+}
@@ -0,0 +1,106 @@
+use crate::TurnstileConfig;
+use axum::http::HeaderMap;
+
+/// Header Cloudflare sets to the client's two-letter country code
+const COUNTRY_HEADER: &str = "CF-IPCountry";
+
+/// The client's country from `CF-IPCountry`, uppercased
+pub(crate) fn country(headers: &HeaderMap) -> Option<String> {
+    header(headers, COUNTRY_HEADER).map(|country| country.to_ascii_uppercase())
+}
+
+/// The client's ASN from [`TurnstileConfig::asn_header`]
+pub(crate) fn asn(headers: &HeaderMap, config: &TurnstileConfig) -> Option<String> {
+    header(headers, config.asn_header.as_deref()?)
+}
+
+/// Whether the request comes from one of [`TurnstileConfig::blocked_countries`]
+pub(crate) fn country_blocked(headers: &HeaderMap, config: &TurnstileConfig) -> bool {
+    if config.blocked_countries.is_empty() {
+        return false;
+    }
+    country(headers).is_some_and(|country| {
+        config
+            .blocked_countries
+            .iter()
+            .any(|blocked| blocked.eq_ignore_ascii_case(&country))
+    })
+}
+
+fn header(headers: &HeaderMap, name: &str) -> Option<String> {
+    let value = headers.get(name)?.to_str().ok()?.trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{test_support::MockSiteverify, TurnstileConfig, TurnstileLayer, TurnstileOutcome};
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        response::Response,
+        routing::post,
+        Extension, Router,
+    };
+    use tower::ServiceExt;
+
+    async fn send(config: TurnstileConfig, country: &str) -> Response {
+        Router::new()
+            .route(
+                "/submit",
+                post(
+                    |Extension(outcome): Extension<TurnstileOutcome>| async move {
+                        format!(
+                            "{} {}",
+                            outcome.country.unwrap_or_default(),
+                            outcome.asn.unwrap_or_default()
+                        )
+                    },
+                ),
+            )
+            .layer(TurnstileLayer::new(config))
+            .oneshot(
+                Request::post("/submit")
+                    .header("CF-Turnstile-Token", "token")
+                    .header("CF-IPCountry", country)
+                    .header("X-ASN", "AS13335")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_captures_country_and_asn() {
+        let mock = MockSiteverify::success().await;
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&mock.url)
+            .with_geo_capture(true)
+            .with_asn_header("X-ASN");
+
+        let response = send(config, "de").await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, "DE AS13335");
+    }
+
+    #[tokio::test]
+    async fn test_blocked_country_rejected_before_verification() {
+        let mock = MockSiteverify::success().await;
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&mock.url)
+            .with_blocked_countries(vec!["KP".to_string()]);
+
+        assert_eq!(
+            send(config.clone(), "kp").await.status(),
+            StatusCode::FORBIDDEN
+        );
+        assert!(mock.requests().is_empty());
+
+        assert_eq!(send(config, "DE").await.status(), StatusCode::OK);
+    }
+}
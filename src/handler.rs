@@ -0,0 +1,69 @@
+use crate::{
+    env_switch,
+    middleware::{audit, evaluate, rejection, tarpit, with_error_codes, with_verification_id},
+    overrides, sitekey, Decision, RejectionReason, TurnstileConfig,
+};
+use axum::{
+    body::Body,
+    http::{Request, Response},
+    response::IntoResponse,
+    routing::{post, MethodRouter},
+    Json,
+};
+
+/// Route that verifies a token on its own, without protecting another handler
+///
+/// Runs the same checks as [`TurnstileLayer`](crate::TurnstileLayer) and answers with
+/// `{"success": true}`, or the usual rejection response. With
+/// [`with_session_jwt`](TurnstileConfig::with_session_jwt) the answer also carries a signed
+/// `session` token and its `expires_in` seconds, for clients that verify once and then
+/// present the token as a bearer.
+///
+/// ```rust
+/// use axum::Router;
+/// use axum_turnstile::{verify_handler, TurnstileConfig};
+///
+/// let app: Router = Router::new().route(
+///     "/turnstile/verify",
+///     verify_handler(TurnstileConfig::new("your-secret-key")),
+/// );
+/// ```
+pub fn verify_handler<S>(config: TurnstileConfig) -> MethodRouter<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    post(move |req: Request<Body>| {
+        let config = config.clone();
+        async move { respond(req, &config).await }
+    })
+}
+
+async fn respond(req: Request<Body>, config: &TurnstileConfig) -> Response<Body> {
+    let config = env_switch::select(req.headers(), config);
+    let config = sitekey::select(req.headers(), config);
+    let config = &*overrides::apply(req.extensions(), config);
+    let (_, outcome, error) = evaluate(req, config).await;
+    audit(&outcome, config);
+    tarpit(&outcome, config).await;
+
+    let response = match &outcome.decision {
+        decision if decision.is_verified() => match &config.session_jwt {
+            Some(session_jwt) => Json(serde_json::json!({
+                "success": true,
+                "session": session_jwt.mint(&outcome),
+                "expires_in": session_jwt.ttl().as_secs(),
+            }))
+            .into_response(),
+            None => Json(serde_json::json!({ "success": true })).into_response(),
+        },
+        Decision::Rejected(reason) | Decision::Monitored(reason) => with_error_codes(
+            rejection(reason, error.as_ref(), config),
+            reason,
+            &outcome.error_codes,
+            config,
+        ),
+        // There's nothing to let through unverified here
+        _ => rejection(&RejectionReason::PayloadTooLarge, None, config),
+    };
+    with_verification_id(response, &outcome.verification_id, config)
+}
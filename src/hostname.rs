@@ -0,0 +1,120 @@
+use crate::TurnstileConfig;
+
+/// Low-cardinality bucket for the `hostname` Cloudflare reported, for use as a metric label
+///
+/// Labelling by raw hostname explodes cardinality once the widget is embedded on partner
+/// sites; this only tells first-party traffic apart from everything else.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum HostnameClass {
+    /// The hostname is one of your own
+    FirstParty,
+    /// The hostname isn't in your first-party list
+    ThirdParty,
+}
+
+impl HostnameClass {
+    /// Label value: `first_party` or `third_party`
+    pub fn as_label(&self) -> &'static str {
+        match self {
+            HostnameClass::FirstParty => "first_party",
+            HostnameClass::ThirdParty => "third_party",
+        }
+    }
+}
+
+/// Classify a reported hostname against the first-party list
+///
+/// Uses [`TurnstileConfig::first_party_hostnames`], or
+/// [`TurnstileConfig::expected_hostnames`] when that's empty.
+pub(crate) fn classify(hostname: &str, config: &TurnstileConfig) -> HostnameClass {
+    let first_party = if config.first_party_hostnames.is_empty() {
+        &config.expected_hostnames
+    } else {
+        &config.first_party_hostnames
+    };
+
+    if first_party
+        .iter()
+        .any(|candidate| candidate.eq_ignore_ascii_case(hostname))
+    {
+        HostnameClass::FirstParty
+    } else {
+        HostnameClass::ThirdParty
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_support::MockSiteverify, AuditEvent, AuditSink, TurnstileLayer};
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        routing::post,
+        Router,
+    };
+    use std::sync::{Arc, Mutex};
+    use tower::ServiceExt;
+
+    #[derive(Default)]
+    struct Labels(Mutex<Vec<Option<&'static str>>>);
+
+    impl AuditSink for Labels {
+        fn record(&self, event: AuditEvent) {
+            let label = event.hostname_class.as_ref().map(HostnameClass::as_label);
+            self.0.lock().unwrap().push(label);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_hostname_bucket_label() {
+        let mock = MockSiteverify::respond_with(|request| {
+            serde_json::json!({ "success": true, "hostname": request.json()["response"] })
+        })
+        .await;
+        let labels = Arc::new(Labels::default());
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&mock.url)
+            .with_first_party_hostnames(vec!["MyApp.example".to_string()])
+            .with_audit_sink(labels.clone());
+        let app = Router::new()
+            .route("/submit", post(|| async { "OK" }))
+            .layer(TurnstileLayer::new(config));
+
+        // The mock echoes the token back as the hostname
+        for hostname in ["myapp.example", "partner.example"] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::post("/submit")
+                        .header("CF-Turnstile-Token", hostname)
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            // Classification never blocks
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+
+        assert_eq!(
+            *labels.0.lock().unwrap(),
+            [Some("first_party"), Some("third_party")]
+        );
+    }
+
+    #[test]
+    fn test_falls_back_to_expected_hostnames() {
+        let config = TurnstileConfig::new("secret")
+            .with_expected_hostnames(vec!["myapp.example".to_string()]);
+
+        assert_eq!(
+            classify("myapp.example", &config),
+            HostnameClass::FirstParty
+        );
+        assert_eq!(
+            classify("other.example", &config),
+            HostnameClass::ThirdParty
+        );
+    }
+}
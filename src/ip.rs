@@ -0,0 +1,407 @@
+use crate::TurnstileConfig;
+use axum::{
+    extract::ConnectInfo,
+    http::{Extensions, HeaderMap},
+};
+use std::{
+    fmt,
+    net::{IpAddr, SocketAddr},
+};
+
+/// Client IP the middleware resolved from [`TurnstileConfig::remote_ip_header`]
+///
+/// Inserted into the extensions of every request the middleware lets through when an IP was
+/// resolved, so layers inside the Turnstile layer, like a `tower-governor` key extractor,
+/// can key on the same IP that was sent to Cloudflare. Layers outside it can get the same
+/// value from [`TurnstileConfig::resolve_client_ip`].
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ResolvedClientIp(pub String);
+
+/// Which `X-Forwarded-For` entries come from proxies you run, see
+/// [`TurnstileConfig::with_trusted_proxies`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TrustedProxies(Trust);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Trust {
+    Count(usize),
+    Networks(Vec<(IpAddr, u8)>),
+}
+
+impl TrustedProxies {
+    /// Exactly `hops` proxies each append the address they received the request from
+    ///
+    /// The client is the `hops`-th entry from the right; entries left of it were sent by the
+    /// client and are ignored. With fewer entries than `hops`, the request didn't pass
+    /// through all proxies and the header isn't used.
+    pub fn count(hops: usize) -> Self {
+        Self(Trust::Count(hops))
+    }
+
+    /// The proxies have addresses in these networks, e.g. `10.0.0.0/8` or `2001:db8::/32`
+    ///
+    /// The client is the rightmost entry outside the networks. The header is only used if
+    /// the connection's peer is in one of them, when the peer is known. A plain address
+    /// is a single-address network.
+    pub fn networks<S: AsRef<str>>(
+        networks: impl IntoIterator<Item = S>,
+    ) -> Result<Self, InvalidNetwork> {
+        networks
+            .into_iter()
+            .map(|network| parse_network(network.as_ref()))
+            .collect::<Result<_, _>>()
+            .map(|networks| Self(Trust::Networks(networks)))
+    }
+
+    fn contains(networks: &[(IpAddr, u8)], ip: IpAddr) -> bool {
+        networks
+            .iter()
+            .any(|&(network, prefix)| match (network, ip) {
+                (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                    let mask = u32::MAX.checked_shl(32 - u32::from(prefix)).unwrap_or(0);
+                    u32::from(network) & mask == u32::from(ip) & mask
+                }
+                (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                    let mask = u128::MAX.checked_shl(128 - u32::from(prefix)).unwrap_or(0);
+                    u128::from(network) & mask == u128::from(ip) & mask
+                }
+                _ => false,
+            })
+    }
+}
+
+/// Error returned by [`TrustedProxies::networks`] for an entry that isn't an IP network
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidNetwork(pub String);
+
+impl fmt::Display for InvalidNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid IP network {:?}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidNetwork {}
+
+fn parse_network(network: &str) -> Result<(IpAddr, u8), InvalidNetwork> {
+    let invalid = || InvalidNetwork(network.to_string());
+    let (addr, prefix) = network.split_once('/').unwrap_or((network, ""));
+    let addr: IpAddr = addr.trim().parse().map_err(|_| invalid())?;
+    let max = if addr.is_ipv4() { 32 } else { 128 };
+    let prefix = match prefix.trim() {
+        "" => max,
+        prefix => prefix
+            .parse()
+            .ok()
+            .filter(|p| *p <= max)
+            .ok_or_else(invalid)?,
+    };
+    Ok((addr, prefix))
+}
+
+/// Resolve the client IP to send to Cloudflare as `remoteip`
+pub(crate) fn resolve_remote_ip(headers: &HeaderMap, config: &TurnstileConfig) -> Option<String> {
+    let name = config.remote_ip_header.as_ref()?;
+    let value = headers.get(name)?.to_str().ok()?.trim();
+
+    if value.is_empty() {
+        return None;
+    }
+
+    if config.ip_normalization {
+        normalize_ip(value).map(|ip| ip.to_string())
+    } else {
+        Some(value.to_string())
+    }
+}
+
+/// The client IP from `X-Forwarded-For`, if [`TurnstileConfig::trusted_proxies`] is set
+///
+/// Falls back to the connection's peer address when the header doesn't match the
+/// configured proxies.
+pub(crate) fn forwarded_ip(
+    headers: &HeaderMap,
+    extensions: &Extensions,
+    config: &TurnstileConfig,
+) -> Option<String> {
+    let trusted = config.trusted_proxies.as_ref()?;
+    let peer = extensions
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip().to_canonical());
+    let entries: Vec<&str> = headers
+        .get_all("X-Forwarded-For")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .collect();
+
+    let client = match &trusted.0 {
+        Trust::Count(hops) => entries
+            .len()
+            .checked_sub(*hops)
+            .filter(|_| *hops > 0)
+            .and_then(|index| normalize_ip(entries[index])),
+        Trust::Networks(networks) => {
+            if peer.is_some_and(|peer| !TrustedProxies::contains(networks, peer)) {
+                None
+            } else {
+                entries
+                    .iter()
+                    .rev()
+                    .map(|entry| normalize_ip(entry))
+                    .find(|ip| ip.is_none_or(|ip| !TrustedProxies::contains(networks, ip)))
+                    .flatten()
+            }
+        }
+    };
+    client.or(peer).map(|ip| ip.to_string())
+}
+
+/// The connection's peer address, if [`TurnstileConfig::remote_ip_from_peer`] is set and
+/// the app was served with `ConnectInfo<SocketAddr>`
+pub(crate) fn peer_ip(extensions: &Extensions, config: &TurnstileConfig) -> Option<String> {
+    if !config.remote_ip_from_peer {
+        return None;
+    }
+    let ConnectInfo(addr) = extensions.get::<ConnectInfo<SocketAddr>>()?;
+    let ip = addr.ip();
+    if config.ip_normalization {
+        Some(ip.to_canonical().to_string())
+    } else {
+        Some(ip.to_string())
+    }
+}
+
+/// Normalize an IP address for Cloudflare
+///
+/// Strips IPv6 brackets and zone identifiers (`fe80::1%eth0`) and maps IPv4-mapped IPv6
+/// addresses (`::ffff:1.2.3.4`) back to IPv4. Returns `None` if the value isn't an IP.
+fn normalize_ip(value: &str) -> Option<IpAddr> {
+    let value = value
+        .strip_prefix('[')
+        .and_then(|v| v.strip_suffix(']'))
+        .unwrap_or(value);
+    let value = value.split_once('%').map_or(value, |(addr, _zone)| addr);
+
+    match value.parse().ok()? {
+        IpAddr::V6(v6) => Some(v6.to_ipv4_mapped().map_or(IpAddr::V6(v6), IpAddr::V4)),
+        ip => Some(ip),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn resolve(value: &str, config: TurnstileConfig) -> Option<String> {
+        let mut headers = HeaderMap::new();
+        headers.insert("CF-Connecting-IP", HeaderValue::from_str(value).unwrap());
+        resolve_remote_ip(&headers, &config.with_remote_ip_header("CF-Connecting-IP"))
+    }
+
+    #[tokio::test]
+    async fn test_downstream_layer_reads_resolved_ip() {
+        use crate::{test_support::MockSiteverify, TurnstileLayer};
+        use axum::{
+            body::Body, extract::Request, http::StatusCode, middleware::Next, routing::post, Router,
+        };
+        use tower::ServiceExt;
+
+        let mock = MockSiteverify::success().await;
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&mock.url)
+            .with_remote_ip_header("CF-Connecting-IP");
+
+        // Stands in for a rate limiter's key extractor
+        async fn key_by_ip(req: Request, next: Next) -> axum::response::Response {
+            let key = req.extensions().get::<ResolvedClientIp>().cloned();
+            let mut response = next.run(req).await;
+            response.extensions_mut().insert(key);
+            response
+        }
+        let app = Router::new()
+            .route("/submit", post(|| async { "OK" }))
+            .layer(axum::middleware::from_fn(key_by_ip))
+            .layer(TurnstileLayer::new(config.clone()));
+
+        let request = || {
+            Request::post("/submit")
+                .header("CF-Turnstile-Token", "token")
+                .header("CF-Connecting-IP", "::ffff:203.0.113.7")
+        };
+        let response = app
+            .oneshot(request().body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let resolved = ResolvedClientIp("203.0.113.7".to_string());
+        assert_eq!(
+            response.extensions().get::<Option<ResolvedClientIp>>(),
+            Some(&Some(resolved.clone()))
+        );
+        let outer = request().body(()).unwrap();
+        assert_eq!(config.resolve_client_ip(outer.headers()), Some(resolved));
+        assert_eq!(mock.requests()[0].json()["remoteip"], "203.0.113.7");
+    }
+
+    #[tokio::test]
+    async fn test_peer_address_fallback() {
+        use crate::{test_support::MockSiteverify, TurnstileLayer};
+        use axum::{body::Body, extract::Request, routing::post, Router};
+        use tower::ServiceExt;
+
+        let mock = MockSiteverify::success().await;
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&mock.url)
+            .with_remote_ip_header("CF-Connecting-IP")
+            .with_remote_ip_from_peer(true);
+        let app = Router::new()
+            .route("/submit", post(|| async { "OK" }))
+            .layer(TurnstileLayer::new(config));
+
+        let peer: SocketAddr = "198.51.100.4:5123".parse().unwrap();
+        let request = |ip: Option<&str>| {
+            let mut builder = Request::post("/submit").header("CF-Turnstile-Token", "token");
+            if let Some(ip) = ip {
+                builder = builder.header("CF-Connecting-IP", ip);
+            }
+            builder
+                .extension(ConnectInfo(peer))
+                .body(Body::empty())
+                .unwrap()
+        };
+        app.clone().oneshot(request(None)).await.unwrap();
+        app.oneshot(request(Some("203.0.113.7"))).await.unwrap();
+
+        let requests = mock.requests();
+        assert_eq!(requests[0].json()["remoteip"], "198.51.100.4");
+        assert_eq!(requests[1].json()["remoteip"], "203.0.113.7");
+    }
+
+    #[test]
+    fn test_strips_ipv6_zone() {
+        let config = TurnstileConfig::new("secret");
+        assert_eq!(resolve("fe80::1%eth0", config).as_deref(), Some("fe80::1"));
+    }
+
+    #[test]
+    fn test_maps_ipv4_mapped_ipv6() {
+        let config = TurnstileConfig::new("secret");
+        assert_eq!(
+            resolve("::ffff:1.2.3.4", config).as_deref(),
+            Some("1.2.3.4")
+        );
+    }
+
+    #[test]
+    fn test_normalization_disabled() {
+        let config = TurnstileConfig::new("secret").with_ip_normalization(false);
+        assert_eq!(
+            resolve("fe80::1%eth0", config).as_deref(),
+            Some("fe80::1%eth0")
+        );
+    }
+
+    #[test]
+    fn test_invalid_ip_is_dropped() {
+        let config = TurnstileConfig::new("secret");
+        assert_eq!(resolve("not-an-ip", config), None);
+    }
+
+    fn forwarded(xff: &[&str], peer: Option<&str>, trusted: TrustedProxies) -> Option<String> {
+        let mut headers = HeaderMap::new();
+        for value in xff {
+            headers.append("X-Forwarded-For", HeaderValue::from_str(value).unwrap());
+        }
+        let mut extensions = Extensions::new();
+        if let Some(peer) = peer {
+            extensions.insert(ConnectInfo(SocketAddr::new(peer.parse().unwrap(), 443)));
+        }
+        let config = TurnstileConfig::new("secret").with_trusted_proxies(trusted);
+        forwarded_ip(&headers, &extensions, &config)
+    }
+
+    #[test]
+    fn test_single_proxy() {
+        let peer = Some("10.0.0.1");
+        let one = || TrustedProxies::count(1);
+        assert_eq!(
+            forwarded(&["203.0.113.7"], peer, one()).as_deref(),
+            Some("203.0.113.7")
+        );
+        assert_eq!(
+            forwarded(&["2001:db8::7"], peer, one()).as_deref(),
+            Some("2001:db8::7")
+        );
+        // No header: the request didn't come through the proxy
+        assert_eq!(forwarded(&[], peer, one()).as_deref(), Some("10.0.0.1"));
+    }
+
+    #[test]
+    fn test_multiple_proxies() {
+        let peer = Some("10.0.0.2");
+        let xff = ["203.0.113.7, 10.0.0.1"];
+        assert_eq!(
+            forwarded(&xff, peer, TrustedProxies::count(2)).as_deref(),
+            Some("203.0.113.7")
+        );
+        // Proxies may send separate header lines
+        assert_eq!(
+            forwarded(&["203.0.113.7", "10.0.0.1"], peer, TrustedProxies::count(2)).as_deref(),
+            Some("203.0.113.7")
+        );
+        let networks = TrustedProxies::networks(["10.0.0.0/8", "2001:db8:ffff::/48"]).unwrap();
+        assert_eq!(
+            forwarded(
+                &["203.0.113.7, 2001:db8:ffff::1, 10.0.0.1"],
+                peer,
+                networks.clone()
+            )
+            .as_deref(),
+            Some("203.0.113.7")
+        );
+        // Fewer entries than hops
+        assert_eq!(
+            forwarded(&["203.0.113.7"], peer, TrustedProxies::count(2)).as_deref(),
+            Some("10.0.0.2")
+        );
+        // A peer outside the networks could have written anything
+        assert_eq!(
+            forwarded(&xff, Some("198.51.100.4"), networks).as_deref(),
+            Some("198.51.100.4")
+        );
+    }
+
+    #[test]
+    fn test_spoofed_entries_are_ignored() {
+        let peer = Some("10.0.0.1");
+        // The client sent its own X-Forwarded-For, the proxy appended the real address
+        let xff = ["198.51.100.66, 192.0.2.1, 203.0.113.7"];
+        assert_eq!(
+            forwarded(&xff, peer, TrustedProxies::count(1)).as_deref(),
+            Some("203.0.113.7")
+        );
+        let networks = TrustedProxies::networks(["10.0.0.0/8"]).unwrap();
+        assert_eq!(
+            forwarded(&xff, peer, networks.clone()).as_deref(),
+            Some("203.0.113.7")
+        );
+        // A garbage entry where the client should be isn't skipped over
+        assert_eq!(
+            forwarded(&["203.0.113.7, bogus"], peer, networks).as_deref(),
+            Some("10.0.0.1")
+        );
+    }
+
+    #[test]
+    fn test_invalid_networks() {
+        for network in ["10.0.0.0/33", "10.0.0/8", "2001:db8::/129", "10.0.0.0/x"] {
+            assert_eq!(
+                TrustedProxies::networks([network]),
+                Err(InvalidNetwork(network.to_string()))
+            );
+        }
+    }
+}
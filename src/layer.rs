@@ -1,28 +1,300 @@
-use crate::{TurnstileConfig, TurnstileMiddleware};
+use crate::{
+    middleware::Configs, verifier, FailurePolicy, Mode, TurnstileConfig, TurnstileMiddleware,
+    TurnstileRegistry,
+};
+use futures_util::future::join_all;
+use std::{env::VarError, fmt, time::Duration};
 use tower_layer::Layer;
 
 /// Layer that applies Turnstile verification middleware
 #[derive(Clone)]
 pub struct TurnstileLayer {
-    config: TurnstileConfig,
+    configs: Configs,
 }
 
 impl TurnstileLayer {
     /// Create a new Turnstile layer with the given config
     pub fn new(config: TurnstileConfig) -> Self {
-        Self { config }
+        Self {
+            configs: Configs::Single(Box::new(config)),
+        }
+    }
+
+    /// Create a layer verifying each request with the config it selects from `registry`
+    pub fn from_registry(registry: TurnstileRegistry) -> Self {
+        Self {
+            configs: Configs::Registry(registry),
+        }
     }
 
     /// Create a new Turnstile layer with just a secret key
     pub fn from_secret(secret: impl Into<String>) -> Self {
         Self::new(TurnstileConfig::new(secret))
     }
+
+    /// Create a new Turnstile layer configured from the environment, see
+    /// [`TurnstileConfig::from_env`]
+    pub fn from_env() -> Result<Self, VarError> {
+        TurnstileConfig::from_env().map(Self::new)
+    }
+
+    /// Connect to the verification endpoint ahead of the first request, so it doesn't pay
+    /// for the TLS handshake
+    ///
+    /// Verifies a dummy token with Cloudflare's always-failing test secret over the pooled
+    /// client the layer then uses. A failure is logged and never returned, so it can't hold
+    /// up startup. With a registry, every config is warmed up.
+    ///
+    /// ```rust,no_run
+    /// use axum::{routing::post, Router};
+    /// use axum_turnstile::TurnstileLayer;
+    ///
+    /// # async fn run() {
+    /// let layer = TurnstileLayer::from_secret("your-secret-key");
+    /// layer.warm_up().await;
+    /// let app: Router = Router::new()
+    ///     .route("/submit", post(|| async { "OK" }))
+    ///     .layer(layer);
+    /// # }
+    /// ```
+    pub async fn warm_up(&self) {
+        match &self.configs {
+            Configs::Single(config) => verifier::warm_up(config).await,
+            Configs::Registry(registry) => {
+                join_all(registry.configs().map(verifier::warm_up)).await;
+            }
+        }
+    }
+
+    /// Start building a layer for this secret key
+    ///
+    /// ```rust
+    /// use axum_turnstile::{Mode, TurnstileLayer};
+    /// use std::time::Duration;
+    ///
+    /// let layer = TurnstileLayer::builder("your-secret-key")
+    ///     .with_expected_action("login")
+    ///     .with_timeout(Duration::from_secs(3))
+    ///     .with_retries(2)
+    ///     .with_mode(Mode::Enforce)
+    ///     .configure(|config| config.with_remote_ip_header("CF-Connecting-IP"))
+    ///     .build()
+    ///     .expect("valid Turnstile settings");
+    /// ```
+    pub fn builder(secret: impl Into<String>) -> TurnstileLayerBuilder {
+        TurnstileLayerBuilder {
+            config: TurnstileConfig::new(secret),
+        }
+    }
 }
 
 impl<S> Layer<S> for TurnstileLayer {
     type Service = TurnstileMiddleware<S>;
 
     fn layer(&self, inner: S) -> Self::Service {
-        TurnstileMiddleware::new(inner, self.config.clone())
+        TurnstileMiddleware::with_configs(inner, self.configs.clone())
+    }
+}
+
+/// Builder for a [`TurnstileLayer`], from [`TurnstileLayer::builder`]
+///
+/// The most common settings have methods here; every other [`TurnstileConfig`] option is
+/// reachable through [`configure`](Self::configure). [`build`](Self::build) checks the
+/// settings and returns a [`BuildError`] instead of panicking, so they can come from
+/// untrusted configuration files.
+#[derive(Debug)]
+pub struct TurnstileLayerBuilder {
+    config: TurnstileConfig,
+}
+
+impl TurnstileLayerBuilder {
+    /// See [`TurnstileConfig::with_verify_url`]
+    pub fn with_verify_url(mut self, url: impl Into<String>) -> Self {
+        self.config = self.config.with_verify_url(url);
+        self
+    }
+
+    /// See [`TurnstileConfig::with_header_name`]
+    pub fn with_header_name(mut self, name: impl Into<String>) -> Self {
+        self.config = self.config.with_header_name(name);
+        self
+    }
+
+    /// See [`TurnstileConfig::with_expected_action`]
+    pub fn with_expected_action(mut self, action: impl Into<String>) -> Self {
+        self.config = self.config.with_expected_action(action);
+        self
+    }
+
+    /// See [`TurnstileConfig::with_expected_hostnames`]
+    pub fn with_expected_hostnames(mut self, hostnames: Vec<String>) -> Self {
+        self.config = self.config.with_expected_hostnames(hostnames);
+        self
+    }
+
+    /// See [`TurnstileConfig::with_timeout`]
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.config = self.config.with_timeout(timeout);
+        self
+    }
+
+    /// See [`TurnstileConfig::with_retries`]
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.config = self.config.with_retries(retries);
+        self
+    }
+
+    /// See [`TurnstileConfig::with_failure_policy`]
+    pub fn with_failure_policy(mut self, policy: FailurePolicy) -> Self {
+        self.config = self.config.with_failure_policy(policy);
+        self
+    }
+
+    /// See [`TurnstileConfig::with_mode`], which panics on a production preset
+    pub fn with_mode(mut self, mode: Mode) -> Self {
+        self.config = self.config.with_mode(mode);
+        self
+    }
+
+    /// Apply any other [`TurnstileConfig`] option
+    pub fn configure(mut self, f: impl FnOnce(TurnstileConfig) -> TurnstileConfig) -> Self {
+        self.config = f(self.config);
+        self
+    }
+
+    /// Check the settings and build the layer
+    ///
+    /// Fails if the secret is empty and no
+    /// [secret provider](TurnstileConfig::with_secret_provider) is set, or if the verify URL
+    /// isn't an absolute `http` or `https` URL.
+    pub fn build(self) -> Result<TurnstileLayer, BuildError> {
+        if self.config.secret.is_empty() && self.config.secret_provider.is_none() {
+            return Err(BuildError::EmptySecret);
+        }
+        match reqwest::Url::parse(self.config.verify_url()) {
+            Ok(url) if matches!(url.scheme(), "http" | "https") && url.has_host() => {}
+            _ => {
+                let url = self.config.verify_url().to_string();
+                return Err(BuildError::InvalidVerifyUrl(url));
+            }
+        }
+        Ok(TurnstileLayer::new(self.config))
+    }
+}
+
+/// Error returned by [`TurnstileLayerBuilder::build`] for settings that can't work
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BuildError {
+    /// The secret key is empty
+    EmptySecret,
+    /// The verify URL isn't an absolute `http` or `https` URL
+    InvalidVerifyUrl(String),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::EmptySecret => f.write_str("Turnstile secret key is empty"),
+            BuildError::InvalidVerifyUrl(url) => write!(f, "invalid Turnstile verify URL {url:?}"),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::MockSiteverify;
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        routing::post,
+        Router,
+    };
+    use tower::ServiceExt;
+
+    #[test]
+    fn test_builder_rejects_unusable_settings() {
+        assert_eq!(
+            TurnstileLayer::builder("").build().err(),
+            Some(BuildError::EmptySecret)
+        );
+        for url in [
+            "",
+            "challenges.cloudflare.com/turnstile",
+            "ftp://example.com/",
+        ] {
+            assert_eq!(
+                TurnstileLayer::builder("secret")
+                    .with_verify_url(url)
+                    .build()
+                    .err(),
+                Some(BuildError::InvalidVerifyUrl(url.to_string())),
+                "{url}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_built_layer_applies_settings() {
+        let mock = MockSiteverify::start(serde_json::json!({
+            "success": true,
+            "action": "signup",
+        }))
+        .await;
+        let layer = TurnstileLayer::builder("secret")
+            .with_verify_url(&mock.url)
+            .with_header_name("X-Captcha")
+            .with_expected_action("login")
+            .build()
+            .unwrap();
+
+        let response = Router::new()
+            .route("/submit", post(|| async { "OK" }))
+            .layer(layer)
+            .oneshot(
+                Request::post("/submit")
+                    .header("X-Captcha", "token")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(mock.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_warm_up_pools_the_connection() {
+        let mock = MockSiteverify::success().await;
+        let layer = TurnstileLayer::new(TurnstileConfig::new("secret").with_verify_url(&mock.url));
+
+        layer.warm_up().await;
+        let response = Router::new()
+            .route("/submit", post(|| async { "OK" }))
+            .layer(layer)
+            .oneshot(
+                Request::post("/submit")
+                    .header("CF-Turnstile-Token", "token")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].json()["secret"], crate::TEST_SECRET_FAIL);
+        assert_eq!(requests[1].json()["secret"], "secret");
+        // The request reused the connection the warm-up opened
+        assert_eq!(requests[0].peer, requests[1].peer);
+
+        // An unreachable endpoint is logged, not returned
+        TurnstileLayer::new(TurnstileConfig::new("secret").with_verify_url("http://127.0.0.1:9/"))
+            .warm_up()
+            .await;
     }
 }
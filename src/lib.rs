@@ -20,11 +20,28 @@
 //!
 //! ```toml
 //! [dependencies]
-//! axum-turnstile = "0.1"
+//! axum-turnstile = "0.2"
 //! axum = "0.8"
 //! tokio = { version = "1", features = ["full"] }
 //! ```
 //!
+//! Calls to Cloudflare use TLS from one of two features:
+//!
+//! | Feature | Backend | |
+//! |---|---|---|
+//! | `rustls-tls` (default) | [rustls](https://docs.rs/rustls) with bundled webpki roots | No system libraries, builds for musl |
+//! | `native-tls` | The platform's TLS: OpenSSL, Secure Transport or SChannel | Uses the system's root certificates |
+//!
+//! For native-tls, turn off the default features:
+//!
+//! ```toml
+//! [dependencies]
+//! axum-turnstile = { version = "0.2", default-features = false, features = ["tracing", "native-tls"] }
+//! ```
+//!
+//! If both are enabled, native-tls is used. With neither, pass a client of your own to
+//! [`with_client`](TurnstileConfig::with_client).
+//!
 //! ## Basic Usage
 //!
 //! ```rust,no_run
@@ -69,27 +86,182 @@
 //! let layer = TurnstileLayer::new(config);
 //! ```
 //!
+//! [`TurnstileLayer::builder`] does the same and checks the settings when building, which
+//! suits values read from configuration files.
+//!
+//! ## Token Sources
+//!
+//! By default the token is read from the `CF-Turnstile-Token` header. Use
+//! [`TurnstileConfig::with_token_source`] to read it from elsewhere, for example a nested
+//! field of a JSON body:
+//!
+//! ```rust
+//! use axum_turnstile::{TokenSource, TurnstileConfig};
+//!
+//! // Matches `{"meta": {"captcha": {"token": "..."}}}`
+//! let config = TurnstileConfig::new("your-secret-key")
+//!     .with_token_source(TokenSource::JsonPointer("/meta/captcha/token".into()));
+//!
+//! // Matches the hidden field the widget adds to a plain HTML form
+//! let config = TurnstileConfig::new("your-secret-key")
+//!     .with_token_source(TokenSource::FormField("cf-turnstile-response".into()));
+//! ```
+//!
+//! Body sources buffer the request body and hand it to your handler unchanged. With the
+//! default header source the token is verified before any of the body is read, and the body
+//! is then streamed to your handler without buffering, so large uploads behind a failing
+//! token never cost upload bandwidth.
+//!
+//! WebSocket upgrades can't carry custom headers from a browser, so pass the token in the
+//! URL and read it with [`TokenSource::QueryParam`], which leaves the upgrade untouched.
+//!
+//! ## Rolling Out
+//!
+//! In [`Mode::Monitor`] the middleware runs every check but lets all requests through, so
+//! clients that don't send tokens yet keep working. Handlers can extract the
+//! [`TurnstileOutcome`] to see what would have happened:
+//!
+//! ```rust
+//! use axum_turnstile::{Decision, Mode, RejectionReason, TurnstileConfig, TurnstileOutcome};
+//!
+//! let config = TurnstileConfig::new("your-secret-key").with_mode(Mode::Monitor);
+//!
+//! async fn handler(outcome: TurnstileOutcome) -> &'static str {
+//!     match outcome.decision {
+//!         Decision::Verified => "verified",
+//!         Decision::Monitored(RejectionReason::MissingToken) => "no token yet",
+//!         _ => "would have been rejected",
+//!     }
+//! }
+//! ```
+//!
+//! ## Verifying Outside Requests
+//!
+//! [`Turnstile`] verifies tokens without the middleware, e.g. in a queue worker or in a
+//! handler that reads the token from a `Multipart` body itself. For a one-off check deep
+//! in a handler, [`verify`] takes the config directly. Either way the caller rejects
+//! unverified requests:
+//!
+//! ```rust,no_run
+//! use axum_turnstile::{Turnstile, TurnstileConfig};
+//!
+//! # async fn run(tokens: Vec<String>) {
+//! let turnstile = Turnstile::new(TurnstileConfig::new("your-secret-key"));
+//! for result in turnstile.verify_batch(tokens, 8).await {
+//!     match result {
+//!         Ok(outcome) if outcome.decision.is_verified() => { /* accept */ }
+//!         Ok(_) => { /* rejected */ }
+//!         Err(e) => eprintln!("couldn't verify: {e}"),
+//!     }
+//! }
+//! # }
+//! ```
+//!
+//! ## Metrics
+//!
+//! [`TurnstileStats`] counts decisions and can render them for a Prometheus scrape:
+//!
+//! ```rust
+//! use axum::{routing::{get, post}, Router};
+//! use axum_turnstile::{TurnstileConfig, TurnstileLayer, TurnstileStats};
+//!
+//! let stats = TurnstileStats::new();
+//! let config = TurnstileConfig::new("your-secret-key").with_stats(stats.clone());
+//!
+//! let app: Router = Router::new()
+//!     .route("/api/protected", post(|| async { "OK" }))
+//!     .layer(TurnstileLayer::new(config))
+//!     .route("/metrics", get(move || async move { stats.render_prometheus() }));
+//! ```
+//!
+//! ## SPA Sessions
+//!
+//! Single-page apps can verify once with [`verify_handler`] and get back a short-lived
+//! session JWT, which they then present as `Authorization: Bearer <token>` to handlers that
+//! extract [`TurnstileSession`]:
+//!
+//! ```rust
+//! use axum::{routing::get, Router};
+//! use axum_turnstile::{verify_handler, SessionJwt, TurnstileConfig, TurnstileSession};
+//! use std::time::Duration;
+//!
+//! let key = b"session-signing-key";
+//! let ttl = Duration::from_secs(15 * 60);
+//! let config = TurnstileConfig::new("your-secret-key")
+//!     .with_session_jwt(key, ttl, Default::default());
+//!
+//! let app: Router = Router::new()
+//!     .route("/turnstile/verify", verify_handler(config))
+//!     .route("/api/data", get(|_session: TurnstileSession| async { "data" }))
+//!     .with_state(SessionJwt::new(key, ttl, Default::default()));
+//! ```
+//!
 //! ## Testing
 //!
 //! Cloudflare provides test keys that always pass or fail verification:
 //!
 //! - **Always passes**: `1x0000000000000000000000000000000AA`
 //! - **Always fails**: `2x0000000000000000000000000000000AA`
+//! - **Token already spent**: `3x0000000000000000000000000000000AA`
 //!
 //! ```rust,no_run
-//! use axum_turnstile::TurnstileLayer;
+//! use axum_turnstile::{TurnstileConfig, TurnstileLayer};
 //!
 //! // Use the test key that always passes
 //! let layer = TurnstileLayer::from_secret("1x0000000000000000000000000000000AA");
+//!
+//! // Or use the matching constructor
+//! let layer = TurnstileLayer::new(TurnstileConfig::test_pass());
 //! ```
 //!
+//! For browser-driven E2E suites against a real secret, configure a bypass token with
+//! [`TurnstileConfig::with_e2e_bypass_token`] outside production instead.
+//!
+//! To run handlers behind the layer without any network, answer verifications yourself with
+//! a [`Verifier`] set through [`TurnstileConfig::with_verifier`].
+//!
+//! To run the layer against a local mock of Cloudflare's endpoint instead, enable the
+//! `testing` feature and use `testing::MockTurnstile`.
+//!
+//! To test how your app handles a slow or failing Cloudflare, enable the `test-faults`
+//! feature in your dev-dependencies and inject latency or errors with
+//! `TurnstileConfig::with_fault_injection`.
+//!
 //! ## Response Codes
 //!
-//! - `400 Bad Request`: Turnstile token header is missing
-//! - `403 Forbidden`: Token verification failed
-//! - `500 Internal Server Error`: Error communicating with Cloudflare's API
+//! - `400 Bad Request`: Turnstile token header is missing, empty or not valid UTF-8, or the
+//!   request didn't arrive over HTTPS while [`require_https`](TurnstileConfig::require_https)
+//!   is set
+//! - `403 Forbidden`: Token verification failed (with a body asking to refresh when Cloudflare
+//!   reports the token as expired or already used), the
+//!   [token store](TurnstileConfig::with_token_store) had already seen the token, or it was
+//!   older than [`max_token_age`](TurnstileConfig::with_max_token_age)
+//! - `413 Payload Too Large`: a body token source met a body over `max_body_bytes`
+//! - `429 Too Many Requests`: A verified identity exceeded its
+//!   [rate limit](TurnstileConfig::with_identity_rate_limit)
+//! - `500 Internal Server Error`: Error communicating with Cloudflare's API, or Cloudflare
+//!   reported the verification call as malformed (`bad-request`, `missing-input-response`);
+//!   see [`with_failure_policy`](TurnstileConfig::with_failure_policy) to let such requests
+//!   through instead
+//! - `502 Bad Gateway`: Cloudflare's API (or a proxy in front of it) returned an empty body
+//! - `504 Gateway Timeout`: Cloudflare's API didn't answer within the
+//!   [timeout](TurnstileConfig::with_timeout)
+//! - `503 Service Unavailable`: The [global rate limit](TurnstileConfig::with_global_rate_limit)
+//!   for verification calls was reached, or a route's
+//!   [verification budget](TurnstileConfig::with_route_verification_budget), or Cloudflare's
+//!   API answered `429 Too Many Requests`
 //!
-//! ## Extracting the Verified Marker
+//! Except for malformed verification calls, the `5xx` answers carry a `Retry-After` header,
+//! see [`with_retry_after`](TurnstileConfig::with_retry_after).
+//!
+//! The `400`, `403` and `500` codes can be changed with
+//! [`with_missing_status`](TurnstileConfig::with_missing_status),
+//! [`with_failed_status`](TurnstileConfig::with_failed_status) and
+//! [`with_error_status`](TurnstileConfig::with_error_status). Use
+//! [`with_rejection`](TurnstileConfig::with_rejection) to answer rejections with your own
+//! bodies as well.
+//!
+//! ## Extracting the Verification Result
 //!
 //! The [`VerifiedTurnstile`] type implements [`FromRequestParts`],
 //! so you can use it as an extractor in your handlers:
@@ -97,33 +269,453 @@
 //! ```rust
 //! use axum_turnstile::VerifiedTurnstile;
 //!
-//! async fn handler(_verified: VerifiedTurnstile) -> &'static str {
-//!     "Only reached if Turnstile verification succeeded"
+//! async fn handler(verified: VerifiedTurnstile) -> String {
+//!     // Only reached if Turnstile verification succeeded
+//!     format!("Solved on {:?} for {:?}", verified.hostname, verified.action)
 //! }
 //! ```
+//!
+//! The extractor answers `500 Internal Server Error` on a route the layer wasn't added to,
+//! so a forgotten layer shows up as the bug it is. When the layer let a request through
+//! without verifying it, e.g. in [`Mode::Monitor`], it answers `401 Unauthorized`, see
+//! [`with_unverified_status`](TurnstileConfig::with_unverified_status). Extract
+//! `Option<VerifiedTurnstile>` instead to serve such requests and branch on `None`.
+//!
+//! ## Logging
+//!
+//! With the default `tracing` feature, the crate logs through [`tracing`](https://docs.rs/tracing):
+//! missing tokens at `debug`, rejected tokens and slow calls at `warn` with their
+//! `error_codes`, and failed calls to Cloudflare at `error`. Calls to Cloudflare run in a
+//! `turnstile.verify` span. Without the feature nothing is logged, and the crate never writes
+//! to stderr.
+//!
+//! With the `opentelemetry` feature,
+//! [`with_otel_baggage`](TurnstileConfig::with_otel_baggage) puts the outcome into the
+//! OpenTelemetry baggage handlers run with, for propagation to downstream services.
 
+// Declared first so its macros are in scope everywhere
+#[macro_use]
+mod log;
+
+mod audit;
+#[cfg(feature = "opentelemetry")]
+mod baggage;
+mod body;
+mod break_glass;
+mod cache;
+mod client;
+mod coalesce;
+mod decision;
+mod env_switch;
+mod error_code;
+mod expiring;
+mod failure;
+#[cfg(feature = "test-faults")]
+mod fault;
+mod from_fn;
+mod geo;
+mod handler;
+mod hostname;
+mod ip;
 mod layer;
+mod method;
 mod middleware;
+mod mode;
+mod mtls;
+mod origin;
+mod overrides;
+mod policy;
+mod rate_limit;
+mod recent;
+mod registry;
+mod replay;
+mod risk;
+mod scheme;
+mod secret;
+mod session;
+mod sitekey;
+mod skip;
+#[cfg(feature = "tracing")]
+mod span;
+mod stats;
+mod tarpit;
+#[cfg(test)]
+mod test_support;
+#[cfg(feature = "testing")]
+pub mod testing;
+mod timestamp;
+mod token;
+mod trust;
+mod validate;
 mod verifier;
+mod widgets;
 
-pub use layer::TurnstileLayer;
+pub use audit::{AuditEvent, AuditSink, NoopAuditSink};
+pub use body::OversizePolicy;
+pub use break_glass::{mint_break_glass_token, BREAK_GLASS_HEADER};
+pub use cache::NegativeCaching;
+pub use client::{verify, Turnstile};
+pub use decision::{Decision, DecisionOverride, RejectionReason, TurnstileOutcome};
+pub use error_code::TurnstileErrorCode;
+pub use failure::FailurePolicy;
+#[cfg(feature = "test-faults")]
+pub use fault::{FaultSpec, InjectedFault};
+pub use from_fn::turnstile_middleware;
+pub use handler::verify_handler;
+pub use hostname::HostnameClass;
+pub use ip::{InvalidNetwork, ResolvedClientIp, TrustedProxies};
+pub use layer::{BuildError, TurnstileLayer, TurnstileLayerBuilder};
+pub use method::HeadPolicy;
 pub use middleware::TurnstileMiddleware;
+pub use mode::{Environment, Mode};
+pub use mtls::VerifiedClientCert;
+pub use overrides::TurnstileOverride;
+pub use policy::VerificationPolicy;
+pub use rate_limit::RateLimitPolicy;
+pub use registry::TurnstileRegistry;
+#[cfg(feature = "memory-token-store")]
+pub use replay::MemoryTokenStore;
+pub use replay::TokenStore;
+pub use risk::RiskLevel;
+pub use secret::{SecretError, SecretProvider};
+pub use session::{SessionJwt, TurnstileSession};
+pub use sitekey::SITEKEY_HEADER;
+pub use stats::TurnstileStats;
+pub use token::TokenSource;
+pub use validate::{ValidationFailure, ValidatorKind};
+pub use verifier::{
+    Region, RequestEncoding, Verifier, VerifierResponse, VerifyContext, VerifyError, VerifyTimings,
+};
+pub use widgets::WidgetOutcomes;
 
 use axum::{
-    extract::FromRequestParts,
-    http::{request::Parts, StatusCode},
+    body::Body,
+    extract::{FromRequestParts, OptionalFromRequestParts},
+    http::{request::Parts, Extensions, Method, Response, StatusCode},
+    response::IntoResponse,
 };
+use break_glass::BreakGlass;
+use cache::{CacheTtl, ResultCache};
+use coalesce::Coalescer;
+use env_switch::EnvSwitch;
+use rate_limit::{IdentityRateLimit, RouteBudget, TokenBucket};
+use recent::RecentOutcomes;
 use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    env::VarError,
+    fmt,
+    net::IpAddr,
+    sync::{Arc, OnceLock},
+    time::{Duration, SystemTime},
+};
+use tarpit::Tarpit;
+use trust::IpTrust;
+
+/// How long Cloudflare accepts a token after its challenge was solved
+const DEFAULT_TOKEN_VALIDITY: Duration = Duration::from_secs(300);
+
+/// Response header carrying [`TurnstileOutcome::verification_id`] when
+/// [`with_verification_id_header`](TurnstileConfig::with_verification_id_header) is enabled
+pub const VERIFICATION_ID_HEADER: &str = "X-Turnstile-Verification-Id";
+
+/// Response header listing Cloudflare's error codes for a rejected token, comma-separated,
+/// when [`with_expose_error_codes`](TurnstileConfig::with_expose_error_codes) is enabled
+pub const ERROR_CODES_HEADER: &str = "X-Turnstile-Error-Codes";
+
+/// `User-Agent` of verification calls unless one is configured
+const DEFAULT_USER_AGENT: &str = concat!("axum-turnstile/", env!("CARGO_PKG_VERSION"));
+
+/// Default cap for buffered bodies, matching axum's `DefaultBodyLimit`
+const DEFAULT_MAX_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+/// Cloudflare's test secret that always passes verification
+const TEST_SECRET_PASS: &str = "1x0000000000000000000000000000000AA";
+/// Cloudflare's test secret that always fails verification
+const TEST_SECRET_FAIL: &str = "2x0000000000000000000000000000000AA";
+/// Cloudflare's test secret that reports the token as already spent
+const TEST_SECRET_SPENT: &str = "3x0000000000000000000000000000000AA";
+
+type ResultCallbackFn = Arc<dyn Fn(&TurnstileOutcome) + Send + Sync>;
+type DecisionOverrideFn = Arc<dyn Fn(&TurnstileOutcome) -> DecisionOverride + Send + Sync>;
+type RiskClassifierFn = Arc<dyn Fn(&TurnstileOutcome) -> RiskLevel + Send + Sync>;
+type ContextEnricherFn = Arc<dyn Fn(&mut Extensions, &TurnstileOutcome) + Send + Sync>;
+type AlternativeGuardFn = Arc<dyn Fn(&Parts) -> bool + Send + Sync>;
+type RequestFingerprintFn = Arc<dyn Fn(&Parts) -> String + Send + Sync>;
+type IdentityFn = Arc<dyn Fn(&TurnstileOutcome) -> Option<String> + Send + Sync>;
+type RouteBudgetFn = Arc<dyn Fn(&Parts) -> Option<(String, u32)> + Send + Sync>;
+type UpstreamErrorResponseFn = Arc<dyn Fn(&VerifyError) -> Response<Body> + Send + Sync>;
+type RejectionResponseFn = Arc<dyn Fn(&RejectionReason) -> Response<Body> + Send + Sync>;
 
 /// Configuration for Turnstile verification
-#[derive(Clone, Debug)]
+#[derive(Clone)]
 pub struct TurnstileConfig {
     /// Cloudflare Turnstile secret key
-    pub secret: String,
+    secret: String,
+    /// Sitekey of the widget whose tokens this config verifies, recorded in outcomes
+    /// (default: none)
+    pub sitekey: Option<String>,
     /// Custom header name (default: "CF-Turnstile-Token")
-    pub header_name: String,
-    /// Verification endpoint (default: Cloudflare's endpoint)
-    pub verify_url: String,
+    header_name: String,
+    /// Headers tried in order when `header_name` has no usable token (default: none)
+    pub fallback_header_names: Vec<String>,
+    /// Cloudflare endpoint verification calls go to (default: [`Region::Global`])
+    pub region: Region,
+    /// Verification endpoint overriding `region` (default: none)
+    verify_url: Option<String>,
+    /// Endpoint tried once when `verify_url` fails in transit (default: none)
+    pub fallback_verify_url: Option<String>,
+    /// How verification calls encode their body (default: form-encoded)
+    pub request_encoding: RequestEncoding,
+    /// `User-Agent` of verification calls (default: `axum-turnstile/<version>`)
+    pub user_agent: String,
+    /// Where to read the token from (default: [`TokenSource::Header`])
+    pub token_source: TokenSource,
+    /// Whether to trim, unquote and percent-decode tokens before verifying (default: false)
+    pub token_sanitizer: bool,
+    /// Largest body buffered by body token sources (default: 2 MiB)
+    pub max_body_bytes: usize,
+    /// What to do with bodies larger than `max_body_bytes` (default: reject with 413)
+    pub oversize_policy: OversizePolicy,
+    /// How long Cloudflare accepts a token after its challenge was solved, for
+    /// [`TurnstileOutcome::expires_in`] (default: 300 seconds)
+    pub token_validity: Duration,
+    /// Reject tokens whose challenge was solved longer ago than this (default: no limit)
+    pub max_token_age: Option<Duration>,
+    /// Whether every verification call sends an `idempotency_key` (default: false)
+    pub idempotency: bool,
+    /// Whether body token sources drop the body once the token is read instead of handing
+    /// it on (default: false)
+    pub consume_body: bool,
+    /// Largest body handlers may read from verified requests; when set, unverified requests
+    /// that get through are held to `max_body_bytes` (default: no limit applied)
+    pub verified_body_limit: Option<usize>,
+    /// Methods whose requests pass through unverified (default: none)
+    pub skip_methods: Vec<Method>,
+    /// Paths whose requests pass through unverified (default: none)
+    pub skip_paths: Vec<String>,
+    /// What to do with `HEAD` requests (default: check them like any other)
+    pub head_policy: HeadPolicy,
+    /// Whether CORS preflight requests pass through unverified (default: true)
+    pub cors_preflight_passthrough: bool,
+    /// Whether requests must have arrived over HTTPS to be verified (default: false)
+    pub require_https: bool,
+    /// Header a trusted proxy sets to the client's protocol, like `X-Forwarded-Proto`
+    /// (default: none, the request's own scheme is used)
+    pub forwarded_proto_header: Option<String>,
+    /// Header carrying the client IP sent to Cloudflare as `remoteip` (default: none)
+    pub remote_ip_header: Option<String>,
+    /// Proxies whose `X-Forwarded-For` entries are trusted for `remoteip` when
+    /// `remote_ip_header` is unset or absent (default: none, the header is ignored)
+    pub trusted_proxies: Option<TrustedProxies>,
+    /// Whether to fall back to the connection's peer address when `remote_ip_header` is
+    /// unset or absent (default: false)
+    pub remote_ip_from_peer: bool,
+    /// Whether to normalize the client IP before sending it (default: true)
+    pub ip_normalization: bool,
+    /// Whether requests with a [`VerifiedClientCert`] skip verification (default: false)
+    pub skip_on_client_cert: bool,
+    /// Origins allowed to submit tokens (default: empty, meaning any origin)
+    pub allowed_origins: Vec<String>,
+    /// Whether requests without `Origin` or `Referer` pass the origin check (default: true)
+    pub allow_missing_origin: bool,
+    /// Action the widget must have been rendered with (default: not checked)
+    pub expected_action: Option<String>,
+    /// Whether a successful token must report a non-empty `action` (default: false)
+    pub require_action_present: bool,
+    /// Hostnames the token may have been issued for (default: empty, not checked)
+    pub expected_hostnames: Vec<String>,
+    /// Your own hostnames, for [`HostnameClass`] labels (default: `expected_hostnames`)
+    pub first_party_hostnames: Vec<String>,
+    /// Customer data the widget must have been rendered with (default: not checked)
+    pub expected_cdata: Option<String>,
+    /// Prefix the widget's customer data must start with, like `order:` (default: not
+    /// checked)
+    pub cdata_prefix: Option<String>,
+    /// Your own checks, appended to the built-in ones set up above (default: none)
+    pub policy: VerificationPolicy,
+    /// JSON body fields carrying one widget's token each, with that widget's expected action
+    /// (default: empty, a single token from `token_source`)
+    pub widgets: BTreeMap<String, String>,
+    result_cache: Option<Arc<ResultCache>>,
+    cache_max_age: Option<Duration>,
+    /// Which failed verifications the result cache keeps (default:
+    /// [`NegativeCaching::DuplicatesOnly`])
+    pub negative_caching: NegativeCaching,
+    coalescer: Option<Arc<Coalescer>>,
+    global_rate_limit: Option<Arc<TokenBucket>>,
+    identity_rate_limit: Option<Arc<IdentityRateLimit>>,
+    route_budget: Option<Arc<RouteBudget>>,
+    /// Whether to record the client's `CF-IPCountry` in the outcome (default: false)
+    pub geo_capture: bool,
+    /// Header carrying the client's ASN, recorded in the outcome (default: none)
+    pub asn_header: Option<String>,
+    /// Two-letter country codes, going by `CF-IPCountry`, that are rejected before
+    /// verification (default: empty)
+    pub blocked_countries: Vec<String>,
+    /// Verification latency above which a `turnstile.slow_verification` warning is emitted
+    /// (default: none)
+    pub slow_verification_threshold: Option<Duration>,
+    /// Whether to record the outcome as `turnstile.*` fields on the current span (default:
+    /// false)
+    #[cfg(feature = "tracing")]
+    pub current_span_fields: bool,
+    /// Whether the inner service runs with the outcome in its OpenTelemetry baggage
+    /// (default: false)
+    #[cfg(feature = "opentelemetry")]
+    pub otel_baggage: bool,
+    /// Whether responses carry the decision's [`VERIFICATION_ID_HEADER`] (default: false)
+    pub verification_id_header: bool,
+    /// Whether token rejections carry Cloudflare's error codes in [`ERROR_CODES_HEADER`]
+    /// (default: false)
+    pub expose_error_codes: bool,
+    /// How long a single call to the verification endpoint may take, including reading
+    /// the response (default: 10 seconds)
+    pub timeout: Duration,
+    /// `Retry-After` sent with `5xx` answers caused by Cloudflare's API (default: 5 seconds)
+    pub retry_after: Duration,
+    /// Status for a missing or malformed token (default: `400 Bad Request`)
+    pub missing_status: StatusCode,
+    /// Status for a token that failed verification, validation or was already used or stale
+    /// (default: `403 Forbidden`)
+    pub failed_status: StatusCode,
+    /// Status for a verification that errored (default: `500 Internal Server Error`)
+    pub error_status: StatusCode,
+    /// Status the [`VerifiedTurnstile`] extractor answers for a request the layer let through
+    /// without verifying it (default: `401 Unauthorized`)
+    pub unverified_status: StatusCode,
+    /// Whether `GET` and `HEAD` requests run the inner service while they are verified
+    /// (default: false)
+    pub speculative: bool,
+    /// How often a verification call that failed in transit is retried (default: 0)
+    pub retries: u32,
+    /// How often a verification is retried when Cloudflare reports a failure on its side,
+    /// such as `internal-error` (default: 1)
+    pub internal_error_retries: u32,
+    /// Whether `missing-input-secret` and `invalid-input-secret` are answered with `500`
+    /// instead of `403` (default: false)
+    pub secret_errors_as_server_error: bool,
+    /// What to do with verifications over the global rate limit (default: reject with `503`)
+    pub rate_limit_policy: RateLimitPolicy,
+    /// What to do when Cloudflare can't be asked or gives an unusable answer (default:
+    /// reject)
+    pub failure_policy: FailurePolicy,
+    /// How long a coalesced request waits for the in-flight verification it joined before
+    /// verifying on its own (default: as long as it takes)
+    pub coalesce_timeout: Option<Duration>,
+    audit_sink: Arc<dyn AuditSink>,
+    result_callback: Option<ResultCallbackFn>,
+    stats: Option<TurnstileStats>,
+    recent_outcomes: Option<Arc<RecentOutcomes>>,
+    secret_provider: Option<Arc<dyn SecretProvider>>,
+    token_store: Option<Arc<dyn TokenStore>>,
+    verifier: Option<Arc<dyn Verifier>>,
+    /// Minimum TLS version for verification calls (default: whatever the server negotiates)
+    ///
+    /// Read when the first verification call builds the HTTP client.
+    #[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
+    pub min_tls_version: Option<reqwest::tls::Version>,
+    http_client: Arc<OnceLock<reqwest::Client>>,
+    custom_client: bool,
+    decision_override: Option<DecisionOverrideFn>,
+    risk_classifier: Option<RiskClassifierFn>,
+    context_enricher: Option<ContextEnricherFn>,
+    session_jwt: Option<SessionJwt>,
+    e2e_bypass_token: Option<String>,
+    upstream_error_response: Option<UpstreamErrorResponseFn>,
+    rejection_response: Option<RejectionResponseFn>,
+    alternative_guard: Option<AlternativeGuardFn>,
+    request_fingerprint: Option<RequestFingerprintFn>,
+    env_switch: Option<Arc<EnvSwitch>>,
+    sitekey_routes: Option<Arc<HashMap<String, TurnstileConfig>>>,
+    break_glass: Option<Arc<BreakGlass>>,
+    failure_tarpit: Option<Arc<Tarpit>>,
+    ip_trust: Option<Arc<IpTrust>>,
+    #[cfg(feature = "test-faults")]
+    fault_injection: Option<FaultSpec>,
+    mode: Mode,
+    environment: Option<Environment>,
+}
+
+impl fmt::Debug for TurnstileConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("TurnstileConfig");
+        debug
+            .field("secret", &"<redacted>")
+            .field("sitekey", &self.sitekey)
+            .field("header_name", &self.header_name)
+            .field("fallback_header_names", &self.fallback_header_names)
+            .field("region", &self.region)
+            .field("verify_url", &self.verify_url)
+            .field("fallback_verify_url", &self.fallback_verify_url)
+            .field("request_encoding", &self.request_encoding)
+            .field("user_agent", &self.user_agent)
+            .field("token_source", &self.token_source)
+            .field("token_sanitizer", &self.token_sanitizer)
+            .field("max_body_bytes", &self.max_body_bytes)
+            .field("oversize_policy", &self.oversize_policy)
+            .field("verified_body_limit", &self.verified_body_limit)
+            .field("consume_body", &self.consume_body)
+            .field("idempotency", &self.idempotency)
+            .field("token_validity", &self.token_validity)
+            .field("max_token_age", &self.max_token_age)
+            .field("skip_methods", &self.skip_methods)
+            .field("skip_paths", &self.skip_paths)
+            .field("head_policy", &self.head_policy)
+            .field(
+                "cors_preflight_passthrough",
+                &self.cors_preflight_passthrough,
+            )
+            .field("require_https", &self.require_https)
+            .field("forwarded_proto_header", &self.forwarded_proto_header)
+            .field("remote_ip_header", &self.remote_ip_header)
+            .field("trusted_proxies", &self.trusted_proxies)
+            .field("remote_ip_from_peer", &self.remote_ip_from_peer)
+            .field("ip_normalization", &self.ip_normalization)
+            .field("skip_on_client_cert", &self.skip_on_client_cert)
+            .field("allowed_origins", &self.allowed_origins)
+            .field("allow_missing_origin", &self.allow_missing_origin)
+            .field("expected_action", &self.expected_action)
+            .field("require_action_present", &self.require_action_present)
+            .field("expected_hostnames", &self.expected_hostnames)
+            .field("first_party_hostnames", &self.first_party_hostnames)
+            .field("expected_cdata", &self.expected_cdata)
+            .field("cdata_prefix", &self.cdata_prefix)
+            .field("policy", &self.policy)
+            .field("widgets", &self.widgets);
+        #[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
+        debug.field("min_tls_version", &self.min_tls_version);
+        debug
+            .field("negative_caching", &self.negative_caching)
+            .field("coalesce_timeout", &self.coalesce_timeout)
+            .field("rate_limit_policy", &self.rate_limit_policy)
+            .field("failure_policy", &self.failure_policy)
+            .field("timeout", &self.timeout)
+            .field("retry_after", &self.retry_after)
+            .field("missing_status", &self.missing_status)
+            .field("failed_status", &self.failed_status)
+            .field("error_status", &self.error_status)
+            .field("unverified_status", &self.unverified_status)
+            .field("speculative", &self.speculative)
+            .field("retries", &self.retries)
+            .field("internal_error_retries", &self.internal_error_retries)
+            .field(
+                "secret_errors_as_server_error",
+                &self.secret_errors_as_server_error,
+            )
+            .field("verification_id_header", &self.verification_id_header)
+            .field("expose_error_codes", &self.expose_error_codes)
+            .field(
+                "slow_verification_threshold",
+                &self.slow_verification_threshold,
+            )
+            .field("geo_capture", &self.geo_capture)
+            .field("asn_header", &self.asn_header)
+            .field("blocked_countries", &self.blocked_countries)
+            .field("mode", &self.mode)
+            .field("environment", &self.environment)
+            .finish_non_exhaustive()
+    }
 }
 
 impl TurnstileConfig {
@@ -131,53 +723,1660 @@ impl TurnstileConfig {
     pub fn new(secret: impl Into<String>) -> Self {
         Self {
             secret: secret.into(),
+            sitekey: None,
             header_name: "CF-Turnstile-Token".to_string(),
-            verify_url: "https://challenges.cloudflare.com/turnstile/v0/siteverify".to_string(),
+            fallback_header_names: Vec::new(),
+            region: Region::default(),
+            verify_url: None,
+            fallback_verify_url: None,
+            request_encoding: RequestEncoding::default(),
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            token_source: TokenSource::default(),
+            token_sanitizer: false,
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            verified_body_limit: None,
+            consume_body: false,
+            idempotency: false,
+            token_validity: DEFAULT_TOKEN_VALIDITY,
+            max_token_age: None,
+            skip_methods: Vec::new(),
+            skip_paths: Vec::new(),
+            head_policy: HeadPolicy::default(),
+            cors_preflight_passthrough: true,
+            require_https: false,
+            forwarded_proto_header: None,
+            oversize_policy: OversizePolicy::default(),
+            remote_ip_header: None,
+            trusted_proxies: None,
+            remote_ip_from_peer: false,
+            ip_normalization: true,
+            skip_on_client_cert: false,
+            allowed_origins: Vec::new(),
+            allow_missing_origin: true,
+            expected_action: None,
+            require_action_present: false,
+            expected_hostnames: Vec::new(),
+            first_party_hostnames: Vec::new(),
+            expected_cdata: None,
+            cdata_prefix: None,
+            policy: VerificationPolicy::default(),
+            widgets: BTreeMap::new(),
+            result_cache: None,
+            cache_max_age: None,
+            negative_caching: NegativeCaching::default(),
+            coalescer: None,
+            global_rate_limit: None,
+            identity_rate_limit: None,
+            route_budget: None,
+            timeout: Duration::from_secs(10),
+            retry_after: Duration::from_secs(5),
+            missing_status: StatusCode::BAD_REQUEST,
+            failed_status: StatusCode::FORBIDDEN,
+            error_status: StatusCode::INTERNAL_SERVER_ERROR,
+            unverified_status: StatusCode::UNAUTHORIZED,
+            speculative: false,
+            retries: 0,
+            internal_error_retries: 1,
+            secret_errors_as_server_error: false,
+            verification_id_header: false,
+            expose_error_codes: false,
+            #[cfg(feature = "tracing")]
+            current_span_fields: false,
+            #[cfg(feature = "opentelemetry")]
+            otel_baggage: false,
+            slow_verification_threshold: None,
+            geo_capture: false,
+            asn_header: None,
+            blocked_countries: Vec::new(),
+            rate_limit_policy: RateLimitPolicy::default(),
+            failure_policy: FailurePolicy::default(),
+            coalesce_timeout: None,
+            audit_sink: Arc::new(NoopAuditSink),
+            result_callback: None,
+            stats: None,
+            recent_outcomes: None,
+            secret_provider: None,
+            token_store: None,
+            verifier: None,
+            #[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
+            min_tls_version: None,
+            http_client: Arc::default(),
+            custom_client: false,
+            decision_override: None,
+            risk_classifier: None,
+            context_enricher: None,
+            session_jwt: None,
+            e2e_bypass_token: None,
+            upstream_error_response: None,
+            rejection_response: None,
+            alternative_guard: None,
+            request_fingerprint: None,
+            env_switch: None,
+            sitekey_routes: None,
+            break_glass: None,
+            failure_tarpit: None,
+            ip_trust: None,
+            #[cfg(feature = "test-faults")]
+            fault_injection: None,
+            mode: Mode::default(),
+            environment: None,
         }
     }
 
+    /// Config with sensible defaults for a deployment environment
+    ///
+    /// Development skips verification, staging monitors against Cloudflare's always-passing
+    /// test secret, and production enforces with `secret`, which the other environments
+    /// ignore. Every preset can be adjusted with the usual builders, except that a
+    /// production preset refuses [`Mode::Passthrough`] (see [`with_mode`](Self::with_mode)).
+    pub fn preset(environment: Environment, secret: impl Into<String>) -> Self {
+        let config = match environment {
+            Environment::Development => Self::test_pass().with_mode(Mode::Passthrough),
+            Environment::Staging => Self::test_pass().with_mode(Mode::Monitor),
+            Environment::Production => Self::new(secret),
+        };
+        Self {
+            environment: Some(environment),
+            ..config
+        }
+    }
+
+    /// Config from the `TURNSTILE_SECRET` environment variable
+    ///
+    /// `TURNSTILE_VERIFY_URL` and `TURNSTILE_HEADER_NAME` are read too if set. Fails if the
+    /// secret isn't set or any of the variables isn't valid unicode.
+    pub fn from_env() -> Result<Self, VarError> {
+        Self::from_env_with_prefix("TURNSTILE_")
+    }
+
+    /// Like [`from_env`](Self::from_env), with `prefix` instead of `TURNSTILE_`
+    ///
+    /// `from_env_with_prefix("MYAPP_CAPTCHA_")` reads `MYAPP_CAPTCHA_SECRET`,
+    /// `MYAPP_CAPTCHA_VERIFY_URL` and `MYAPP_CAPTCHA_HEADER_NAME`.
+    pub fn from_env_with_prefix(prefix: &str) -> Result<Self, VarError> {
+        let optional = |name: &str| match std::env::var(format!("{prefix}{name}")) {
+            Err(VarError::NotPresent) => Ok(None),
+            value => value.map(Some),
+        };
+        let mut config = Self::new(std::env::var(format!("{prefix}SECRET"))?);
+        if let Some(url) = optional("VERIFY_URL")? {
+            config = config.with_verify_url(url);
+        }
+        if let Some(name) = optional("HEADER_NAME")? {
+            config = config.with_header_name(name);
+        }
+        Ok(config)
+    }
+
+    /// Create a new config, checking that the secret looks like a Cloudflare secret key
+    ///
+    /// A mistyped or truncated secret otherwise only shows up as `invalid-input-secret`
+    /// rejections at runtime, which look like failed user challenges. Use this in startup
+    /// checks to fail fast. The check is a shape check only; Cloudflare's test secrets
+    /// pass it, see [`is_test_key`](Self::is_test_key).
+    ///
+    /// ```rust
+    /// use axum_turnstile::{SecretError, TurnstileConfig};
+    ///
+    /// assert!(TurnstileConfig::try_new("1x0000000000000000000000000000000AA").is_ok());
+    /// assert_eq!(
+    ///     TurnstileConfig::try_new("0x4AAA").unwrap_err(),
+    ///     SecretError::Malformed
+    /// );
+    /// ```
+    pub fn try_new(secret: impl Into<String>) -> Result<Self, SecretError> {
+        let secret = secret.into();
+        secret::check_format(&secret)?;
+        Ok(Self::new(secret))
+    }
+
+    /// The secret key tokens are verified with
+    ///
+    /// Unused while a [secret provider](Self::with_secret_provider) is set.
+    pub fn secret(&self) -> &str {
+        &self.secret
+    }
+
+    /// Whether the secret is one of Cloudflare's test secrets, which must not be used in
+    /// production
+    pub fn is_test_key(&self) -> bool {
+        secret::is_test_key(&self.secret)
+    }
+
+    /// Config using Cloudflare's test secret that always passes verification
+    pub fn test_pass() -> Self {
+        Self::new(TEST_SECRET_PASS)
+    }
+
+    /// Config using Cloudflare's test secret that always fails verification
+    pub fn test_fail() -> Self {
+        Self::new(TEST_SECRET_FAIL)
+    }
+
+    /// Config using Cloudflare's test secret that reports the token as already spent
+    pub fn test_token_spent() -> Self {
+        Self::new(TEST_SECRET_SPENT)
+    }
+
+    /// Accept this exact token without calling Cloudflare, for automated E2E tests
+    ///
+    /// A request presenting the bypass token (compared in constant time) is let through with
+    /// [`Decision::BypassedByE2e`] and a [`VerifiedTurnstile`]; validators don't run. Use a
+    /// long random value, and only configure it outside production, e.g. behind an
+    /// environment variable your production deployment never sets.
+    pub fn with_e2e_bypass_token(mut self, token: impl Into<String>) -> Self {
+        self.e2e_bypass_token = Some(token.into());
+        self
+    }
+
+    /// Answer every verification call as `spec` says instead of calling the endpoint
+    ///
+    /// Only for tests: lets them exercise timeouts and upstream failures deterministically.
+    /// Everything after the call, like retries, validators and the rejection responses,
+    /// runs as usual.
+    #[cfg(feature = "test-faults")]
+    pub fn with_fault_injection(mut self, spec: FaultSpec) -> Self {
+        self.fault_injection = Some(spec);
+        self
+    }
+
+    /// Set how the middleware acts on its decisions (default: [`Mode::Enforce`])
+    ///
+    /// # Panics
+    ///
+    /// When setting [`Mode::Passthrough`] on a [production preset](Self::preset), so a
+    /// development setting can't ship to production by accident.
+    pub fn with_mode(mut self, mode: Mode) -> Self {
+        assert!(
+            !(mode == Mode::Passthrough && self.environment == Some(Environment::Production)),
+            "refusing to enable passthrough mode on a production Turnstile config"
+        );
+        self.mode = mode;
+        self
+    }
+
+    /// How the middleware acts on its decisions
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
     /// Set a custom header name
+    ///
+    /// Header names are matched case-insensitively, so this works unchanged over HTTP/2,
+    /// where all header names are lowercase.
+    ///
+    /// `Sec-`-prefixed names such as `Sec-Turnstile` work too. Browsers don't let page
+    /// JavaScript set those, so they suit flows where a browser extension injects the token
+    /// and page scripts mustn't be able to forge it.
     pub fn with_header_name(mut self, name: impl Into<String>) -> Self {
         self.header_name = name.into();
         self
     }
 
+    /// The header the token is read from (default: `CF-Turnstile-Token`)
+    pub fn header_name(&self) -> &str {
+        &self.header_name
+    }
+
+    /// Accept the token from any of these headers, e.g. a legacy one during a migration
+    ///
+    /// The headers are tried in order and the first with a non-empty, valid UTF-8 value is
+    /// used. The first name becomes `header_name` and the rest `fallback_header_names`; an
+    /// empty list only clears the fallbacks.
+    pub fn with_header_names(mut self, names: Vec<String>) -> Self {
+        let mut names = names.into_iter();
+        if let Some(name) = names.next() {
+            self.header_name = name;
+        }
+        self.fallback_header_names = names.collect();
+        self
+    }
+
     /// Set a custom verification URL (for testing)
+    ///
+    /// It takes precedence over the [region](Self::with_region), whichever is set last.
     pub fn with_verify_url(mut self, url: impl Into<String>) -> Self {
-        self.verify_url = url.into();
+        self.verify_url = Some(url.into());
+        self
+    }
+
+    /// Send verification calls to the Cloudflare endpoint of `region`
+    pub fn with_region(mut self, region: Region) -> Self {
+        self.region = region;
+        self
+    }
+
+    /// The verification endpoint: the custom URL if set, otherwise the region's
+    pub fn verify_url(&self) -> &str {
+        self.verify_url
+            .as_deref()
+            .unwrap_or_else(|| self.region.verify_url())
+    }
+
+    /// Fail over to a secondary verification endpoint, such as a mirror, when the primary
+    /// one can't be reached
+    ///
+    /// Once the [retries](Self::with_retries) against `verify_url` are used up on connection
+    /// failures or timeouts, the call is made once more against `url`. A clean answer from
+    /// the primary, including `success: false`, never triggers the fallback. The primary may
+    /// have spent the token before failing, so verifications with a fallback always send an
+    /// `idempotency_key`, as with [`with_idempotency`](Self::with_idempotency).
+    pub fn with_fallback_verify_url(mut self, url: impl Into<String>) -> Self {
+        self.fallback_verify_url = Some(url.into());
+        self
+    }
+
+    /// Set how verification calls encode their body
+    ///
+    /// Cloudflare accepts both; [`RequestEncoding::Json`] is for compatible endpoints or
+    /// proxies that only handle JSON.
+    pub fn with_request_encoding(mut self, encoding: RequestEncoding) -> Self {
+        self.request_encoding = encoding;
+        self
+    }
+
+    /// Set the `User-Agent` verification calls send, e.g. `myapp/1.2`, so Cloudflare's logs
+    /// and WAF rules can tell them apart
+    ///
+    /// Also applies to a [custom client](Self::with_client).
+    pub fn with_user_agent(mut self, user_agent: impl Into<String>) -> Self {
+        self.user_agent = user_agent.into();
+        self
+    }
+
+    /// Set where the token is read from
+    pub fn with_token_source(mut self, source: TokenSource) -> Self {
+        self.token_source = source;
+        self
+    }
+
+    /// Clean up tokens mangled by client templating before verifying them
+    ///
+    /// Trims surrounding whitespace, strips one pair of surrounding quotes and decodes
+    /// `%XX` escapes. Off by default so client bugs aren't masked.
+    pub fn with_token_sanitizer(mut self, enabled: bool) -> Self {
+        self.token_sanitizer = enabled;
+        self
+    }
+
+    /// Set the largest body that body token sources will buffer
+    pub fn with_max_body_bytes(mut self, limit: usize) -> Self {
+        self.max_body_bytes = limit;
+        self
+    }
+
+    /// Set how long tokens stay valid after their challenge was solved
+    ///
+    /// Only feeds [`TurnstileOutcome::expires_in`]; Cloudflare enforces its own limit.
+    pub fn with_token_validity(mut self, validity: Duration) -> Self {
+        self.token_validity = validity;
+        self
+    }
+
+    /// Reject tokens Cloudflare accepted if their challenge was solved longer ago than
+    /// `max_age`, with `403 Forbidden`
+    ///
+    /// The age is taken from the returned `challenge_ts`; a token without a readable one is
+    /// rejected. A `challenge_ts` up to 30 seconds in the future is allowed for clock skew.
+    pub fn with_max_token_age(mut self, max_age: Duration) -> Self {
+        self.max_token_age = Some(max_age);
+        self
+    }
+
+    /// Send an `idempotency_key` with every verification call
+    ///
+    /// Cloudflare answers a repeated key with the result it already has, so a call that
+    /// failed in transit after the token was spent can be retried without the token coming
+    /// back as `timeout-or-duplicate`. The key is generated per request, is reused for the
+    /// retries of [`with_retries`](Self::with_retries) and
+    /// [`with_internal_error_retries`](Self::with_internal_error_retries) and is recorded in
+    /// [`TurnstileOutcome::idempotency_key`]. To retry across requests, supply your own with
+    /// [`Turnstile::verify_idempotent`].
+    pub fn with_idempotency(mut self, enabled: bool) -> Self {
+        self.idempotency = enabled;
+        self
+    }
+
+    /// Don't hand a body that was buffered for its token on to the inner service
+    ///
+    /// Saves rebuilding the body for routes whose handler only cares that the token was
+    /// valid. The inner service gets an empty body with `Content-Length: 0` instead, so any
+    /// extractor reading the body, like `Json` or `Form`, sees nothing and fails: only enable
+    /// this for handlers that never read it. Header tokens never buffer the body and aren't
+    /// affected.
+    pub fn with_consume_body(mut self, consume: bool) -> Self {
+        self.consume_body = consume;
+        self
+    }
+
+    /// Hold unverified traffic to a small body and let verified requests send a larger one
+    ///
+    /// `unverified` becomes [`max_body_bytes`](Self::max_body_bytes), so body token sources
+    /// never buffer more than that before the token is verified. The bodies handed to
+    /// handlers are limited to `verified` for verified requests and to `unverified` for any
+    /// others that get through, e.g. in [`Mode::Monitor`]; reading past the limit fails, and
+    /// axum's body extractors answer `413 Payload Too Large`.
+    ///
+    /// axum's extractors also apply their own 2 MiB default, so a `verified` limit above it
+    /// needs [`DefaultBodyLimit::disable`](axum::extract::DefaultBodyLimit::disable) on the
+    /// route.
+    pub fn with_body_limits(mut self, unverified: usize, verified: usize) -> Self {
+        self.max_body_bytes = unverified;
+        self.verified_body_limit = Some(verified);
+        self
+    }
+
+    /// Set what happens when a body token source meets a body over `max_body_bytes`
+    ///
+    /// [`OversizePolicy::SkipVerification`] suits routes that accept large uploads which
+    /// don't need a token: the request reaches the handler with its body intact but
+    /// without a [`VerifiedTurnstile`], so handlers that extract it still reject.
+    pub fn with_oversize_policy(mut self, policy: OversizePolicy) -> Self {
+        self.oversize_policy = policy;
+        self
+    }
+
+    /// Let allowlisted clients pick an alternate config with a request header
+    ///
+    /// A request whose `header` names one of `configs`, e.g. `X-Turnstile-Env: staging`, is
+    /// handled entirely by that config, secret and endpoint included, but only if its client
+    /// IP is in `allowed_ips`. Everyone else gets this config, whatever they send. The IP is
+    /// resolved like `remoteip`, so this needs
+    /// [`with_remote_ip_header`](Self::with_remote_ip_header).
+    pub fn with_env_switch(
+        mut self,
+        header: impl Into<String>,
+        configs: HashMap<String, TurnstileConfig>,
+        allowed_ips: Vec<IpAddr>,
+    ) -> Self {
+        self.env_switch = Some(Arc::new(EnvSwitch {
+            header: header.into(),
+            configs,
+            allowed_ips,
+        }));
+        self
+    }
+
+    /// Set the sitekey of the widget whose tokens this config verifies
+    ///
+    /// Cloudflare doesn't report which sitekey a token was solved on, so this is only
+    /// recorded in [`TurnstileOutcome::sitekey`], e.g. to tell widgets apart in logs.
+    pub fn with_sitekey(mut self, sitekey: impl Into<String>) -> Self {
+        self.sitekey = Some(sitekey.into());
+        self
+    }
+
+    /// Verify tokens with the config for the sitekey the client names in
+    /// [`SITEKEY_HEADER`]
+    ///
+    /// For pages with several widgets, each with its own secret: the frontend sends the
+    /// sitekey of the widget it took the token from, and the request is handled entirely by
+    /// the config `routes` has for it, which records that sitekey in the outcome. Requests
+    /// without the header or with an unknown sitekey use this config. Clients can name any
+    /// of the sitekeys, so give every routed config validators as strict as the route needs.
+    pub fn with_sitekey_routing(mut self, routes: HashMap<String, TurnstileConfig>) -> Self {
+        let routes = routes
+            .into_iter()
+            .map(|(sitekey, config)| {
+                let config = TurnstileConfig {
+                    sitekey: Some(sitekey.clone()),
+                    ..config
+                };
+                (sitekey, config)
+            })
+            .collect();
+        self.sitekey_routes = Some(Arc::new(routes));
+        self
+    }
+
+    /// Hold back the responses to repeated failed verifications from the same client IP
+    ///
+    /// Slows down brute-force token guessing: the first `403` to an IP is delayed by `base`,
+    /// and each further failure within 15 minutes of the previous one doubles the delay, up
+    /// to `max`. A successful verification resets the IP. Client IPs are resolved like
+    /// `remoteip`, so this needs [`with_remote_ip_header`](Self::with_remote_ip_header) or
+    /// [`with_remote_ip_from_peer`](Self::with_remote_ip_from_peer).
+    pub fn with_failure_tarpit(mut self, base: Duration, max: Duration) -> Self {
+        self.failure_tarpit = Some(Arc::new(Tarpit::new(base, max)));
+        self
+    }
+
+    /// Let requests from a client IP that passed verification in the last `window` through
+    /// without a token
+    ///
+    /// Spares repeat visitors another challenge; such requests pass with
+    /// [`Decision::TrustedIp`]. This trusts the IP, not the visitor: everyone behind the same
+    /// NAT, corporate proxy or mobile carrier gateway rides on one person's verification,
+    /// and an attacker can solve one challenge by hand and script the rest of the window.
+    /// Keep the window short and leave it off for endpoints worth abusing. Client IPs are
+    /// resolved like `remoteip`, so this needs
+    /// [`with_remote_ip_header`](Self::with_remote_ip_header) or
+    /// [`with_remote_ip_from_peer`](Self::with_remote_ip_from_peer).
+    pub fn with_ip_trust_window(mut self, window: Duration) -> Self {
+        self.ip_trust = Some(Arc::new(IpTrust::new(window)));
+        self
+    }
+
+    /// Let requests carrying a valid [`BREAK_GLASS_HEADER`] token through unverified
+    ///
+    /// For incidents where Turnstile itself blocks ops traffic. Tokens are minted with
+    /// [`mint_break_glass_token`] from the same `ops_key` and are accepted for `ttl` after
+    /// they were minted; forged, tampered or expired ones are ignored and the request is
+    /// checked as usual. Every accepted token is logged loudly and reaches the audit sink as
+    /// [`Decision::BypassedByBreakGlass`]. Anyone holding `ops_key` can skip Turnstile, so
+    /// keep it with your other incident credentials.
+    pub fn with_break_glass(mut self, ops_key: impl AsRef<[u8]>, ttl: Duration) -> Self {
+        self.break_glass = Some(Arc::new(BreakGlass::new(ops_key.as_ref(), ttl)));
+        self
+    }
+
+    /// Let requests with these methods through without looking at them
+    ///
+    /// Skipped requests reach the inner service with [`Decision::SkippedByRule`] and without
+    /// a [`VerifiedTurnstile`]; no header is read and Cloudflare isn't called. A request is
+    /// skipped if its method or its path matches, see
+    /// [`with_skip_paths`](Self::with_skip_paths). Skip rules are checked first, before
+    /// the [`HeadPolicy`], CORS preflights and every bypass; only
+    /// [`Mode::Passthrough`] comes earlier.
+    pub fn with_skip_methods(mut self, methods: Vec<Method>) -> Self {
+        self.skip_methods = methods;
+        self
+    }
+
+    /// Let requests for these paths through without looking at them, e.g. `/healthz`
+    ///
+    /// Paths are compared exactly with the request path, without the query string.
+    /// Otherwise these work like [`with_skip_methods`](Self::with_skip_methods).
+    pub fn with_skip_paths(mut self, paths: Vec<String>) -> Self {
+        self.skip_paths = paths;
+        self
+    }
+
+    /// Set what happens to `HEAD` requests
+    ///
+    /// [`HeadPolicy::Skip`] passes them through unverified, so monitors and link previewers
+    /// don't get `400`s; handlers that extract [`VerifiedTurnstile`] still reject them.
+    pub fn with_head_policy(mut self, policy: HeadPolicy) -> Self {
+        self.head_policy = policy;
+        self
+    }
+
+    /// Set whether CORS preflight requests pass through unverified
+    ///
+    /// Browsers send a preflight, an `OPTIONS` request with `Access-Control-Request-Method`,
+    /// before a cross-origin request carrying the token header, and never send a token with
+    /// it. Rejecting it means the real request is never sent, so preflights pass through by
+    /// default with [`Decision::SkippedPreflight`] and without a [`VerifiedTurnstile`]; let
+    /// your CORS layer answer them. Other `OPTIONS` requests are checked as usual.
+    pub fn with_cors_preflight_passthrough(mut self, enabled: bool) -> Self {
+        self.cors_preflight_passthrough = enabled;
+        self
+    }
+
+    /// Reject requests that didn't arrive over HTTPS with `400 Bad Request` before
+    /// verifying them
+    ///
+    /// Behind a TLS-terminating proxy, also set
+    /// [`with_forwarded_proto_header`](Self::with_forwarded_proto_header); otherwise the
+    /// request's own scheme is used, and requests whose scheme is unknown are rejected.
+    pub fn with_require_https(mut self, require: bool) -> Self {
+        self.require_https = require;
+        self
+    }
+
+    /// Take the client's protocol from this header (e.g. `X-Forwarded-Proto`)
+    ///
+    /// Like [`with_remote_ip_header`](Self::with_remote_ip_header), only set this if a
+    /// proxy you control overwrites the header on every request.
+    pub fn with_forwarded_proto_header(mut self, name: impl Into<String>) -> Self {
+        self.forwarded_proto_header = Some(name.into());
+        self
+    }
+
+    /// Send the client IP from this header (e.g. `CF-Connecting-IP`) as `remoteip`
+    pub fn with_remote_ip_header(mut self, name: impl Into<String>) -> Self {
+        self.remote_ip_header = Some(name.into());
+        self
+    }
+
+    /// Take the client IP sent as `remoteip` from `X-Forwarded-For`, as appended by these
+    /// proxies
+    ///
+    /// Clients can send `X-Forwarded-For` themselves, so only the entries your proxies added
+    /// are trusted; see [`TrustedProxies`]. When the header doesn't match them, the
+    /// connection's peer address is sent instead, if the app was served with
+    /// [`ConnectInfo`](axum::extract::ConnectInfo). A
+    /// [`remote_ip_header`](Self::with_remote_ip_header) takes precedence when present.
+    ///
+    /// ```rust
+    /// use axum_turnstile::{TrustedProxies, TurnstileConfig};
+    ///
+    /// // One load balancer in front of the app
+    /// let config = TurnstileConfig::new("secret").with_trusted_proxies(TrustedProxies::count(1));
+    ///
+    /// // Any number of proxies in the private network
+    /// let proxies = TrustedProxies::networks(["10.0.0.0/8", "fd00::/8"]).unwrap();
+    /// let config = TurnstileConfig::new("secret").with_trusted_proxies(proxies);
+    /// ```
+    pub fn with_trusted_proxies(mut self, proxies: TrustedProxies) -> Self {
+        self.trusted_proxies = Some(proxies);
+        self
+    }
+
+    /// Send the connection's peer address as `remoteip` when no IP header is available
+    ///
+    /// The address comes from axum's [`ConnectInfo`](axum::extract::ConnectInfo), so serve
+    /// the app with `into_make_service_with_connect_info::<SocketAddr>()`. Behind a proxy
+    /// the peer is the proxy itself; use [`with_remote_ip_header`](Self::with_remote_ip_header)
+    /// there instead.
+    pub fn with_remote_ip_from_peer(mut self, enabled: bool) -> Self {
+        self.remote_ip_from_peer = enabled;
+        self
+    }
+
+    /// Enable or disable client IP normalization
+    ///
+    /// When enabled, IPv6 zone identifiers are stripped and IPv4-mapped IPv6 addresses are
+    /// sent as plain IPv4. Values that aren't valid IPs are not sent at all.
+    pub fn with_ip_normalization(mut self, enabled: bool) -> Self {
+        self.ip_normalization = enabled;
+        self
+    }
+
+    /// Let requests over a connection with a verified client certificate skip verification
+    ///
+    /// For service-to-service traffic authenticated by mTLS, which shouldn't face a human
+    /// challenge. Such requests are let through with [`Decision::BypassedByMtls`] and a
+    /// [`VerifiedTurnstile`], before the HTTPS, origin, country and token checks. Your TLS
+    /// layer must insert [`VerifiedClientCert`]; see its docs for what that layer has to
+    /// guarantee.
+    pub fn with_skip_on_client_cert(mut self, enabled: bool) -> Self {
+        self.skip_on_client_cert = enabled;
+        self
+    }
+
+    /// Accept requests that satisfy another check instead of Turnstile, such as a valid CSRF
+    ///
+    /// When `guard` returns `true` for the request head, the Turnstile checks are skipped
+    /// and the request is let through with [`Decision::BypassedByGuard`] and a
+    /// [`VerifiedTurnstile`]. Handy while migrating forms that already have CSRF
+    /// protection:
+    ///
+    /// ```rust
+    /// use axum_turnstile::TurnstileConfig;
+    ///
+    /// # fn csrf_token_is_valid(_: &str) -> bool { true }
+    /// let config = TurnstileConfig::new("your-secret-key").with_alternative_guard(|parts| {
+    ///     parts
+    ///         .headers
+    ///         .get("X-CSRF-Token")
+    ///         .and_then(|v| v.to_str().ok())
+    ///         .is_some_and(csrf_token_is_valid)
+    /// });
+    /// ```
+    pub fn with_alternative_guard(
+        mut self,
+        guard: impl Fn(&Parts) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.alternative_guard = Some(Arc::new(guard));
+        self
+    }
+
+    /// Bind tokens to the request they were solved for
+    ///
+    /// Render the widget with `cData` set to `fingerprint` of the request it will be
+    /// submitted with, e.g. its method, path and selected headers. The middleware
+    /// recomputes the fingerprint from the submitted request and rejects it with `403` and
+    /// [`ValidatorKind::Fingerprint`] unless it equals the returned `cdata`, or what follows
+    /// [`cdata_prefix`](Self::cdata_prefix) when one is set. A token solved for one request
+    /// can't then be replayed against another. [`Turnstile`] has no request to fingerprint
+    /// and doesn't check this.
+    ///
+    /// ```rust
+    /// use axum_turnstile::TurnstileConfig;
+    ///
+    /// let config = TurnstileConfig::new("your-secret-key")
+    ///     .with_request_fingerprint(|parts| format!("{} {}", parts.method, parts.uri.path()));
+    /// ```
+    pub fn with_request_fingerprint(
+        mut self,
+        fingerprint: impl Fn(&Parts) -> String + Send + Sync + 'static,
+    ) -> Self {
+        self.request_fingerprint = Some(Arc::new(fingerprint));
+        self
+    }
+
+    /// Only accept requests whose `Origin` is in this list (e.g. `https://myapp.example`)
+    ///
+    /// The check runs before verification, so cross-site requests are rejected with
+    /// `403 Forbidden` without a round trip to Cloudflare. The origin is taken from the
+    /// `Referer` header when `Origin` is absent. Comparison is case-insensitive.
+    pub fn with_allowed_origins(mut self, origins: Vec<String>) -> Self {
+        self.allowed_origins = origins;
+        self
+    }
+
+    /// Set whether requests with neither `Origin` nor `Referer` pass the origin check
+    pub fn with_allow_missing_origin(mut self, allow: bool) -> Self {
+        self.allow_missing_origin = allow;
+        self
+    }
+
+    /// Require Cloudflare to report this `action` for the token
+    ///
+    /// Render each form's widget with its own action, e.g. `login`, so a token solved on one
+    /// form can't be replayed against another. A successful token reporting a different or
+    /// no action is rejected with `403` and [`ValidatorKind::Action`]. Without an expected
+    /// action the returned one isn't checked.
+    pub fn with_expected_action(mut self, action: impl Into<String>) -> Self {
+        self.expected_action = Some(action.into());
+        self
+    }
+
+    /// Require Cloudflare to report one of these hostnames (case-insensitive)
+    ///
+    /// Stops a token solved on another site, e.g. one an attacker embeds your sitekey in,
+    /// from passing here: a successful token for any other hostname is rejected with `403`
+    /// and [`ValidatorKind::Hostname`]. An empty list disables the check.
+    pub fn with_expected_hostnames(mut self, hostnames: Vec<String>) -> Self {
+        self.expected_hostnames = hostnames;
+        self
+    }
+
+    /// Verify a separate widget whose token is in the top-level JSON body field `field`
+    ///
+    /// Call once per widget for pages with several widgets submitted together. Every
+    /// widget's token is verified and must report its own `expected_action` (the other
+    /// validators apply to all of them); the request only proceeds if all pass, with the
+    /// per-widget outcomes available as [`WidgetOutcomes`]. Replaces
+    /// [`token_source`](Self::with_token_source) and
+    /// [`expected_action`](Self::with_expected_action).
+    pub fn with_widget(
+        mut self,
+        field: impl Into<String>,
+        expected_action: impl Into<String>,
+    ) -> Self {
+        self.widgets.insert(field.into(), expected_action.into());
+        self
+    }
+
+    /// Set the hostnames classified as [`HostnameClass::FirstParty`]
+    ///
+    /// Unlike [`with_expected_hostnames`](Self::with_expected_hostnames) this never rejects
+    /// anything; it only decides the bucket reported in
+    /// [`TurnstileOutcome::hostname_class`] and [`AuditEvent::hostname_class`].
+    pub fn with_first_party_hostnames(mut self, hostnames: Vec<String>) -> Self {
+        self.first_party_hostnames = hostnames;
+        self
+    }
+
+    /// Reject successful tokens whose `action` is missing or empty
+    ///
+    /// Stricter than [`with_expected_action`](Self::with_expected_action) on its own: use it
+    /// on sensitive routes that rely on action binding, so a widget rendered without an
+    /// action fails with `403` instead of slipping through.
+    pub fn with_require_action_present(mut self, require: bool) -> Self {
+        self.require_action_present = require;
+        self
+    }
+
+    /// Require Cloudflare to report this `cdata` for the token
+    pub fn with_expected_cdata(mut self, cdata: impl Into<String>) -> Self {
+        self.expected_cdata = Some(cdata.into());
+        self
+    }
+
+    /// Require Cloudflare to report a `cdata` starting with `prefix`
+    ///
+    /// Suits namespaced values like `order:12345`. The rest of the value is available to
+    /// handlers as [`TurnstileOutcome::cdata_suffix`].
+    pub fn with_cdata_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.cdata_prefix = Some(prefix.into());
+        self
+    }
+
+    /// Append `policy`'s checks to the built-in ones, such as the expected action, rejecting
+    /// with the reason of the first that fails, see [`VerificationPolicy`]
+    pub fn with_policy(mut self, policy: VerificationPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Cache verification results until the token's `challenge_ts` plus `window`
+    ///
+    /// Repeated submissions of the same token within that time reuse the first result
+    /// instead of calling Cloudflare again, and a result is never cached for longer than
+    /// the token itself was valid. Successful results without a `challenge_ts` aren't
+    /// cached; failures are kept for `window` from when they happened, subject to
+    /// [`with_negative_caching`](Self::with_negative_caching).
+    pub fn with_cache_ttl_from_challenge(mut self, window: Duration) -> Self {
+        let cache = ResultCache::new(CacheTtl::FromChallenge(window));
+        let cache = match self.cache_max_age {
+            Some(max_age) => cache.with_max_age(max_age),
+            None => cache,
+        };
+        self.result_cache = Some(Arc::new(cache));
+        self
+    }
+
+    /// Cache verification results for `ttl` after they came in
+    ///
+    /// Meant for short windows that absorb double-clicked submit buttons and client retries:
+    /// the same token submitted again within `ttl` gets the first outcome, successful or
+    /// not, without another call to Cloudflare. This is a performance and UX measure, not
+    /// replay protection; it deliberately lets a duplicate through. Which failures are kept
+    /// is set with [`with_negative_caching`](Self::with_negative_caching), and a
+    /// [token store](Self::with_token_store) still rejects the duplicate before the cache
+    /// is consulted.
+    ///
+    /// Replaces a cache set with
+    /// [`with_cache_ttl_from_challenge`](Self::with_cache_ttl_from_challenge), and the
+    /// other way round.
+    pub fn with_result_cache(mut self, ttl: Duration) -> Self {
+        let cache = ResultCache::new(CacheTtl::Fixed(ttl));
+        let cache = match self.cache_max_age {
+            Some(max_age) => cache.with_max_age(max_age),
+            None => cache,
+        };
+        self.result_cache = Some(Arc::new(cache));
+        self
+    }
+
+    /// Never keep a cached result for longer than `max_age`, whatever the token's validity
+    ///
+    /// Entries expire at the earlier of the challenge-derived expiry and `max_age` after
+    /// they were cached, bounding how stale results can get across config changes. Only
+    /// has an effect together with
+    /// [`with_cache_ttl_from_challenge`](Self::with_cache_ttl_from_challenge) or
+    /// [`with_result_cache`](Self::with_result_cache).
+    pub fn with_cache_max_age(mut self, max_age: Duration) -> Self {
+        self.cache_max_age = Some(max_age);
+        if let Some(cache) = &self.result_cache {
+            self.result_cache = Some(Arc::new(cache.with_max_age(max_age)));
+        }
+        self
+    }
+
+    /// Choose which failed verifications the result cache keeps
+    ///
+    /// Only has an effect together with
+    /// [`with_cache_ttl_from_challenge`](Self::with_cache_ttl_from_challenge) or
+    /// [`with_result_cache`](Self::with_result_cache).
+    pub fn with_negative_caching(mut self, negative: NegativeCaching) -> Self {
+        self.negative_caching = negative;
+        self
+    }
+
+    /// Share one verification between identical concurrent requests
+    ///
+    /// While a token is being verified, other requests with the same token and client IP
+    /// wait for that result instead of calling Cloudflare again. If the first call fails,
+    /// the waiting requests verify on their own.
+    pub fn with_request_coalescing(mut self, enabled: bool) -> Self {
+        self.coalescer = enabled.then(|| Arc::new(Coalescer::default()));
+        self
+    }
+
+    /// Bound how long a coalesced request waits for the verification it joined
+    ///
+    /// A request that waits longer proceeds with its own verification, so a stalled call
+    /// can't hold up every identical request behind it. Only has an effect together with
+    /// [`with_request_coalescing`](Self::with_request_coalescing).
+    pub fn with_coalesce_timeout(mut self, timeout: Duration) -> Self {
+        self.coalesce_timeout = Some(timeout);
+        self
+    }
+
+    /// Send an [`AuditEvent`] to `sink` for every decision (default: [`NoopAuditSink`])
+    ///
+    /// See [`AuditSink`] for when events are recorded.
+    pub fn with_audit_sink(mut self, sink: Arc<dyn AuditSink>) -> Self {
+        self.audit_sink = sink;
+        self
+    }
+
+    /// Call `callback` with the outcome of every request, e.g. to feed your own metrics
+    ///
+    /// The outcome carries the decision, Cloudflare's error codes and the
+    /// [timings](TurnstileOutcome::timings) of the call. Like an [`AuditSink`], the callback
+    /// runs once per request inside the request future, right after the decision, so it
+    /// should only bump counters or hand off to a channel.
+    pub fn with_result_callback(
+        mut self,
+        callback: impl Fn(&TurnstileOutcome) + Send + Sync + 'static,
+    ) -> Self {
+        self.result_callback = Some(Arc::new(callback));
+        self
+    }
+
+    /// Record the client's country from Cloudflare's `CF-IPCountry` header in
+    /// [`TurnstileOutcome::country`]
+    ///
+    /// Only meaningful behind Cloudflare, which sets the header on every request it proxies.
+    pub fn with_geo_capture(mut self, enabled: bool) -> Self {
+        self.geo_capture = enabled;
+        self
+    }
+
+    /// Record the client's ASN from this header in [`TurnstileOutcome::asn`]
+    pub fn with_asn_header(mut self, name: impl Into<String>) -> Self {
+        self.asn_header = Some(name.into());
+        self
+    }
+
+    /// Reject requests from these countries with `403` before verifying their token
+    ///
+    /// Countries are two-letter codes as sent in `CF-IPCountry`, compared
+    /// case-insensitively. Requests without the header aren't blocked.
+    pub fn with_blocked_countries(mut self, countries: Vec<String>) -> Self {
+        self.blocked_countries = countries;
+        self
+    }
+
+    /// Warn about single verification calls that take longer than `threshold`
+    ///
+    /// Each slow call emits a `turnstile.slow_verification` warning with the measured
+    /// latency and counts towards [`TurnstileStats::slow_verifications`], separately from
+    /// the per-request outcome, so latency SLOs can alert before calls start failing.
+    pub fn with_slow_verification_threshold(mut self, threshold: Duration) -> Self {
+        self.slow_verification_threshold = Some(threshold);
+        self
+    }
+
+    /// Record each outcome as fields on the caller's current `tracing` span
+    ///
+    /// No span or event of its own is emitted, so an app that already wraps every request
+    /// in a span gets `turnstile.result` (`verified`, `rejected`, `monitored` or
+    /// `bypassed`), `turnstile.reason`, `turnstile.action`, `turnstile.hostname` and
+    /// `turnstile.verification_id` on it. `tracing` only records fields a span declared up
+    /// front, so declare the ones you want as [`Empty`](tracing::field::Empty):
+    ///
+    /// ```rust
+    /// let span = tracing::info_span!(
+    ///     "request",
+    ///     "turnstile.result" = tracing::field::Empty,
+    ///     "turnstile.reason" = tracing::field::Empty,
+    /// );
+    /// ```
+    #[cfg(feature = "tracing")]
+    pub fn with_current_span_fields(mut self, enabled: bool) -> Self {
+        self.current_span_fields = enabled;
+        self
+    }
+
+    /// Run the inner service with the outcome in its OpenTelemetry baggage
+    ///
+    /// The baggage gets `turnstile.verified` (`true` or `false`) and, when Cloudflare
+    /// reported one, `turnstile.action`, on top of whatever the current context already
+    /// carries. With a baggage propagator installed, outgoing requests made while handling
+    /// the request pass them on, so downstream services know it was human-verified.
+    #[cfg(feature = "opentelemetry")]
+    pub fn with_otel_baggage(mut self, enabled: bool) -> Self {
+        self.otel_baggage = enabled;
+        self
+    }
+
+    /// Echo each decision's [`TurnstileOutcome::verification_id`] in the
+    /// [`VERIFICATION_ID_HEADER`] response header
+    ///
+    /// Handlers get the same id from the [`TurnstileOutcome`] extension, so clients, logs
+    /// and business events can all refer to one verification.
+    pub fn with_verification_id_header(mut self, enabled: bool) -> Self {
+        self.verification_id_header = enabled;
+        self
+    }
+
+    /// List Cloudflare's error codes in the [`ERROR_CODES_HEADER`] of responses rejecting a
+    /// token, e.g. `timeout-or-duplicate,invalid-input-response`
+    ///
+    /// Lets frontends tell an expired token, worth a silent retry, from other failures
+    /// without a custom rejection body. Off by default, as the codes are Cloudflare's
+    /// internals; codes about the siteverify call itself, such as a wrong secret, are never
+    /// exposed. Characters other than letters, digits, `-` and `_` are dropped from the codes.
+    pub fn with_expose_error_codes(mut self, enabled: bool) -> Self {
+        self.expose_error_codes = enabled;
+        self
+    }
+
+    /// Set how long a single call to the verification endpoint may take
+    ///
+    /// A call that runs over fails with [`VerifyError::Timeout`] and the request is answered
+    /// with `504 Gateway Timeout`. The limit applies to each attempt, so
+    /// [retries](Self::with_internal_error_retries) can take longer in total. It also
+    /// applies to a client set with [`with_client`](Self::with_client).
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Start the inner service on `GET` and `HEAD` requests while the token is still being
+    /// verified, to hide the round trip to Cloudflare behind slow handlers
+    ///
+    /// **Only enable this if every handler behind the layer is free of side effects.** The
+    /// handler runs before it is known whether the token is valid: its response is only
+    /// returned if the request is let through, and its future is dropped as soon as the
+    /// request is rejected, but anything it did up to then has happened; errors it returns
+    /// are held back the same way. Handlers also don't see the [`VerifiedTurnstile`],
+    /// [`TurnstileOutcome`] or [`ResolvedClientIp`] extensions, which don't exist yet when
+    /// they start: the [`VerifiedTurnstile`] extractor answers the
+    /// [unverified status](Self::with_unverified_status) and `Option<VerifiedTurnstile>` is
+    /// `None`, so keep them off speculative routes. Other methods are always verified first.
+    /// Off by default.
+    pub fn with_speculative(mut self, enabled: bool) -> Self {
+        self.speculative = enabled;
+        self
+    }
+
+    /// Set the `Retry-After` sent when Cloudflare's API failed or was over capacity
+    ///
+    /// Applies to the built-in `500`, `502`, `503` and `504` answers, so clients back off
+    /// instead of retrying at once. Rounded down to whole seconds. Not sent for a malformed
+    /// siteverify request, which retrying can't fix.
+    pub fn with_retry_after(mut self, retry_after: Duration) -> Self {
+        self.retry_after = retry_after;
+        self
+    }
+
+    /// Set the status answered when the token is missing, empty or malformed
+    /// (default: `400 Bad Request`)
+    ///
+    /// # Panics
+    ///
+    /// When `status` is not a client or server error code.
+    pub fn with_missing_status(mut self, status: StatusCode) -> Self {
+        self.missing_status = error_status(status);
+        self
+    }
+
+    /// Set the status answered when the token failed verification or a validator, or the
+    /// token was already used or stale (default: `403 Forbidden`)
+    ///
+    /// # Panics
+    ///
+    /// When `status` is not a client or server error code.
+    pub fn with_failed_status(mut self, status: StatusCode) -> Self {
+        self.failed_status = error_status(status);
+        self
+    }
+
+    /// Set the status answered when Cloudflare's API errored or reported the verification
+    /// call as malformed (default: `500 Internal Server Error`)
+    ///
+    /// Empty answers, timeouts and exhausted capacity keep their `502`, `504` and `503`.
+    ///
+    /// # Panics
+    ///
+    /// When `status` is not a client or server error code.
+    pub fn with_error_status(mut self, status: StatusCode) -> Self {
+        self.error_status = error_status(status);
+        self
+    }
+
+    /// Set the status the [`VerifiedTurnstile`] extractor answers when the layer let the
+    /// request through without verifying it, e.g. in [`Mode::Monitor`] (default:
+    /// `401 Unauthorized`)
+    ///
+    /// # Panics
+    ///
+    /// When `status` is not a client or server error code.
+    pub fn with_unverified_status(mut self, status: StatusCode) -> Self {
+        self.unverified_status = error_status(status);
+        self
+    }
+
+    /// Set how often to retry a verification call that failed in transit
+    ///
+    /// Connection failures and [timeouts](Self::with_timeout) are retried after 50ms,
+    /// doubling for each further retry; the waits add up to at most 500ms per verification.
+    /// A `success: false` answer is never retried. A call can fail after Cloudflare spent the
+    /// token, so verifications with retries always send an `idempotency_key`, as with
+    /// [`with_idempotency`](Self::with_idempotency). Off by default.
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Set how often to retry a verification that failed on Cloudflare's side
+    ///
+    /// Codes like `internal-error` mean Cloudflare couldn't check the token, not that it was
+    /// bad. Once the retries are used up the request is treated as an API error (`500`)
+    /// rather than a rejected token. Retries wait like those of
+    /// [`with_retries`](Self::with_retries), sharing their 500ms budget, and a verification
+    /// that may be retried always sends an `idempotency_key`; set `0` to send none.
+    pub fn with_internal_error_retries(mut self, retries: u32) -> Self {
+        self.internal_error_retries = retries;
+        self
+    }
+
+    /// Treat a secret Cloudflare refuses as a server misconfiguration
+    ///
+    /// `missing-input-secret` and `invalid-input-secret` then reject with
+    /// [`RejectionReason::MalformedRequest`] and `500 Internal Server Error`, and are logged
+    /// as errors, instead of a `403` that blames the user's token.
+    pub fn with_secret_errors_as_server_error(mut self, enabled: bool) -> Self {
+        self.secret_errors_as_server_error = enabled;
+        self
+    }
+
+    /// Cap verification calls to Cloudflare at `per_second`, across all clients
+    ///
+    /// Calls draw from a token bucket holding up to `burst` calls, shared by every clone of
+    /// this config. Cached results don't count. What happens over the limit is set with
+    /// [`with_rate_limit_policy`](Self::with_rate_limit_policy).
+    pub fn with_global_rate_limit(mut self, per_second: u32, burst: u32) -> Self {
+        self.global_rate_limit = Some(Arc::new(TokenBucket::new(per_second, burst)));
+        self
+    }
+
+    /// Rate limit verified requests per identity, answering `429 Too Many Requests` over
+    /// the limit
+    ///
+    /// `identity` derives the key from the outcome of a successful verification, e.g. a
+    /// user id carried in `cdata`; outcomes it returns `None` for aren't limited. Each
+    /// identity gets a token bucket refilling `per_second` requests up to `burst`, shared
+    /// by every clone of this config.
+    pub fn with_identity_rate_limit(
+        mut self,
+        identity: impl Fn(&TurnstileOutcome) -> Option<String> + Send + Sync + 'static,
+        per_second: u32,
+        burst: u32,
+    ) -> Self {
+        self.identity_rate_limit = Some(Arc::new(IdentityRateLimit::new(
+            Arc::new(identity),
+            per_second,
+            burst,
+        )));
+        self
+    }
+
+    /// Cap verification calls per minute for the routes `budget` picks out
+    ///
+    /// `budget` sees the request head and returns a bucket key and that bucket's calls per
+    /// minute, or `None` for requests that shouldn't be limited. Requests over their bucket's
+    /// budget are shed with `503 Service Unavailable` before calling Cloudflare. Only requests
+    /// carrying a token count. Buckets are shared by every clone of this config, so routes
+    /// attackers favour can be held to a budget without touching the rest:
+    ///
+    /// ```rust
+    /// use axum_turnstile::TurnstileConfig;
+    ///
+    /// let config = TurnstileConfig::new("your-secret-key").with_route_verification_budget(|parts| {
+    ///     parts
+    ///         .uri
+    ///         .path()
+    ///         .starts_with("/signup")
+    ///         .then(|| ("signup".to_string(), 600))
+    /// });
+    /// ```
+    pub fn with_route_verification_budget(
+        mut self,
+        budget: impl Fn(&Parts) -> Option<(String, u32)> + Send + Sync + 'static,
+    ) -> Self {
+        self.route_budget = Some(Arc::new(RouteBudget::new(Arc::new(budget))));
+        self
+    }
+
+    /// Set what happens to verifications over the global rate limit
+    ///
+    /// [`RateLimitPolicy::SkipVerification`] keeps requests flowing during a spike, but
+    /// without a [`VerifiedTurnstile`], so handlers that extract it still reject.
+    pub fn with_rate_limit_policy(mut self, policy: RateLimitPolicy) -> Self {
+        self.rate_limit_policy = policy;
+        self
+    }
+
+    /// Set what happens when Cloudflare can't be asked or gives an unusable answer
+    ///
+    /// [`FailurePolicy::Open`] keeps the site up during a Cloudflare outage by letting
+    /// requests through unverified. Handlers can spot them by
+    /// [`VerifiedTurnstile::degraded`] and, say, hold back sensitive actions. A token
+    /// Cloudflare rejects is still rejected.
+    pub fn with_failure_policy(mut self, policy: FailurePolicy) -> Self {
+        self.failure_policy = policy;
+        self
+    }
+
+    /// Count every decision in `stats`
+    ///
+    /// Keep a clone of the handle to read the counters, e.g. to serve
+    /// [`TurnstileStats::render_prometheus`] at `/metrics`.
+    pub fn with_stats(mut self, stats: TurnstileStats) -> Self {
+        self.stats = Some(stats);
+        self
+    }
+
+    /// Keep the latest `capacity` outcomes in memory, for a debug endpoint
+    ///
+    /// The buffer is shared by every clone of this config, so keep a clone to read it with
+    /// [`recent_outcomes`](Self::recent_outcomes). Outcomes are stored without their
+    /// `token_hash` and `idempotency_key`; the secret and raw token are never part of one.
+    ///
+    /// ```rust
+    /// use axum::{routing::{get, post}, Router};
+    /// use axum_turnstile::{TurnstileConfig, TurnstileLayer};
+    ///
+    /// let config = TurnstileConfig::new("your-secret-key").with_recent_outcomes(50);
+    /// let debug = config.clone();
+    ///
+    /// let app: Router = Router::new()
+    ///     .route("/api/protected", post(|| async { "OK" }))
+    ///     .layer(TurnstileLayer::new(config))
+    ///     .route(
+    ///         "/debug/turnstile",
+    ///         get(move || async move { format!("{:#?}", debug.recent_outcomes()) }),
+    ///     );
+    /// ```
+    pub fn with_recent_outcomes(mut self, capacity: usize) -> Self {
+        self.recent_outcomes = Some(Arc::new(RecentOutcomes::new(capacity)));
+        self
+    }
+
+    /// The outcomes kept by [`with_recent_outcomes`](Self::with_recent_outcomes), oldest
+    /// first, or none if it isn't enabled
+    pub fn recent_outcomes(&self) -> Vec<TurnstileOutcome> {
+        self.recent_outcomes
+            .as_ref()
+            .map(|recent| recent.snapshot())
+            .unwrap_or_default()
+    }
+
+    /// Resolve the secret asynchronously for each verification instead of using `secret`
+    ///
+    /// If the provider fails, the request is treated like any other verification error.
+    pub fn with_secret_provider(mut self, provider: Arc<dyn SecretProvider>) -> Self {
+        self.secret_provider = Some(provider);
+        self
+    }
+
+    /// Reject tokens the store has already seen with `403` before asking Cloudflare
+    ///
+    /// Closes the window in which the same token, submitted twice at once, passes twice
+    /// because Cloudflare hasn't marked it spent yet. Only the token's SHA-256 hash is
+    /// stored. A resubmitted token is rejected even if the
+    /// [result cache](Self::with_cache_ttl_from_challenge) still holds its result. The
+    /// `memory-token-store` feature adds `MemoryTokenStore`, for a single instance.
+    pub fn with_token_store(mut self, store: Arc<dyn TokenStore>) -> Self {
+        self.token_store = Some(store);
+        self
+    }
+
+    /// Verify tokens with `verifier` instead of calling the verification endpoint
+    ///
+    /// Lets handlers behind the layer be tested with canned answers and no network, see
+    /// [`Verifier`]. The [timeout](Self::with_timeout) still applies to each call.
+    pub fn with_verifier(mut self, verifier: Arc<dyn Verifier>) -> Self {
+        self.verifier = Some(verifier);
+        self
+    }
+
+    /// Call the verification endpoint with this client instead of one built by this crate
+    ///
+    /// Use it to configure proxies, TLS roots or a `User-Agent`. The client is used as is,
+    /// so [`with_min_tls_version`](Self::with_min_tls_version) no longer applies.
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.http_client = Arc::new(OnceLock::from(client));
+        self.custom_client = true;
+        self
+    }
+
+    /// Require at least this TLS version when calling the verification endpoint
+    ///
+    /// This only applies to the HTTP client built by this crate. The native-tls backend can't
+    /// enforce a TLS 1.3 minimum; with it, every verification fails with a
+    /// [`VerifyError::Request`] builder error.
+    #[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
+    pub fn with_min_tls_version(mut self, version: reqwest::tls::Version) -> Self {
+        self.min_tls_version = Some(version);
+        if !self.custom_client {
+            self.http_client = Arc::default();
+        }
+        self
+    }
+
+    /// Give a custom risk engine the last word on every decision
+    ///
+    /// The callback runs after verification and all built-in checks, and sees the full
+    /// [`TurnstileOutcome`]. Returning [`DecisionOverride::Reject`] turns an otherwise
+    /// allowed request into a `403 Forbidden`; it can't allow a rejected request.
+    pub fn with_decision_override(
+        mut self,
+        callback: impl Fn(&TurnstileOutcome) -> DecisionOverride + Send + Sync + 'static,
+    ) -> Self {
+        self.decision_override = Some(Arc::new(callback));
+        self
+    }
+
+    /// Rate verified outcomes with your own classifier instead of [`RiskLevel::classify`]
+    ///
+    /// The level ends up in [`TurnstileOutcome::risk_level`], e.g. for handlers that ask for
+    /// a second factor on [`RiskLevel::High`]. The classifier runs before the
+    /// [decision override](Self::with_decision_override), which sees its result.
+    pub fn with_risk_classifier(
+        mut self,
+        classifier: impl Fn(&TurnstileOutcome) -> RiskLevel + Send + Sync + 'static,
+    ) -> Self {
+        self.risk_classifier = Some(Arc::new(classifier));
+        self
+    }
+
+    /// Push the outcome into your own request context before the inner service runs
+    ///
+    /// The callback receives the request's extensions and the final [`TurnstileOutcome`]
+    /// of every request that is let through, so you can update a context type inserted by
+    /// an earlier layer. It isn't called for rejected requests.
+    pub fn with_context_enricher(
+        mut self,
+        enricher: impl Fn(&mut Extensions, &TurnstileOutcome) + Send + Sync + 'static,
+    ) -> Self {
+        self.context_enricher = Some(Arc::new(enricher));
+        self
+    }
+
+    /// Build the response for upstream failures yourself instead of the fixed `500`/`502`
+    ///
+    /// Called with the [`VerifyError`] whenever Cloudflare couldn't be asked or gave an
+    /// unusable answer, so you can log it and answer with your own error type:
+    /// `|e| MyError::from(e).into_response()`. Other rejections are unaffected.
+    pub fn with_upstream_error_response(
+        mut self,
+        handler: impl Fn(&VerifyError) -> Response<Body> + Send + Sync + 'static,
+    ) -> Self {
+        self.upstream_error_response = Some(Arc::new(handler));
+        self
+    }
+
+    /// Build the response for rejected requests yourself instead of the plain-text defaults
+    ///
+    /// Called with the [`RejectionReason`] of every rejection, so you can answer with your
+    /// own status codes and error envelope. Upstream failures go to
+    /// [`with_upstream_error_response`](Self::with_upstream_error_response) instead when
+    /// that is set too.
+    ///
+    /// ```rust
+    /// use axum::{http::StatusCode, response::IntoResponse, Json};
+    /// use axum_turnstile::{RejectionReason, TurnstileConfig};
+    ///
+    /// let config = TurnstileConfig::new("your-secret-key").with_rejection(|reason| {
+    ///     let (status, codes) = match reason {
+    ///         RejectionReason::MissingToken => (StatusCode::BAD_REQUEST, vec![]),
+    ///         RejectionReason::VerificationFailed(codes) => (StatusCode::FORBIDDEN, codes.clone()),
+    ///         _ => (StatusCode::FORBIDDEN, vec![]),
+    ///     };
+    ///     let body = serde_json::json!({ "error": "captcha", "codes": codes });
+    ///     (status, Json(body)).into_response()
+    /// });
+    /// ```
+    pub fn with_rejection(
+        mut self,
+        handler: impl Fn(&RejectionReason) -> Response<Body> + Send + Sync + 'static,
+    ) -> Self {
+        self.rejection_response = Some(Arc::new(handler));
+        self
+    }
+
+    /// Have [`verify_handler`] issue an HS256 session JWT after a successful verification
+    ///
+    /// The token is valid for `ttl` and carries `claims` alongside the verification outcome.
+    /// Validate it on later requests with the [`TurnstileSession`] extractor, putting a
+    /// [`SessionJwt`] with the same key in the router state (see
+    /// [`session_jwt`](Self::session_jwt)).
+    pub fn with_session_jwt(
+        mut self,
+        key: impl AsRef<[u8]>,
+        ttl: Duration,
+        claims: serde_json::Map<String, serde_json::Value>,
+    ) -> Self {
+        self.session_jwt = Some(SessionJwt::new(key, ttl, claims));
         self
     }
+
+    /// Resolve the client IP from `headers` the same way the middleware does for `remoteip`
+    ///
+    /// For layers outside the Turnstile layer that need the same key, e.g. a rate limiter.
+    /// Layers inside it can read the [`ResolvedClientIp`] extension instead.
+    pub fn resolve_client_ip(&self, headers: &axum::http::HeaderMap) -> Option<ResolvedClientIp> {
+        ip::resolve_remote_ip(headers, self).map(ResolvedClientIp)
+    }
+
+    /// The session JWT signer set with [`with_session_jwt`](Self::with_session_jwt), if any
+    pub fn session_jwt(&self) -> Option<&SessionJwt> {
+        self.session_jwt.as_ref()
+    }
 }
 
 #[derive(Serialize)]
 struct VerifyRequest {
     secret: String,
     response: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    remoteip: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    idempotency_key: Option<String>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone)]
 struct VerifyResponse {
+    #[serde(deserialize_with = "deserialize_success")]
     success: bool,
     #[serde(rename = "error-codes")]
     error_codes: Option<Vec<String>>,
+    challenge_ts: Option<String>,
+    hostname: Option<String>,
+    action: Option<String>,
+    cdata: Option<String>,
+    /// Only sent for Enterprise customers
+    #[serde(default)]
+    metadata: Option<TurnstileMetadata>,
+    /// `cf-ray` header of the siteverify response, for support tickets with Cloudflare
+    #[serde(skip)]
+    cf_ray: Option<String>,
+}
+
+/// Additional data Cloudflare Enterprise attaches to a verification
+///
+/// Parsed from the siteverify response's `metadata` object. Fields Cloudflare doesn't send
+/// stay `None`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+#[non_exhaustive]
+pub struct TurnstileMetadata {
+    /// ID linking solves by the same visitor over a short period, without identifying them
+    pub ephemeral_id: Option<String>,
+}
+
+/// Check that a configured rejection status is a `4xx` or `5xx`
+fn error_status(status: StatusCode) -> StatusCode {
+    assert!(
+        status.is_client_error() || status.is_server_error(),
+        "Turnstile rejection status must be a client or server error, got {status}"
+    );
+    status
+}
+
+/// Accept `success` as a bool, or as `"true"`/`"false"` in any case, which some proxies
+/// and compatible endpoints send
+fn deserialize_success<'de, D>(deserializer: D) -> Result<bool, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Success {
+        Bool(bool),
+        String(String),
+    }
+
+    match Success::deserialize(deserializer)? {
+        Success::Bool(success) => Ok(success),
+        Success::String(s) if s.eq_ignore_ascii_case("true") => Ok(true),
+        Success::String(s) if s.eq_ignore_ascii_case("false") => Ok(false),
+        Success::String(s) => Err(serde::de::Error::invalid_value(
+            serde::de::Unexpected::Str(&s),
+            &"a bool or \"true\"/\"false\"",
+        )),
+    }
+}
+
+/// Cloudflare's answer to a verification call
+///
+/// A stable view of the siteverify response: new response fields can be added without
+/// breaking code that reads these, and the wire format can change without touching it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct TurnstileVerification {
+    /// Whether Cloudflare accepted the token
+    pub success: bool,
+    /// When the challenge was solved, parsed from `challenge_ts`
+    pub challenge_ts: Option<SystemTime>,
+    /// Hostname of the site the challenge was solved on
+    pub hostname: Option<String>,
+    /// `action` the widget was rendered with
+    pub action: Option<String>,
+    /// `cdata` the widget was rendered with
+    pub cdata: Option<String>,
+    /// Why Cloudflare rejected the token, if it did
+    pub error_codes: Vec<TurnstileErrorCode>,
+    /// Enterprise `metadata`, if Cloudflare sent it
+    pub metadata: Option<TurnstileMetadata>,
+}
+
+impl TurnstileVerification {
+    pub(crate) fn from_response(response: &VerifyResponse) -> Self {
+        Self {
+            success: response.success,
+            challenge_ts: response
+                .challenge_ts
+                .as_deref()
+                .and_then(timestamp::parse_rfc3339),
+            hostname: response.hostname.clone(),
+            action: response.action.clone(),
+            cdata: response.cdata.clone(),
+            error_codes: response
+                .error_codes
+                .as_deref()
+                .map(TurnstileErrorCode::parse_all)
+                .unwrap_or_default(),
+            metadata: response.metadata.clone(),
+        }
+    }
+}
+
+/// What Cloudflare reported about a verified token, extractable in handlers after
+/// successful verification
+///
+/// Requests that were let through without calling Cloudflare, like an
+/// [E2E bypass](TurnstileConfig::with_e2e_bypass_token), leave the fields `None`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct VerifiedTurnstile {
+    /// When the challenge was solved, parsed from `challenge_ts`
+    pub challenge_ts: Option<SystemTime>,
+    /// Hostname of the site the challenge was solved on
+    pub hostname: Option<String>,
+    /// `action` the widget was rendered with
+    pub action: Option<String>,
+    /// `cdata` the widget was rendered with
+    pub cdata: Option<String>,
+    /// Whether Cloudflare couldn't be asked and [`FailurePolicy::Open`] let the request
+    /// through unverified
+    pub degraded: bool,
+    /// Whether [`Mode::Passthrough`] let the request through without checking anything
+    pub bypassed: bool,
+    /// Enterprise `metadata`, such as the ephemeral ID, if Cloudflare sent it
+    pub metadata: Option<TurnstileMetadata>,
+    /// Cloudflare's full answer, if it was asked
+    pub verification: Option<TurnstileVerification>,
+}
+
+impl VerifiedTurnstile {
+    pub(crate) fn from_outcome(outcome: &TurnstileOutcome) -> Self {
+        Self {
+            challenge_ts: outcome
+                .challenge_ts
+                .as_deref()
+                .and_then(timestamp::parse_rfc3339),
+            hostname: outcome.hostname.clone(),
+            action: outcome.action.clone(),
+            cdata: outcome.cdata.clone(),
+            degraded: outcome.decision == Decision::Degraded,
+            bypassed: outcome.decision == Decision::Passthrough,
+            metadata: outcome
+                .verification
+                .as_ref()
+                .and_then(|verification| verification.metadata.clone()),
+            verification: outcome.verification.clone(),
+        }
+    }
+}
+
+/// Left in the extensions of every request the middleware lets through, so extractors can
+/// tell a request that wasn't verified from a route without the layer
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct LayerMarker {
+    pub unverified_status: StatusCode,
+}
+
+impl LayerMarker {
+    pub(crate) fn new(config: &TurnstileConfig) -> Self {
+        Self {
+            unverified_status: config.unverified_status,
+        }
+    }
+}
+
+/// Why the [`VerifiedTurnstile`] extractor failed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum VerifiedTurnstileRejection {
+    /// No [`TurnstileLayer`] ran for the request, so the route was set up without one
+    ///
+    /// Answered with `500 Internal Server Error`, as this is a bug in the app rather than
+    /// something the client did.
+    MissingLayer,
+    /// The layer let the request through without a verified token, e.g. in
+    /// [`Mode::Monitor`], a [skipped](TurnstileConfig::with_skip_paths) request or
+    /// during [speculative](TurnstileConfig::with_speculative) execution
+    ///
+    /// Answered with the configured
+    /// [`unverified_status`](TurnstileConfig::with_unverified_status).
+    NotVerified(StatusCode),
+}
+
+impl fmt::Display for VerifiedTurnstileRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifiedTurnstileRejection::MissingLayer => {
+                f.write_str("VerifiedTurnstile extracted on a route without TurnstileLayer")
+            }
+            VerifiedTurnstileRejection::NotVerified(_) => {
+                f.write_str("Turnstile token was not verified")
+            }
+        }
+    }
 }
 
-/// Marker type that can be extracted in handlers after successful verification
-#[derive(Clone, Debug)]
-pub struct VerifiedTurnstile;
+impl std::error::Error for VerifiedTurnstileRejection {}
+
+impl IntoResponse for VerifiedTurnstileRejection {
+    fn into_response(self) -> axum::response::Response {
+        match self {
+            VerifiedTurnstileRejection::MissingLayer => {
+                error!("{self}, check that the layer is added to this route");
+                (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()).into_response()
+            }
+            VerifiedTurnstileRejection::NotVerified(status) => {
+                (status, self.to_string()).into_response()
+            }
+        }
+    }
+}
 
+/// Extracts what Cloudflare reported, if the middleware verified the token
+///
+/// Fails with [`VerifiedTurnstileRejection::MissingLayer`] on routes without a
+/// [`TurnstileLayer`], and with [`VerifiedTurnstileRejection::NotVerified`] when the layer
+/// let the request through unverified.
 impl<S> FromRequestParts<S> for VerifiedTurnstile
 where
     S: Send + Sync,
 {
-    type Rejection = StatusCode;
+    type Rejection = VerifiedTurnstileRejection;
 
     async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
-        parts
-            .extensions
-            .get::<VerifiedTurnstile>()
-            .cloned()
-            .ok_or(StatusCode::UNAUTHORIZED)
+        if let Some(verified) = parts.extensions.get::<VerifiedTurnstile>() {
+            return Ok(verified.clone());
+        }
+        match parts.extensions.get::<LayerMarker>() {
+            Some(marker) => Err(VerifiedTurnstileRejection::NotVerified(
+                marker.unverified_status,
+            )),
+            None => Err(VerifiedTurnstileRejection::MissingLayer),
+        }
+    }
+}
+
+/// Extracts `Option<VerifiedTurnstile>`, `None` when the layer let the request through
+/// unverified
+///
+/// For routes that serve unverified requests too, typically in [`Mode::Monitor`], and add
+/// friction such as stricter rate limits only for those. Still fails with
+/// [`VerifiedTurnstileRejection::MissingLayer`] on routes without a [`TurnstileLayer`], so a
+/// forgotten layer isn't mistaken for unverified traffic.
+impl<S> OptionalFromRequestParts<S> for VerifiedTurnstile
+where
+    S: Send + Sync,
+{
+    type Rejection = VerifiedTurnstileRejection;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &S,
+    ) -> Result<Option<Self>, Self::Rejection> {
+        match <Self as FromRequestParts<S>>::from_request_parts(parts, state).await {
+            Ok(verified) => Ok(Some(verified)),
+            Err(VerifiedTurnstileRejection::NotVerified(_)) => Ok(None),
+            Err(rejection) => Err(rejection),
+        }
     }
 }
 
@@ -206,6 +2405,98 @@ mod tests {
         assert_eq!(response.status(), StatusCode::BAD_REQUEST);
     }
 
+    #[test]
+    fn test_debug_redacts_secret() {
+        let config = TurnstileConfig::new("0x4AAAAAAAsecret");
+        let debug = format!("{config:?}");
+        assert!(!debug.contains("0x4AAAAAAAsecret"));
+        assert!(debug.contains(r#"secret: "<redacted>""#));
+    }
+
+    #[tokio::test]
+    async fn test_extractor_tells_missing_layer_from_unverified() {
+        let handler =
+            || get(|verified: VerifiedTurnstile| async move { verified.degraded.to_string() });
+        let send = |app: Router| async move {
+            let response = app
+                .oneshot(Request::get("/test").body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            let status = response.status();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            (status, String::from_utf8(body.to_vec()).unwrap())
+        };
+
+        let (status, body) = send(Router::new().route("/test", handler())).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(body.contains("without TurnstileLayer"), "{body}");
+
+        let monitored = TurnstileConfig::new("secret").with_mode(Mode::Monitor);
+        let app = Router::new()
+            .route("/test", handler())
+            .layer(TurnstileLayer::new(monitored.clone()));
+        assert_eq!(send(app).await.0, StatusCode::UNAUTHORIZED);
+
+        let app = Router::new()
+            .route("/test", handler())
+            .layer(TurnstileLayer::new(
+                monitored.with_unverified_status(StatusCode::FORBIDDEN),
+            ));
+        assert_eq!(send(app).await.0, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_optional_extractor_branches_on_verification() {
+        let mock = test_support::MockSiteverify::success().await;
+        let handler = || {
+            get(|verified: Option<VerifiedTurnstile>| async move {
+                match verified {
+                    Some(_) => "verified",
+                    None => "unverified, rate limited harder",
+                }
+            })
+        };
+        let send = |app: Router, token: Option<&'static str>| async move {
+            let mut request = Request::get("/test");
+            if let Some(token) = token {
+                request = request.header("CF-Turnstile-Token", token);
+            }
+            let response = app
+                .oneshot(request.body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            let status = response.status();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            (status, String::from_utf8(body.to_vec()).unwrap())
+        };
+
+        let app = Router::new()
+            .route("/test", handler())
+            .layer(TurnstileLayer::new(
+                TurnstileConfig::new("secret")
+                    .with_verify_url(&mock.url)
+                    .with_mode(Mode::Monitor),
+            ));
+        assert_eq!(
+            send(app.clone(), Some("token")).await,
+            (StatusCode::OK, "verified".to_string())
+        );
+        assert_eq!(
+            send(app, None).await,
+            (
+                StatusCode::OK,
+                "unverified, rate limited harder".to_string()
+            )
+        );
+
+        let (status, _) = send(Router::new().route("/test", handler()), Some("token")).await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
     #[tokio::test]
     async fn test_with_test_key() {
         // Using Cloudflare's test key
@@ -225,4 +2516,255 @@ mod tests {
 
         assert_eq!(response.status(), StatusCode::OK);
     }
+
+    #[test]
+    fn test_from_env() {
+        // SAFETY: the variables are unique to this test, so nothing reads them concurrently
+        unsafe {
+            std::env::set_var("FROM_ENV_TEST_SECRET", "env-secret");
+            std::env::set_var("FROM_ENV_TEST_HEADER_NAME", "X-Captcha");
+        }
+        let config = TurnstileConfig::from_env_with_prefix("FROM_ENV_TEST_").unwrap();
+        assert_eq!(config.secret(), "env-secret");
+        assert_eq!(config.header_name(), "X-Captcha");
+        assert_eq!(config.verify_url(), TurnstileConfig::new("").verify_url());
+
+        // SAFETY: as above
+        unsafe {
+            std::env::remove_var("FROM_ENV_TEST_SECRET");
+        }
+        assert!(matches!(
+            TurnstileConfig::from_env_with_prefix("FROM_ENV_TEST_"),
+            Err(VarError::NotPresent)
+        ));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+            let invalid = std::ffi::OsStr::from_bytes(b"http://\xff");
+            // SAFETY: as above
+            unsafe {
+                std::env::set_var("FROM_ENV_TEST_SECRET", "env-secret");
+                std::env::set_var("FROM_ENV_TEST_VERIFY_URL", invalid);
+            }
+            assert!(matches!(
+                TurnstileConfig::from_env_with_prefix("FROM_ENV_TEST_"),
+                Err(VarError::NotUnicode(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_success_as_bool_or_string() {
+        let parse = |json: &str| serde_json::from_str::<VerifyResponse>(json).map(|r| r.success);
+
+        assert!(parse(r#"{"success": true}"#).unwrap());
+        assert!(!parse(r#"{"success": false}"#).unwrap());
+        assert!(parse(r#"{"success": "true"}"#).unwrap());
+        assert!(parse(r#"{"success": "TRUE"}"#).unwrap());
+        assert!(!parse(r#"{"success": "False"}"#).unwrap());
+        assert!(parse(r#"{"success": "yes"}"#).is_err());
+        assert!(parse(r#"{"success": 1}"#).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_handler_reads_verification_fields() {
+        let mock = test_support::MockSiteverify::start(serde_json::json!({
+            "success": true,
+            "challenge_ts": "2022-02-28T15:14:30.096Z",
+            "hostname": "example.com",
+            "action": "login",
+            "cdata": "session-1",
+        }))
+        .await;
+        let app = Router::new()
+            .route(
+                "/test",
+                get(|verified: VerifiedTurnstile| async move {
+                    format!(
+                        "{} {} {} {}",
+                        verified
+                            .challenge_ts
+                            .unwrap()
+                            .duration_since(std::time::UNIX_EPOCH)
+                            .unwrap()
+                            .as_millis(),
+                        verified.hostname.unwrap(),
+                        verified.action.unwrap(),
+                        verified.verification.unwrap().cdata.unwrap()
+                    )
+                }),
+            )
+            .layer(TurnstileLayer::new(
+                TurnstileConfig::new("secret").with_verify_url(&mock.url),
+            ));
+
+        let response = app
+            .oneshot(
+                Request::get("/test")
+                    .header("CF-Turnstile-Token", "test-token")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, "1646061270096 example.com login session-1");
+    }
+
+    #[test]
+    fn test_verification_from_wire_response() {
+        let response: VerifyResponse = serde_json::from_value(serde_json::json!({
+            "success": false,
+            "error-codes": ["timeout-or-duplicate", "something-new"],
+            "challenge_ts": "2022-02-28T15:14:30.096Z",
+            "hostname": "example.com",
+            "action": "login",
+            "cdata": "session-1",
+            "metadata": { "ephemeral_id": "x:9f2a" },
+        }))
+        .unwrap();
+
+        let verification = TurnstileVerification::from_response(&response);
+        assert!(!verification.success);
+        assert_eq!(
+            verification
+                .challenge_ts
+                .unwrap()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_millis(),
+            1646061270096
+        );
+        assert_eq!(verification.hostname.as_deref(), Some("example.com"));
+        assert_eq!(verification.action.as_deref(), Some("login"));
+        assert_eq!(verification.cdata.as_deref(), Some("session-1"));
+        assert_eq!(
+            verification.metadata.unwrap().ephemeral_id.as_deref(),
+            Some("x:9f2a")
+        );
+        assert_eq!(
+            verification.error_codes,
+            [
+                TurnstileErrorCode::TimeoutOrDuplicate,
+                TurnstileErrorCode::Unknown("something-new".to_string())
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_enterprise_metadata_reaches_handler() {
+        for (response, expected) in [
+            (
+                serde_json::json!({
+                    "success": true,
+                    "metadata": { "ephemeral_id": "x:9f78e0ed210960d7693b167e" },
+                }),
+                Some("x:9f78e0ed210960d7693b167e"),
+            ),
+            // Free plan: no metadata at all
+            (serde_json::json!({ "success": true }), None),
+            (serde_json::json!({ "success": true, "metadata": {} }), None),
+        ] {
+            let mock = test_support::MockSiteverify::start(response).await;
+            let app = Router::new()
+                .route(
+                    "/test",
+                    get(|verified: VerifiedTurnstile| async move {
+                        verified
+                            .metadata
+                            .and_then(|metadata| metadata.ephemeral_id)
+                            .unwrap_or_default()
+                    }),
+                )
+                .layer(TurnstileLayer::new(
+                    TurnstileConfig::new("secret").with_verify_url(&mock.url),
+                ));
+
+            let response = app
+                .oneshot(
+                    Request::get("/test")
+                        .header("CF-Turnstile-Token", "token")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert_eq!(&body[..], expected.unwrap_or_default().as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_region_and_verify_url_precedence() {
+        let config = TurnstileConfig::new("secret");
+        assert_eq!(config.region, Region::Global);
+        assert_eq!(
+            config.verify_url(),
+            "https://challenges.cloudflare.com/turnstile/v0/siteverify"
+        );
+        assert_eq!(
+            config.clone().with_region(Region::Global).verify_url(),
+            Region::Global.verify_url()
+        );
+
+        let custom = "https://mirror.example.com/siteverify";
+        for config in [
+            config
+                .clone()
+                .with_region(Region::Global)
+                .with_verify_url(custom),
+            config.with_verify_url(custom).with_region(Region::Global),
+        ] {
+            assert_eq!(config.verify_url(), custom);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_test_key_constructors() {
+        // Mimic Cloudflare's documented behavior for each test secret
+        let mock = test_support::MockSiteverify::respond_with(|request| {
+            match request.json()["secret"].as_str() {
+                Some(TEST_SECRET_PASS) => serde_json::json!({ "success": true }),
+                Some(TEST_SECRET_SPENT) => serde_json::json!({
+                    "success": false,
+                    "error-codes": ["timeout-or-duplicate"],
+                }),
+                _ => serde_json::json!({
+                    "success": false,
+                    "error-codes": ["invalid-input-response"],
+                }),
+            }
+        })
+        .await;
+
+        for (config, expected) in [
+            (TurnstileConfig::test_pass(), StatusCode::OK),
+            (TurnstileConfig::test_fail(), StatusCode::FORBIDDEN),
+            (TurnstileConfig::test_token_spent(), StatusCode::FORBIDDEN),
+        ] {
+            let app = Router::new()
+                .route("/test", get(|| async { "OK" }))
+                .layer(TurnstileLayer::new(config.with_verify_url(&mock.url)));
+
+            let response = app
+                .oneshot(
+                    Request::get("/test")
+                        .header("CF-Turnstile-Token", "test-token")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(response.status(), expected);
+        }
+    }
 }
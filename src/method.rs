@@ -0,0 +1,136 @@
+use axum::http::{header, Method, Request};
+
+/// What the middleware does with `HEAD` requests
+///
+/// Link previewers and uptime monitors send `HEAD` requests without a token.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HeadPolicy {
+    /// Check `HEAD` requests like any other, so they're rejected without a token (default)
+    #[default]
+    Reject,
+    /// Pass `HEAD` requests through unverified, without a [`VerifiedTurnstile`]
+    ///
+    /// [`VerifiedTurnstile`]: crate::VerifiedTurnstile
+    Skip,
+}
+
+/// Whether the request is a CORS preflight: an `OPTIONS` request naming the method of the
+/// request the browser wants to send
+pub(crate) fn is_cors_preflight<B>(req: &Request<B>) -> bool {
+    req.method() == Method::OPTIONS
+        && req
+            .headers()
+            .contains_key(header::ACCESS_CONTROL_REQUEST_METHOD)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Decision, TurnstileConfig, TurnstileLayer, TurnstileOutcome};
+    use axum::{
+        body::Body,
+        http::StatusCode,
+        routing::{get, post},
+        Extension, Router,
+    };
+    use tower::ServiceExt;
+
+    async fn head(policy: HeadPolicy) -> (StatusCode, Option<Decision>) {
+        let response = Router::new()
+            .route(
+                "/page",
+                get(|outcome: Option<Extension<TurnstileOutcome>>| async move {
+                    let decision = outcome.map(|Extension(outcome)| outcome.decision);
+                    (Extension(decision), "OK")
+                }),
+            )
+            .layer(TurnstileLayer::new(
+                TurnstileConfig::new("secret").with_head_policy(policy),
+            ))
+            .oneshot(Request::head("/page").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let decision = response.extensions().get::<Option<Decision>>().cloned();
+        (response.status(), decision.flatten())
+    }
+
+    #[tokio::test]
+    async fn test_head_rejected_by_default() {
+        assert_eq!(
+            head(HeadPolicy::default()).await,
+            (StatusCode::BAD_REQUEST, None)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_head_skipped() {
+        assert_eq!(
+            head(HeadPolicy::Skip).await,
+            (StatusCode::OK, Some(Decision::SkippedHead))
+        );
+    }
+
+    async fn send(
+        config: TurnstileConfig,
+        request: Request<Body>,
+    ) -> (StatusCode, Option<Decision>) {
+        let decision = |outcome: Option<Extension<TurnstileOutcome>>| async move {
+            let decision = outcome.map(|Extension(outcome)| outcome.decision);
+            (Extension(decision), "OK")
+        };
+        // The OPTIONS handler stands in for a CORS layer inside the Turnstile layer
+        let response = Router::new()
+            .route("/api/submit", post(decision).options(decision))
+            .layer(TurnstileLayer::new(config))
+            .oneshot(request)
+            .await
+            .unwrap();
+        let decision = response.extensions().get::<Option<Decision>>().cloned();
+        (response.status(), decision.flatten())
+    }
+
+    fn preflight() -> Request<Body> {
+        Request::options("/api/submit")
+            .header("Origin", "https://myapp.example")
+            .header("Access-Control-Request-Method", "POST")
+            .header(
+                "Access-Control-Request-Headers",
+                "cf-turnstile-token, content-type",
+            )
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_cors_preflight_passes_through() {
+        let config = TurnstileConfig::new("secret");
+
+        assert_eq!(
+            send(config.clone(), preflight()).await,
+            (StatusCode::OK, Some(Decision::SkippedPreflight))
+        );
+
+        // The real request still needs a token
+        let post = Request::post("/api/submit")
+            .header("Origin", "https://myapp.example")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(
+            send(config.clone(), post).await,
+            (StatusCode::BAD_REQUEST, None)
+        );
+
+        // So does a plain OPTIONS request that isn't a preflight
+        let options = Request::options("/api/submit").body(Body::empty()).unwrap();
+        assert_eq!(
+            send(config.clone(), options).await,
+            (StatusCode::BAD_REQUEST, None)
+        );
+
+        let config = config.with_cors_preflight_passthrough(false);
+        assert_eq!(
+            send(config, preflight()).await,
+            (StatusCode::BAD_REQUEST, None)
+        );
+    }
+}
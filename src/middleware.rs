@@ -1,31 +1,69 @@
-use crate::{verifier, TurnstileConfig, VerifiedTurnstile};
+use crate::{
+    decision, env_switch, geo, hostname, ip, method, origin, overrides, risk, scheme, sitekey,
+    skip,
+    token::{self, TokenError},
+    validate, verifier, widgets, AuditEvent, Decision, DecisionOverride, FailurePolicy, HeadPolicy,
+    LayerMarker, Mode, OversizePolicy, RateLimitPolicy, RejectionReason, ResolvedClientIp,
+    TurnstileConfig, TurnstileErrorCode, TurnstileOutcome, TurnstileRegistry,
+    TurnstileVerification, ValidationFailure, ValidatorKind, VerifiedClientCert, VerifiedTurnstile,
+    VerifyError, VerifyResponse, VerifyTimings, ERROR_CODES_HEADER, VERIFICATION_ID_HEADER,
+};
 use axum::{
-    body::Body,
-    http::{Request, Response, StatusCode},
+    body::{Body, Bytes},
+    http::{header::RETRY_AFTER, HeaderName, HeaderValue, Method, Request, Response, StatusCode},
+    BoxError,
+};
+use futures_util::future::{select, BoxFuture, Either};
+use http_body_util::Limited;
+use std::{
+    sync::Once,
+    task::{Context, Poll},
+    time::SystemTime,
 };
-use futures_util::future::BoxFuture;
-use std::task::{Context, Poll};
 use tower_service::Service;
 
 /// Middleware that verifies Turnstile tokens
+///
+/// The inner service is called at most once per request, and only after verification has
+/// finished. Whatever the verifier does internally never causes the handler to run twice.
+///
+/// Requests with any body type are accepted and handed on with an axum [`Body`], and the
+/// inner service may answer with any body type, which is turned into a [`Body`] too, so the
+/// middleware fits stacks that don't use axum's body throughout.
 #[derive(Clone)]
 pub struct TurnstileMiddleware<S> {
     inner: S,
-    config: TurnstileConfig,
+    configs: Configs,
+}
+
+/// Where the middleware takes the config for a request from
+#[derive(Clone, Debug)]
+pub(crate) enum Configs {
+    Single(Box<TurnstileConfig>),
+    Registry(TurnstileRegistry),
 }
 
 impl<S> TurnstileMiddleware<S> {
     pub fn new(inner: S, config: TurnstileConfig) -> Self {
-        Self { inner, config }
+        Self::with_configs(inner, Configs::Single(Box::new(config)))
+    }
+
+    pub(crate) fn with_configs(inner: S, configs: Configs) -> Self {
+        Self { inner, configs }
     }
 }
 
-impl<S> Service<Request<Body>> for TurnstileMiddleware<S>
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for TurnstileMiddleware<S>
 where
-    S: Service<Request<Body>, Response = Response<Body>> + Clone + Send + 'static,
+    S: Service<Request<Body>, Response = Response<ResBody>> + Clone + Send + 'static,
     S::Future: Send + 'static,
+    S::Error: Send,
+    ReqBody: http_body::Body<Data = Bytes> + Send + 'static,
+    ReqBody::Error: Into<BoxError>,
+    ResBody: http_body::Body<Data = Bytes> + Send + 'static,
+    ResBody::Error: Into<BoxError>,
 {
-    type Response = S::Response;
+    type Response = Response<Body>;
     type Error = S::Error;
     type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
 
@@ -33,47 +71,1642 @@ where
         self.inner.poll_ready(cx)
     }
 
-    fn call(&mut self, mut req: Request<Body>) -> Self::Future {
-        let config = self.config.clone();
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let mut req = req.map(Body::new);
+        let config = match &self.configs {
+            Configs::Single(config) => config,
+            Configs::Registry(registry) => {
+                let (parts, body) = req.into_parts();
+                let selected = registry.select(&parts);
+                req = Request::from_parts(parts, body);
+                match selected {
+                    Some(config) => config,
+                    None => return Box::pin(async { Ok(misconfigured()) }),
+                }
+            }
+        };
+        let config = env_switch::select(req.headers(), config);
+        let config = sitekey::select(req.headers(), config);
+        let config = overrides::apply(req.extensions(), config).into_owned();
+        // Call the service `poll_ready` readied and keep the not-yet-readied clone for the
+        // next request, as readiness may be tracked per instance
         let inner = self.inner.clone();
         let mut inner = std::mem::replace(&mut self.inner, inner);
 
+        if config.speculative && matches!(*req.method(), Method::GET | Method::HEAD) {
+            return Box::pin(speculate(inner, req, config));
+        }
+
         Box::pin(async move {
-            // Extract token from header
-            let token = req
-                .headers()
-                .get(&config.header_name)
-                .and_then(|v| v.to_str().ok());
-
-            let token = match token {
-                Some(t) => t,
-                None => {
-                    return Ok(Response::builder()
-                        .status(StatusCode::BAD_REQUEST)
-                        .body(Body::from("Missing Turnstile token"))
-                        .unwrap());
+            let (mut req, outcome, error) = evaluate(req, &config).await;
+            audit(&outcome, &config);
+            tarpit(&outcome, &config).await;
+            let verification_id = outcome.verification_id.clone();
+
+            let response = match &outcome.decision {
+                Decision::Rejected(reason) => with_error_codes(
+                    rejection(reason, error.as_ref(), &config),
+                    reason,
+                    &outcome.error_codes,
+                    &config,
+                ),
+                decision => {
+                    if decision.is_verified() {
+                        // Token is valid - hand what Cloudflare reported to the handler
+                        req.extensions_mut()
+                            .insert(VerifiedTurnstile::from_outcome(&outcome));
+                    }
+                    if let Some(verified_limit) = config.verified_body_limit {
+                        let limit = if decision.is_verified() {
+                            verified_limit
+                        } else {
+                            config.max_body_bytes
+                        };
+                        req = req.map(|body| Body::new(Limited::new(body, limit)));
+                    }
+                    if let Some(ip) = &outcome.remote_ip {
+                        req.extensions_mut().insert(ResolvedClientIp(ip.clone()));
+                    }
+                    if let Some(timings) = outcome.timings {
+                        req.extensions_mut().insert(timings);
+                    }
+                    if let Some(enricher) = &config.context_enricher {
+                        enricher(req.extensions_mut(), &outcome);
+                    }
+                    #[cfg(feature = "opentelemetry")]
+                    let otel_context = if config.otel_baggage {
+                        crate::baggage::context(&outcome)
+                    } else {
+                        opentelemetry::Context::current()
+                    };
+                    req.extensions_mut().insert(outcome);
+                    req.extensions_mut().insert(LayerMarker::new(&config));
+                    let response = inner.call(req);
+                    #[cfg(feature = "opentelemetry")]
+                    let response =
+                        opentelemetry::context::FutureExt::with_context(response, otel_context);
+                    response.await?.map(Body::new)
                 }
             };
+            Ok(with_verification_id(response, &verification_id, &config))
+        })
+    }
+}
+
+/// Call the inner service and verify the request at the same time, see
+/// [`TurnstileConfig::with_speculative`]
+///
+/// The inner response, or error, is held back until the request is let through; on a
+/// rejection the inner future is dropped, wherever it got to.
+async fn speculate<S, ResBody>(
+    mut inner: S,
+    req: Request<Body>,
+    config: TurnstileConfig,
+) -> Result<Response<Body>, S::Error>
+where
+    S: Service<Request<Body>, Response = Response<ResBody>>,
+    ResBody: http_body::Body<Data = Bytes> + Send + 'static,
+    ResBody::Error: Into<BoxError>,
+{
+    let (parts, body) = req.into_parts();
+    let mut handled = Request::from_parts(parts.clone(), body);
+    handled.extensions_mut().insert(LayerMarker::new(&config));
+    let handled = inner.call(handled);
+    let checked = async {
+        let (_, outcome, error) =
+            evaluate(Request::from_parts(parts, Body::empty()), &config).await;
+        audit(&outcome, &config);
+        tarpit(&outcome, &config).await;
+        (outcome, error)
+    };
+    futures_util::pin_mut!(handled, checked);
+
+    let (checked_first, handled_first) = match select(checked.as_mut(), handled.as_mut()).await {
+        Either::Left((checked, _)) => (Some(checked), None),
+        Either::Right((result, _)) => (None, Some(result)),
+    };
+    let (outcome, error) = match checked_first {
+        Some(checked) => checked,
+        None => checked.await,
+    };
+    let response = match &outcome.decision {
+        Decision::Rejected(reason) => with_error_codes(
+            rejection(reason, error.as_ref(), &config),
+            reason,
+            &outcome.error_codes,
+            &config,
+        ),
+        _ => match handled_first {
+            Some(result) => result?.map(Body::new),
+            None => handled.await?.map(Body::new),
+        },
+    };
+    Ok(with_verification_id(
+        response,
+        &outcome.verification_id,
+        &config,
+    ))
+}
+
+/// Add the `X-Turnstile-Verification-Id` header if configured
+pub(crate) fn with_verification_id(
+    mut response: Response<Body>,
+    verification_id: &str,
+    config: &TurnstileConfig,
+) -> Response<Body> {
+    if config.verification_id_header
+        && let Ok(value) = HeaderValue::from_str(verification_id)
+    {
+        response.headers_mut().insert(VERIFICATION_ID_HEADER, value);
+    }
+    response
+}
+
+/// Add the [`ERROR_CODES_HEADER`] to a token rejection if configured
+pub(crate) fn with_error_codes(
+    mut response: Response<Body>,
+    reason: &RejectionReason,
+    error_codes: &[String],
+    config: &TurnstileConfig,
+) -> Response<Body> {
+    let token_rejected = matches!(
+        reason,
+        RejectionReason::VerificationFailed(_) | RejectionReason::ExpiredOrUsed
+    );
+    if !config.expose_error_codes || !token_rejected {
+        return response;
+    }
+    // Codes come from Cloudflare's response and must not smuggle anything into the header
+    let codes: Vec<String> = error_codes
+        .iter()
+        .map(|code| {
+            code.chars()
+                .filter(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'))
+                .collect()
+        })
+        .filter(|code: &String| !code.is_empty())
+        .collect();
+    if !codes.is_empty()
+        && let Ok(value) = HeaderValue::from_str(&codes.join(","))
+    {
+        response.headers_mut().insert(ERROR_CODES_HEADER, value);
+    }
+    response
+}
+
+/// Record the final decision with the configured audit sink, result callback, stats and
+/// recent outcomes
+pub(crate) fn audit(outcome: &TurnstileOutcome, config: &TurnstileConfig) {
+    config.audit_sink.record(AuditEvent {
+        timestamp: SystemTime::now(),
+        verification_id: outcome.verification_id.clone(),
+        decision: outcome.decision.clone(),
+        token_hash: outcome.token_hash.clone(),
+        remote_ip: outcome.remote_ip.clone(),
+        country: outcome.country.clone(),
+        action: outcome.action.clone(),
+        hostname: outcome.hostname.clone(),
+        hostname_class: outcome.hostname_class,
+    });
+    if let Some(callback) = &config.result_callback {
+        callback(outcome);
+    }
+    if let Some(stats) = &config.stats {
+        stats.record(outcome);
+    }
+    if let Some(recent) = &config.recent_outcomes {
+        recent.push(outcome);
+    }
+    #[cfg(feature = "tracing")]
+    if config.current_span_fields {
+        crate::span::record_current(outcome);
+    }
+}
+
+/// Delay the response to a failed verification if the failure tarpit is enabled
+pub(crate) async fn tarpit(outcome: &TurnstileOutcome, config: &TurnstileConfig) {
+    let (Some(tarpit), Some(ip)) = (&config.failure_tarpit, &outcome.remote_ip) else {
+        return;
+    };
+    match &outcome.decision {
+        Decision::Rejected(RejectionReason::VerificationFailed(_)) => {
+            tokio::time::sleep(tarpit.failure(ip)).await;
+        }
+        Decision::Verified => tarpit.success(ip),
+        _ => {}
+    }
+}
+
+/// Whether the code means the call to siteverify was malformed, not that the token was bad
+fn is_malformed_request(code: &TurnstileErrorCode, config: &TurnstileConfig) -> bool {
+    match code {
+        TurnstileErrorCode::BadRequest | TurnstileErrorCode::MissingInputResponse => true,
+        code => code.is_secret_error() && config.secret_errors_as_server_error,
+    }
+}
+
+/// Run every check on the request and decide whether to let it through
+///
+/// Also returns the error behind an upstream failure, for
+/// [`with_upstream_error_response`](TurnstileConfig::with_upstream_error_response).
+pub(crate) async fn evaluate(
+    req: Request<Body>,
+    config: &TurnstileConfig,
+) -> (Request<Body>, TurnstileOutcome, Option<VerifyError>) {
+    let (req, outcome, error) = run_checks(req, config).await;
+
+    if let Some(limit) = &config.identity_rate_limit
+        && outcome.decision == Decision::Verified
+        && !limit.try_acquire(&outcome)
+    {
+        let outcome = outcome.reject(RejectionReason::IdentityRateLimited);
+        return (req, monitor(outcome, config), None);
+    }
+
+    // The override has the last word, but can only turn a pass into a rejection
+    if let Some(decision_override) = &config.decision_override
+        && outcome.decision.is_allowed()
+        && decision_override(&outcome) == DecisionOverride::Reject
+    {
+        return (
+            req,
+            monitor(outcome.reject(RejectionReason::Overridden), config),
+            None,
+        );
+    }
+
+    if let (Some(trust), Some(ip)) = (&config.ip_trust, &outcome.remote_ip)
+        && outcome.decision == Decision::Verified
+    {
+        trust.trust(ip);
+    }
+
+    (req, monitor(outcome, config), error)
+}
+
+/// In monitor mode, turn a rejection into a pass that records what would have happened
+fn monitor(mut outcome: TurnstileOutcome, config: &TurnstileConfig) -> TurnstileOutcome {
+    if config.mode == Mode::Monitor
+        && let Decision::Rejected(reason) = &outcome.decision
+    {
+        outcome.decision = Decision::Monitored(reason.clone());
+    }
+    outcome
+}
+
+/// Run the built-in checks on the request
+async fn run_checks(
+    mut req: Request<Body>,
+    config: &TurnstileConfig,
+) -> (Request<Body>, TurnstileOutcome, Option<VerifyError>) {
+    let remote_ip = ip::resolve_remote_ip(req.headers(), config)
+        .or_else(|| ip::forwarded_ip(req.headers(), req.extensions(), config))
+        .or_else(|| ip::peer_ip(req.extensions(), config));
+    let mut outcome = TurnstileOutcome::new(remote_ip);
+    outcome.sitekey = config.sitekey.clone();
+    if config.geo_capture {
+        outcome.country = geo::country(req.headers());
+    }
+    outcome.asn = geo::asn(req.headers(), config);
+
+    if config.mode == Mode::Passthrough {
+        warn_passthrough();
+        outcome.decision = Decision::Passthrough;
+        return (req, outcome, None);
+    }
+
+    if skip::matches(&req, config) {
+        outcome.decision = Decision::SkippedByRule;
+        return (req, outcome, None);
+    }
+
+    if config.head_policy == HeadPolicy::Skip && req.method() == Method::HEAD {
+        outcome.decision = Decision::SkippedHead;
+        return (req, outcome, None);
+    }
+
+    if config.cors_preflight_passthrough && method::is_cors_preflight(&req) {
+        outcome.decision = Decision::SkippedPreflight;
+        return (req, outcome, None);
+    }
+
+    if let Some(break_glass) = &config.break_glass
+        && break_glass.accepts(req.headers())
+    {
+        warn!(
+            method = %req.method(),
+            path = req.uri().path(),
+            verification_id = %outcome.verification_id,
+            remote_ip = ?outcome.remote_ip,
+            "Turnstile verification bypassed with a break-glass token"
+        );
+        outcome.decision = Decision::BypassedByBreakGlass;
+        return (req, outcome, None);
+    }
+
+    if config.skip_on_client_cert && req.extensions().get::<VerifiedClientCert>().is_some() {
+        outcome.decision = Decision::BypassedByMtls;
+        return (req, outcome, None);
+    }
+
+    if let Some(guard) = &config.alternative_guard {
+        let (parts, body) = req.into_parts();
+        let accepted = guard(&parts);
+        req = Request::from_parts(parts, body);
+        if accepted {
+            outcome.decision = Decision::BypassedByGuard;
+            return (req, outcome, None);
+        }
+    }
+
+    // Cheap pre-filters before touching the body or the network
+    if config.require_https && !scheme::is_https(&req, config) {
+        return (req, outcome.reject(RejectionReason::HttpsRequired), None);
+    }
+    if !origin::origin_allowed(req.headers(), config) {
+        return (req, outcome.reject(RejectionReason::OriginNotAllowed), None);
+    }
+    if geo::country_blocked(req.headers(), config) {
+        return (req, outcome.reject(RejectionReason::CountryBlocked), None);
+    }
+
+    if let Some(fingerprint) = &config.request_fingerprint {
+        let (parts, body) = req.into_parts();
+        outcome.request_fingerprint = Some(fingerprint(&parts));
+        req = Request::from_parts(parts, body);
+    }
+
+    if let (Some(trust), Some(ip)) = (&config.ip_trust, &outcome.remote_ip)
+        && trust.is_trusted(ip)
+    {
+        outcome.decision = Decision::TrustedIp;
+        return (req, outcome, None);
+    }
+
+    if !config.widgets.is_empty() {
+        return widgets::run_widget_checks(req, outcome, config).await;
+    }
+
+    // Extract token from the configured source
+    let (req, token) = token::extract_token(req, config).await;
+    let token = match token {
+        Ok(token) => token,
+        Err(e) => return (req, token_error(outcome, e, config), None),
+    };
+    let (req, within_budget) = take_route_budget(req, config);
+    if !within_budget {
+        let outcome = outcome.reject(RejectionReason::RouteBudgetExhausted);
+        return (req, outcome, None);
+    }
+
+    let expected_action = config.expected_action.as_deref();
+    let (outcome, error) = check_token(&token, outcome, expected_action, config).await;
+    (req, outcome, error)
+}
+
+/// Take a verification from the request's route budget, returning whether there was one
+/// left
+pub(crate) fn take_route_budget(
+    req: Request<Body>,
+    config: &TurnstileConfig,
+) -> (Request<Body>, bool) {
+    let Some(budget) = &config.route_budget else {
+        return (req, true);
+    };
+    let (parts, body) = req.into_parts();
+    let within_budget = budget.try_acquire(&parts);
+    (Request::from_parts(parts, body), within_budget)
+}
+
+/// Decide on a request whose token couldn't be extracted
+pub(crate) fn token_error(
+    mut outcome: TurnstileOutcome,
+    error: TokenError,
+    config: &TurnstileConfig,
+) -> TurnstileOutcome {
+    match (error, config.oversize_policy) {
+        (TokenError::Missing, _) => {
+            debug!(source = ?config.token_source, "no Turnstile token in request");
+            outcome.reject(RejectionReason::MissingToken)
+        }
+        (TokenError::Malformed, _) => {
+            debug!(source = ?config.token_source, "unusable Turnstile token in request");
+            outcome.reject(RejectionReason::MalformedToken)
+        }
+        (TokenError::TooLarge, OversizePolicy::Reject413) => {
+            outcome.reject(RejectionReason::PayloadTooLarge)
+        }
+        (TokenError::TooLarge, OversizePolicy::SkipVerification) => {
+            outcome.decision = Decision::SkippedOversizeBody;
+            outcome
+        }
+    }
+}
 
-            // Verify token
-            match verifier::verify_token(token, &config).await {
-                Ok(true) => {
-                    // Token is valid - add marker to extensions
-                    req.extensions_mut().insert(VerifiedTurnstile);
-                    inner.call(req).await
+/// Verify a single token and run the validators on Cloudflare's answer
+pub(crate) async fn check_token(
+    token: &str,
+    mut outcome: TurnstileOutcome,
+    expected_action: Option<&str>,
+    config: &TurnstileConfig,
+) -> (TurnstileOutcome, Option<VerifyError>) {
+    let sanitized;
+    let token = if config.token_sanitizer {
+        sanitized = token::sanitize(token);
+        sanitized.as_str()
+    } else {
+        token
+    };
+    let token_hash = token::hash_token(token);
+    outcome.token_hash = Some(token_hash.clone());
+
+    if let Some(bypass) = &config.e2e_bypass_token
+        && token::constant_time_eq(token.as_bytes(), bypass.as_bytes())
+    {
+        outcome.decision = Decision::BypassedByE2e;
+        return (outcome, None);
+    }
+
+    if let Some(store) = &config.token_store
+        && !store.check_and_insert(&token_hash).await
+    {
+        return (outcome.reject(RejectionReason::Replay), None);
+    }
+
+    let may_resend = config.retries > 0
+        || config.internal_error_retries > 0
+        || config.fallback_verify_url.is_some();
+    if (config.idempotency || may_resend) && outcome.idempotency_key.is_none() {
+        outcome.idempotency_key = Some(decision::new_verification_id());
+    }
+
+    // Verify token
+    let remote_ip = outcome.remote_ip.clone();
+    let idempotency_key = outcome.idempotency_key.as_deref();
+    let response = match verify(token, &token_hash, remote_ip, idempotency_key, config).await {
+        Ok((response, timings)) => {
+            if let Some(timings) = &timings {
+                report_latency(timings, config);
+            }
+            outcome.timings = timings;
+            response
+        }
+        Err(VerifyError::RateLimited) => {
+            return match config.rate_limit_policy {
+                RateLimitPolicy::Reject503 => (outcome.reject(RejectionReason::RateLimited), None),
+                RateLimitPolicy::SkipVerification => {
+                    outcome.decision = Decision::SkippedRateLimit;
+                    (outcome, None)
                 }
-                Ok(false) => Ok(Response::builder()
-                    .status(StatusCode::FORBIDDEN)
-                    .body(Body::from("Turnstile verification failed"))
-                    .unwrap()),
-                Err(e) => {
-                    eprintln!("Turnstile verification error: {}", e);
-                    Ok(Response::builder()
-                        .status(StatusCode::INTERNAL_SERVER_ERROR)
-                        .body(Body::from("Verification error"))
-                        .unwrap())
+            };
+        }
+        Err(e) => {
+            error!(error = %e, "Turnstile verification call failed");
+            if config.failure_policy == FailurePolicy::Open {
+                outcome.decision = Decision::Degraded;
+                return (outcome, None);
+            }
+            // No verdict came back for the token, so let the client retry it
+            if let Some(store) = &config.token_store {
+                store.remove(&token_hash).await;
+            }
+            let reason = match e {
+                VerifyError::EmptyResponse => RejectionReason::EmptyResponse,
+                VerifyError::Timeout => RejectionReason::Timeout,
+                VerifyError::Http(StatusCode::TOO_MANY_REQUESTS, _) => {
+                    RejectionReason::UpstreamRateLimited
+                }
+                _ => RejectionReason::ApiError,
+            };
+            return (outcome.reject(reason), Some(e));
+        }
+    };
+
+    let verification = TurnstileVerification::from_response(&response);
+    let reason = if !response.success {
+        let error_codes = response.error_codes.clone().unwrap_or_default();
+        if TurnstileErrorCode::parse_all(&error_codes)
+            .iter()
+            .any(|code| is_malformed_request(code, config))
+        {
+            error!(
+                error_codes = ?error_codes,
+                cf_ray = ?response.cf_ray,
+                "Turnstile siteverify rejected the request as malformed, check how it is called"
+            );
+            Some(RejectionReason::MalformedRequest(error_codes))
+        } else if !error_codes.is_empty()
+            && TurnstileErrorCode::parse_all(&error_codes)
+                .iter()
+                .all(|code| *code == TurnstileErrorCode::TimeoutOrDuplicate)
+        {
+            Some(RejectionReason::ExpiredOrUsed)
+        } else {
+            Some(RejectionReason::VerificationFailed(error_codes))
+        }
+    } else {
+        let fingerprint = outcome.request_fingerprint.as_deref();
+        let policy = validate::policy(config, expected_action, fingerprint);
+        policy.check(&verification).err()
+    };
+
+    outcome.verification = Some(verification);
+    outcome.cdata_suffix = validate::cdata_suffix(&response, config).map(str::to_owned);
+    outcome.challenge_ts = response.challenge_ts;
+    outcome.hostname_class = response
+        .hostname
+        .as_deref()
+        .map(|hostname| hostname::classify(hostname, config));
+    outcome.hostname = response.hostname;
+    outcome.action = response.action;
+    outcome.cdata = response.cdata;
+    outcome.error_codes = response.error_codes.unwrap_or_default();
+    outcome.cf_ray = response.cf_ray;
+    outcome.expires_in = outcome.remaining_validity(config.token_validity, SystemTime::now());
+
+    match reason {
+        Some(reason) => (outcome.reject(reason), None),
+        None => {
+            outcome.risk_level = Some(risk::assess(&outcome, config));
+            (outcome, None)
+        }
+    }
+}
+
+/// Warn about a verification call over the configured latency budget
+fn report_latency(timings: &VerifyTimings, config: &TurnstileConfig) {
+    let Some(threshold) = config.slow_verification_threshold else {
+        return;
+    };
+    if timings.total > threshold {
+        warn!(
+            elapsed_ms = timings.total.as_millis() as u64,
+            threshold_ms = threshold.as_millis() as u64,
+            "slow Turnstile verification"
+        );
+        if let Some(stats) = &config.stats {
+            stats.record_slow_verification();
+        }
+    }
+}
+
+/// Build the response for a rejected request, deferring to the configured handlers
+pub(crate) fn rejection(
+    reason: &RejectionReason,
+    error: Option<&VerifyError>,
+    config: &TurnstileConfig,
+) -> Response<Body> {
+    match (error, &config.upstream_error_response) {
+        (Some(error), Some(handler)) => handler(error),
+        _ => match &config.rejection_response {
+            Some(handler) => handler(reason),
+            None => rejection_response(reason.clone(), config),
+        },
+    }
+}
+
+/// Warn on the first request let through by [`Mode::Passthrough`] in this process
+fn warn_passthrough() {
+    static WARNED: Once = Once::new();
+    WARNED.call_once(|| {
+        warn!("Turnstile passthrough mode is on: requests are NOT verified, never use this in production");
+    });
+}
+
+/// Answer for a request the [`TurnstileRegistry`] has no config for
+fn misconfigured() -> Response<Body> {
+    build_rejection(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        "Turnstile is not configured for this request",
+    )
+}
+
+/// Build the response returned for a rejected request
+fn rejection_response(reason: RejectionReason, config: &TurnstileConfig) -> Response<Body> {
+    match reason {
+        RejectionReason::OriginNotAllowed => {
+            build_rejection(StatusCode::FORBIDDEN, "Origin not allowed")
+        }
+        RejectionReason::HttpsRequired => {
+            build_rejection(StatusCode::BAD_REQUEST, "HTTPS required")
+        }
+        RejectionReason::CountryBlocked => {
+            build_rejection(StatusCode::FORBIDDEN, "Country not allowed")
+        }
+        RejectionReason::MissingToken => {
+            build_rejection(config.missing_status, "Missing Turnstile token")
+        }
+        RejectionReason::MalformedToken => {
+            build_rejection(config.missing_status, "Malformed Turnstile token")
+        }
+        RejectionReason::PayloadTooLarge => {
+            build_rejection(StatusCode::PAYLOAD_TOO_LARGE, "Request body too large")
+        }
+        RejectionReason::Replay => {
+            build_rejection(config.failed_status, "Turnstile token already used")
+        }
+        RejectionReason::Stale => build_rejection(config.failed_status, "Turnstile token is stale"),
+        RejectionReason::ExpiredOrUsed => build_rejection(
+            config.failed_status,
+            "Turnstile token expired or was already used, please refresh and try again",
+        ),
+        RejectionReason::VerificationFailed(_) | RejectionReason::Overridden => {
+            build_rejection(config.failed_status, "Turnstile verification failed")
+        }
+        RejectionReason::ValidationFailed(failed_validators) => {
+            let names: Vec<_> = failed_validators
+                .iter()
+                .map(ValidatorKind::as_str)
+                .collect();
+            let body = format!("Turnstile validation failed: {}", names.join(", "));
+            let mut response = build_rejection(config.failed_status, body);
+            response
+                .extensions_mut()
+                .insert(ValidationFailure { failed_validators });
+            response
+        }
+        RejectionReason::MalformedRequest(_) => {
+            build_rejection(config.error_status, "Verification error")
+        }
+        RejectionReason::ApiError => retry_later(
+            build_rejection(config.error_status, "Verification error"),
+            config,
+        ),
+        RejectionReason::RateLimited
+        | RejectionReason::UpstreamRateLimited
+        | RejectionReason::RouteBudgetExhausted => retry_later(
+            build_rejection(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Verification capacity exceeded",
+            ),
+            config,
+        ),
+        RejectionReason::IdentityRateLimited => {
+            build_rejection(StatusCode::TOO_MANY_REQUESTS, "Too many requests")
+        }
+        RejectionReason::EmptyResponse => retry_later(
+            build_rejection(StatusCode::BAD_GATEWAY, "Verification error"),
+            config,
+        ),
+        RejectionReason::Timeout => retry_later(
+            build_rejection(StatusCode::GATEWAY_TIMEOUT, "Verification timed out"),
+            config,
+        ),
+    }
+}
+
+/// A response with `status` and `reason` as its body
+///
+/// Built directly rather than through [`Response::builder`], so it can't fail. Add headers
+/// with [`insert_header`].
+fn build_rejection(status: StatusCode, reason: impl Into<Body>) -> Response<Body> {
+    let mut response = Response::new(reason.into());
+    *response.status_mut() = status;
+    response
+}
+
+/// Add a `Retry-After` header telling clients when to come back
+fn retry_later(mut response: Response<Body>, config: &TurnstileConfig) -> Response<Body> {
+    insert_header(
+        &mut response,
+        RETRY_AFTER,
+        config.retry_after.as_secs().to_string(),
+    );
+    response
+}
+
+/// Set a header on a response, leaving it out if `value` isn't a valid header value
+///
+/// The response still goes out without the header instead of panicking on it, since header
+/// values may come from configuration.
+fn insert_header<V>(response: &mut Response<Body>, name: HeaderName, value: V)
+where
+    V: TryInto<HeaderValue>,
+{
+    if let Ok(value) = value.try_into() {
+        response.headers_mut().insert(name, value);
+    } else {
+        error!(header = %name, "invalid header value, leaving it out of the response");
+    }
+}
+
+/// Verify a token, consulting the result cache and coalescing identical verifications
+/// when configured
+///
+/// Timings are only reported when this request actually called Cloudflare.
+async fn verify(
+    token: &str,
+    token_hash: &str,
+    remote_ip: Option<String>,
+    idempotency_key: Option<&str>,
+    config: &TurnstileConfig,
+) -> Result<(VerifyResponse, Option<VerifyTimings>), VerifyError> {
+    if let Some(cached) = config
+        .result_cache
+        .as_ref()
+        .and_then(|cache| cache.get(token_hash))
+    {
+        return Ok((cached, None));
+    }
+
+    let (result, timings) = match &config.coalescer {
+        Some(coalescer) => {
+            let key = format!("{token_hash}:{}", remote_ip.as_deref().unwrap_or_default());
+            let mut timings = None;
+            let result = coalescer
+                .run(&key, config.coalesce_timeout, || async {
+                    let (result, own_timings) =
+                        verifier::verify_token(token, remote_ip, idempotency_key, config).await?;
+                    timings = Some(own_timings);
+                    Ok::<_, VerifyError>(result)
+                })
+                .await;
+            (result?, timings)
+        }
+        None => {
+            let (result, timings) =
+                verifier::verify_token(token, remote_ip, idempotency_key, config).await?;
+            (result, Some(timings))
+        }
+    };
+
+    if let Some(cache) = &config.result_cache {
+        cache.insert(token_hash.to_string(), &result, config.negative_caching);
+    }
+    Ok((result, timings))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        test_support::MockSiteverify, timestamp, RejectionReason, Turnstile, TurnstileConfig,
+        TurnstileLayer, TurnstileMiddleware, VerifiedTurnstile,
+    };
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        middleware::map_request,
+        response::IntoResponse,
+        routing::post,
+        Extension, Json, Router,
+    };
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        task::{Context, Poll},
+        time::{Duration, SystemTime},
+    };
+    use tower::ServiceExt;
+    use tower_layer::Layer;
+    use tower_service::Service;
+
+    #[tokio::test]
+    async fn test_expires_in() {
+        let solved_ago =
+            |secs| timestamp::format_rfc3339(SystemTime::now() - Duration::from_secs(secs));
+        for (challenge_ts, expected) in [
+            (Some(solved_ago(60)), true),
+            (Some(solved_ago(600)), false),
+            (None, false),
+        ] {
+            let mock = MockSiteverify::start(serde_json::json!({
+                "success": true,
+                "challenge_ts": challenge_ts,
+            }))
+            .await;
+            let config = TurnstileConfig::new("secret").with_verify_url(&mock.url);
+            let outcome = Turnstile::new(config).verify("token", None).await.unwrap();
+
+            match outcome.expires_in {
+                Some(expires_in) => {
+                    assert!(expected);
+                    assert!(expires_in > Duration::from_secs(230));
+                    assert!(expires_in <= Duration::from_secs(240));
                 }
+                None => assert!(!expected),
             }
-        })
+        }
+    }
+
+    async fn handler_calls(mock: &MockSiteverify) -> (StatusCode, usize) {
+        handler_calls_with(TurnstileConfig::new("test-secret").with_verify_url(&mock.url)).await
+    }
+
+    async fn handler_calls_with(config: TurnstileConfig) -> (StatusCode, usize) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let app = Router::new()
+            .route(
+                "/submit",
+                post(move || async move {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    "OK"
+                }),
+            )
+            .layer(TurnstileLayer::new(config));
+
+        let response = app
+            .oneshot(
+                Request::post("/submit")
+                    .header("CF-Turnstile-Token", "token")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        (response.status(), calls.load(Ordering::SeqCst))
+    }
+
+    /// Inner service that only accepts calls on an instance that was polled ready, like a
+    /// buffer holding a reserved slot
+    struct ReadinessTracking {
+        ready: bool,
+    }
+
+    impl Clone for ReadinessTracking {
+        fn clone(&self) -> Self {
+            Self { ready: false }
+        }
+    }
+
+    impl Service<Request<Body>> for ReadinessTracking {
+        type Response = axum::response::Response;
+        type Error = std::convert::Infallible;
+        type Future = std::future::Ready<Result<Self::Response, Self::Error>>;
+
+        fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            self.ready = true;
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _req: Request<Body>) -> Self::Future {
+            assert!(self.ready, "called before poll_ready");
+            self.ready = false;
+            std::future::ready(Ok(axum::response::Response::new(Body::empty())))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_other_body_types() {
+        let mock = MockSiteverify::success().await;
+        let config = TurnstileConfig::new("secret").with_verify_url(&mock.url);
+        // Answers with a body type other than axum's
+        let inner = tower::service_fn(|req: Request<Body>| async move {
+            let body = axum::body::to_bytes(req.into_body(), usize::MAX).await?;
+            let body = http_body_util::Full::new(body);
+            Ok::<_, axum::Error>(axum::http::Response::new(body))
+        });
+        let service = TurnstileLayer::new(config).layer(inner);
+
+        for (token, expected) in [
+            (Some("token"), StatusCode::OK),
+            (None, StatusCode::BAD_REQUEST),
+        ] {
+            let mut request = Request::post("/submit");
+            if let Some(token) = token {
+                request = request.header("CF-Turnstile-Token", token);
+            }
+            let request: Request<String> = request.body("hello".to_string()).unwrap();
+            let response = service.clone().oneshot(request).await.unwrap();
+            assert_eq!(response.status(), expected);
+            if expected == StatusCode::OK {
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                assert_eq!(body, "hello");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_readied_inner_service_is_called() {
+        let mock = MockSiteverify::success().await;
+        let config = TurnstileConfig::new("test-secret").with_verify_url(&mock.url);
+        let mut middleware = TurnstileMiddleware::new(ReadinessTracking { ready: false }, config);
+
+        for _ in 0..2 {
+            let request = Request::post("/submit")
+                .header("CF-Turnstile-Token", "token")
+                .body(Body::empty())
+                .unwrap();
+            let ready = ServiceExt::<Request<Body>>::ready(&mut middleware).await;
+            let response = ready.unwrap().call(request).await;
+            assert_eq!(response.unwrap().status(), StatusCode::OK);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_handler_called_exactly_once_on_success() {
+        let mock = MockSiteverify::success().await;
+        assert_eq!(handler_calls(&mock).await, (StatusCode::OK, 1));
+    }
+
+    #[tokio::test]
+    async fn test_handler_called_exactly_once_when_verification_retries() {
+        // The first call times out in transit, the second succeeds
+        let mock = MockSiteverify::success().await;
+        mock.delay_with(|index| match index {
+            0 => Duration::from_secs(5),
+            _ => Duration::ZERO,
+        });
+        let config = TurnstileConfig::new("test-secret")
+            .with_verify_url(&mock.url)
+            .with_timeout(Duration::from_millis(200))
+            .with_retries(2);
+        assert_eq!(handler_calls_with(config).await, (StatusCode::OK, 1));
+        assert_eq!(mock.requests().len(), 2);
+
+        // Cloudflare fails on its side first, then verifies the token
+        let answered = AtomicUsize::new(0);
+        let mock =
+            MockSiteverify::respond_with(move |_| match answered.fetch_add(1, Ordering::SeqCst) {
+                0 => serde_json::json!({ "success": false, "error-codes": ["internal-error"] }),
+                _ => serde_json::json!({ "success": true }),
+            })
+            .await;
+        assert_eq!(handler_calls(&mock).await, (StatusCode::OK, 1));
+        assert_eq!(mock.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_internal_error_retry_reuses_idempotency_key() {
+        let answered = AtomicUsize::new(0);
+        let mock =
+            MockSiteverify::respond_with(move |_| match answered.fetch_add(1, Ordering::SeqCst) {
+                0 => serde_json::json!({ "success": false, "error-codes": ["internal-error"] }),
+                _ => serde_json::json!({ "success": true }),
+            })
+            .await;
+
+        // Retrying after `internal-error` is on by default, so the key is too
+        assert_eq!(handler_calls(&mock).await, (StatusCode::OK, 1));
+        let keys: Vec<_> = mock
+            .requests()
+            .iter()
+            .map(|r| r.json()["idempotency_key"].clone())
+            .collect();
+        assert_eq!(keys.len(), 2);
+        assert!(keys[0].is_string(), "{keys:?}");
+        assert_eq!(keys[0], keys[1]);
+    }
+
+    #[tokio::test]
+    async fn test_context_enricher_updates_existing_context() {
+        #[derive(Clone, Default)]
+        struct RequestContext {
+            signals: Vec<String>,
+        }
+
+        let mock = MockSiteverify::start(serde_json::json!({
+            "success": true,
+            "action": "checkout",
+        }))
+        .await;
+        let config = TurnstileConfig::new("test-secret")
+            .with_verify_url(&mock.url)
+            .with_context_enricher(|extensions, outcome| {
+                let context = extensions.get_mut::<RequestContext>().unwrap();
+                context.signals.push(format!(
+                    "turnstile:{}",
+                    outcome.action.as_deref().unwrap_or_default()
+                ));
+            });
+
+        let app = Router::new()
+            .route(
+                "/submit",
+                post(|Extension(context): Extension<RequestContext>| async move {
+                    context.signals.join(",")
+                }),
+            )
+            .layer(TurnstileLayer::new(config))
+            .layer(map_request(|mut req: Request<Body>| async move {
+                let context = RequestContext {
+                    signals: vec!["auth:anonymous".to_string()],
+                };
+                req.extensions_mut().insert(context);
+                req
+            }));
+
+        let response = app
+            .oneshot(
+                Request::post("/submit")
+                    .header("CF-Turnstile-Token", "token")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, "auth:anonymous,turnstile:checkout");
+    }
+
+    #[tokio::test]
+    async fn test_e2e_bypass_token() {
+        let mock = MockSiteverify::start(serde_json::json!({ "success": false })).await;
+        let app = Router::new()
+            .route(
+                "/submit",
+                post(|_verified: VerifiedTurnstile| async { "OK" }),
+            )
+            .layer(TurnstileLayer::new(
+                TurnstileConfig::new("test-secret")
+                    .with_verify_url(&mock.url)
+                    .with_e2e_bypass_token("e2e-bypass-secret"),
+            ));
+
+        for (token, expected) in [
+            ("e2e-bypass-secret", StatusCode::OK),
+            ("e2e-bypass-secreT", StatusCode::FORBIDDEN),
+            ("e2e-bypass", StatusCode::FORBIDDEN),
+        ] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::post("/submit")
+                        .header("CF-Turnstile-Token", token)
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), expected, "token {token}");
+        }
+
+        // Only the non-matching tokens reached Cloudflare
+        assert_eq!(mock.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_alternative_guard_accepts_valid_csrf() {
+        let mock = MockSiteverify::success().await;
+        let app = Router::new()
+            .route("/form", post(|_verified: VerifiedTurnstile| async { "OK" }))
+            .layer(TurnstileLayer::new(
+                TurnstileConfig::new("test-secret")
+                    .with_verify_url(&mock.url)
+                    .with_alternative_guard(|parts| {
+                        parts
+                            .headers
+                            .get("X-CSRF-Token")
+                            .is_some_and(|v| v == "valid")
+                    }),
+            ));
+
+        for (csrf, expected) in [
+            (Some("valid"), StatusCode::OK),
+            (Some("forged"), StatusCode::BAD_REQUEST),
+            (None, StatusCode::BAD_REQUEST),
+        ] {
+            let mut request = Request::post("/form");
+            if let Some(csrf) = csrf {
+                request = request.header("X-CSRF-Token", csrf);
+            }
+            let response = app
+                .clone()
+                .oneshot(request.body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(response.status(), expected, "csrf {csrf:?}");
+        }
+
+        assert!(mock.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_handler_not_called_on_failure() {
+        let mock = MockSiteverify::start(serde_json::json!({ "success": false })).await;
+        assert_eq!(handler_calls(&mock).await, (StatusCode::FORBIDDEN, 0));
+    }
+
+    #[tokio::test]
+    async fn test_timeout_or_duplicate_is_expired_or_used() {
+        let mock = MockSiteverify::start(serde_json::json!({
+            "success": false,
+            "error-codes": ["timeout-or-duplicate"],
+        }))
+        .await;
+        let send = |config: TurnstileConfig| {
+            let response = Router::new()
+                .route("/submit", post(|| async { "OK" }))
+                .layer(TurnstileLayer::new(config.with_verify_url(&mock.url)))
+                .oneshot(
+                    Request::post("/submit")
+                        .header("CF-Turnstile-Token", "token")
+                        .body(Body::empty())
+                        .unwrap(),
+                );
+            async move {
+                let response = response.await.unwrap();
+                let status = response.status();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                (status, String::from_utf8(body.to_vec()).unwrap())
+            }
+        };
+
+        let (status, body) = send(TurnstileConfig::new("secret")).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert!(body.contains("please refresh"), "{body}");
+
+        let friendly = TurnstileConfig::new("secret").with_rejection(|reason| match reason {
+            RejectionReason::ExpiredOrUsed => {
+                (StatusCode::CONFLICT, "Please refresh and try again").into_response()
+            }
+            _ => StatusCode::FORBIDDEN.into_response(),
+        });
+        assert_eq!(
+            send(friendly).await,
+            (
+                StatusCode::CONFLICT,
+                "Please refresh and try again".to_string()
+            )
+        );
+
+        let outcome =
+            crate::Turnstile::new(TurnstileConfig::new("secret").with_verify_url(&mock.url))
+                .verify("token", None)
+                .await
+                .unwrap();
+        assert_eq!(
+            outcome.decision,
+            crate::Decision::Rejected(RejectionReason::ExpiredOrUsed)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_malformed_siteverify_request_is_server_error() {
+        let mock = MockSiteverify::start(serde_json::json!({
+            "success": false,
+            "error-codes": ["missing-input-response"],
+        }))
+        .await;
+        assert_eq!(
+            handler_calls(&mock).await,
+            (StatusCode::INTERNAL_SERVER_ERROR, 0)
+        );
+        // Retrying can't fix a broken integration
+        let response = Router::new()
+            .route("/submit", post(|| async { "OK" }))
+            .layer(TurnstileLayer::new(
+                TurnstileConfig::new("test-secret").with_verify_url(&mock.url),
+            ))
+            .oneshot(
+                Request::post("/submit")
+                    .header("CF-Turnstile-Token", "token")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert!(!response.headers().contains_key("Retry-After"));
+
+        let outcome =
+            crate::Turnstile::new(TurnstileConfig::new("test-secret").with_verify_url(&mock.url))
+                .verify("token", None)
+                .await
+                .unwrap();
+        assert_eq!(
+            outcome.decision,
+            crate::Decision::Rejected(crate::RejectionReason::MalformedRequest(vec![
+                "missing-input-response".to_string()
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_invalid_header_value_is_left_out() {
+        let mut response =
+            super::build_rejection(StatusCode::FORBIDDEN, "Turnstile verification failed");
+        super::insert_header(
+            &mut response,
+            axum::http::header::RETRY_AFTER,
+            "30\r\nX-Injected: yes",
+        );
+        super::insert_header(&mut response, axum::http::header::CACHE_CONTROL, "no-store");
+
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert!(!response.headers().contains_key("Retry-After"));
+        assert!(!response.headers().contains_key("X-Injected"));
+        assert_eq!(response.headers()["Cache-Control"], "no-store");
+    }
+
+    #[tokio::test]
+    async fn test_upstream_failure_sends_retry_after() {
+        let mock = MockSiteverify::respond_raw(|_| StatusCode::BAD_GATEWAY.into_response()).await;
+        let app = |config: TurnstileConfig| {
+            Router::new()
+                .route("/submit", post(|| async { "OK" }))
+                .layer(TurnstileLayer::new(config.with_verify_url(&mock.url)))
+        };
+        let request = || {
+            Request::post("/submit")
+                .header("CF-Turnstile-Token", "token")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = app(TurnstileConfig::new("secret"))
+            .oneshot(request())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(response.headers()["Retry-After"], "5");
+
+        let response =
+            app(TurnstileConfig::new("secret").with_retry_after(Duration::from_secs(30)))
+                .oneshot(request())
+                .await
+                .unwrap();
+        let retry_after: u64 = response.headers()["Retry-After"]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert_eq!(retry_after, 30);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], b"Verification error");
+    }
+
+    #[tokio::test]
+    async fn test_upstream_rate_limit_is_service_unavailable() {
+        let mock =
+            MockSiteverify::respond_raw(|_| StatusCode::TOO_MANY_REQUESTS.into_response()).await;
+        let response = Router::new()
+            .route("/submit", post(|| async { "OK" }))
+            .layer(TurnstileLayer::new(
+                TurnstileConfig::new("secret").with_verify_url(&mock.url),
+            ))
+            .oneshot(
+                Request::post("/submit")
+                    .header("CF-Turnstile-Token", "token")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()["Retry-After"], "5");
+    }
+
+    #[tokio::test]
+    async fn test_configured_rejection_statuses() {
+        let failing = MockSiteverify::start(serde_json::json!({ "success": false })).await;
+        let erroring =
+            MockSiteverify::respond_raw(|_| StatusCode::BAD_GATEWAY.into_response()).await;
+        let send = |verify_url: &str, token: Option<&str>| {
+            let config = TurnstileConfig::new("secret")
+                .with_verify_url(verify_url)
+                .with_missing_status(StatusCode::UNPROCESSABLE_ENTITY)
+                .with_failed_status(StatusCode::UNAUTHORIZED)
+                .with_error_status(StatusCode::SERVICE_UNAVAILABLE);
+            let mut request = Request::post("/submit");
+            if let Some(token) = token {
+                request = request.header("CF-Turnstile-Token", token);
+            }
+            let response = Router::new()
+                .route("/submit", post(|| async { "OK" }))
+                .layer(TurnstileLayer::new(config))
+                .oneshot(request.body(Body::empty()).unwrap());
+            async move { response.await.unwrap().status() }
+        };
+
+        assert_eq!(
+            send(&failing.url, None).await,
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+        assert_eq!(
+            send(&failing.url, Some("token")).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            send(&erroring.url, Some("token")).await,
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "client or server error")]
+    fn test_success_status_for_rejections_panics() {
+        let _ = TurnstileConfig::new("secret").with_failed_status(StatusCode::OK);
+    }
+
+    /// Counts handler runs that started, finished and were dropped before finishing
+    #[derive(Clone, Default)]
+    struct HandlerRuns {
+        started: Arc<AtomicUsize>,
+        finished: Arc<AtomicUsize>,
+        dropped: Arc<AtomicUsize>,
+    }
+
+    struct DropGuard(Arc<AtomicUsize>);
+
+    impl Drop for DropGuard {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn speculative_app(mock: &MockSiteverify, runs: &HandlerRuns, work: Duration) -> Router {
+        let runs = runs.clone();
+        let handler = move || {
+            let runs = runs.clone();
+            async move {
+                runs.started.fetch_add(1, Ordering::SeqCst);
+                let guard = DropGuard(runs.dropped.clone());
+                tokio::time::sleep(work).await;
+                std::mem::forget(guard);
+                runs.finished.fetch_add(1, Ordering::SeqCst);
+                "expensive"
+            }
+        };
+        Router::new()
+            .route("/report", axum::routing::get(handler.clone()).post(handler))
+            .layer(TurnstileLayer::new(
+                TurnstileConfig::new("secret")
+                    .with_verify_url(&mock.url)
+                    .with_speculative(true),
+            ))
+    }
+
+    fn report(method: &str) -> Request<Body> {
+        Request::builder()
+            .method(method)
+            .uri("/report")
+            .header("CF-Turnstile-Token", "token")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_speculative_handler_dropped_on_rejection() {
+        let mock = MockSiteverify::start(serde_json::json!({ "success": false })).await;
+        let runs = HandlerRuns::default();
+        let app = speculative_app(&mock, &runs, Duration::from_secs(30));
+
+        let response = tokio::time::timeout(Duration::from_secs(5), app.oneshot(report("GET")))
+            .await
+            .expect("rejected without waiting for the handler")
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(runs.started.load(Ordering::SeqCst), 1);
+        assert_eq!(runs.dropped.load(Ordering::SeqCst), 1);
+        assert_eq!(runs.finished.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_speculative_handler_overlaps_verification() {
+        let mock = MockSiteverify::success().await;
+        mock.delay_with(|_| Duration::from_millis(300));
+        let runs = HandlerRuns::default();
+        let app = speculative_app(&mock, &runs, Duration::from_millis(300));
+
+        let started = std::time::Instant::now();
+        let response = app.oneshot(report("GET")).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(started.elapsed() < Duration::from_millis(550));
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], b"expensive");
+        assert_eq!(runs.finished.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_speculative_inner_error_held_back_on_rejection() {
+        let mock = MockSiteverify::start(serde_json::json!({ "success": false })).await;
+        mock.delay_with(|_| Duration::from_millis(100));
+        let failing = tower::service_fn(|_: Request<Body>| async {
+            Err::<axum::response::Response, _>("handler failed")
+        });
+        let service = TurnstileMiddleware::new(
+            failing,
+            TurnstileConfig::new("secret")
+                .with_verify_url(&mock.url)
+                .with_speculative(true),
+        );
+
+        let response = service.oneshot(report("GET")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn test_speculative_handler_has_no_verified_turnstile() {
+        let mock = MockSiteverify::success().await;
+        let app = Router::new()
+            .route(
+                "/strict",
+                axum::routing::get(|_: VerifiedTurnstile| async { "verified" }),
+            )
+            .route(
+                "/lenient",
+                axum::routing::get(|verified: Option<VerifiedTurnstile>| async move {
+                    match verified {
+                        Some(_) => "verified",
+                        None => "not yet verified",
+                    }
+                }),
+            )
+            .layer(TurnstileLayer::new(
+                TurnstileConfig::new("secret")
+                    .with_verify_url(&mock.url)
+                    .with_speculative(true),
+            ));
+        let send = |uri: &'static str| {
+            let request = Request::get(uri)
+                .header("CF-Turnstile-Token", "token")
+                .body(Body::empty())
+                .unwrap();
+            let response = app.clone().oneshot(request);
+            async move {
+                let response = response.await.unwrap();
+                let status = response.status();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                (status, String::from_utf8(body.to_vec()).unwrap())
+            }
+        };
+
+        // The handler starts before the token is verified, so the extractor can't succeed
+        assert_eq!(send("/strict").await.0, StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            send("/lenient").await,
+            (StatusCode::OK, "not yet verified".to_string())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_speculative_skips_unsafe_methods() {
+        let mock = MockSiteverify::start(serde_json::json!({ "success": false })).await;
+        let runs = HandlerRuns::default();
+        let app = speculative_app(&mock, &runs, Duration::ZERO);
+
+        let response = app.oneshot(report("POST")).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(runs.started.load(Ordering::SeqCst), 0);
+    }
+
+    #[tokio::test]
+    async fn test_custom_rejection_response() {
+        let mock = MockSiteverify::start(serde_json::json!({
+            "success": false,
+            "error-codes": ["invalid-input-response"],
+        }))
+        .await;
+        let app = Router::new()
+            .route("/submit", post(|| async { "OK" }))
+            .layer(TurnstileLayer::new(
+                TurnstileConfig::new("test-secret")
+                    .with_verify_url(&mock.url)
+                    .with_rejection(|reason| {
+                        let (status, codes) = match reason {
+                            RejectionReason::VerificationFailed(codes) => {
+                                (StatusCode::UNAUTHORIZED, codes.clone())
+                            }
+                            _ => (StatusCode::UNPROCESSABLE_ENTITY, vec![]),
+                        };
+                        let body = serde_json::json!({ "error": "captcha", "codes": codes });
+                        (status, Json(body)).into_response()
+                    }),
+            ));
+
+        for (token, expected_status, expected_body) in [
+            (
+                Some("token"),
+                StatusCode::UNAUTHORIZED,
+                serde_json::json!({ "error": "captcha", "codes": ["invalid-input-response"] }),
+            ),
+            (
+                None,
+                StatusCode::UNPROCESSABLE_ENTITY,
+                serde_json::json!({ "error": "captcha", "codes": [] }),
+            ),
+        ] {
+            let mut request = Request::post("/submit");
+            if let Some(token) = token {
+                request = request.header("CF-Turnstile-Token", token);
+            }
+            let response = app
+                .clone()
+                .oneshot(request.body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(response.status(), expected_status);
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(body, expected_body);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_error_codes_header() {
+        let mock = MockSiteverify::start(serde_json::json!({
+            "success": false,
+            "error-codes": [
+                "timeout-or-duplicate",
+                "invalid-input-response",
+                "evil\r\nSet-Cookie: a=b",
+            ],
+        }))
+        .await;
+        let url = &mock.url;
+        let send = |config: TurnstileConfig| async move {
+            Router::new()
+                .route("/submit", post(|| async { "OK" }))
+                .layer(TurnstileLayer::new(config.with_verify_url(url)))
+                .oneshot(
+                    Request::post("/submit")
+                        .header("CF-Turnstile-Token", "token")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap()
+        };
+
+        let response = send(TurnstileConfig::new("secret")).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert!(!response.headers().contains_key(crate::ERROR_CODES_HEADER));
+
+        let response = send(TurnstileConfig::new("secret").with_expose_error_codes(true)).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(
+            response.headers()[crate::ERROR_CODES_HEADER],
+            "timeout-or-duplicate,invalid-input-response,evilSet-Cookieab"
+        );
+        assert!(!response.headers().contains_key("Set-Cookie"));
+    }
+
+    #[tokio::test]
+    async fn test_verification_id_in_extension_and_header() {
+        let mock = MockSiteverify::success().await;
+        let app = Router::new()
+            .route(
+                "/submit",
+                post(
+                    |Extension(outcome): Extension<crate::TurnstileOutcome>| async move {
+                        outcome.verification_id
+                    },
+                ),
+            )
+            .layer(TurnstileLayer::new(
+                TurnstileConfig::new("test-secret")
+                    .with_verify_url(&mock.url)
+                    .with_verification_id_header(true),
+            ));
+
+        let mut ids = Vec::new();
+        for _ in 0..2 {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::post("/submit")
+                        .header("CF-Turnstile-Token", "token")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            let header = response.headers()[crate::VERIFICATION_ID_HEADER]
+                .to_str()
+                .unwrap()
+                .to_string();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert_eq!(body, header);
+            ids.push(header);
+        }
+
+        assert_ne!(ids[0], ids[1]);
+        let groups: Vec<_> = ids[0].split('-').map(str::len).collect();
+        assert_eq!(groups, [8, 4, 4, 4, 12]);
+        assert_eq!(&ids[0][14..15], "4");
+
+        // Rejections carry an id too, so they can be traced from the client
+        let response = app
+            .oneshot(Request::post("/submit").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert!(response
+            .headers()
+            .contains_key(crate::VERIFICATION_ID_HEADER));
     }
 }
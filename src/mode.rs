@@ -0,0 +1,187 @@
+/// How the middleware acts on its decisions
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Mode {
+    /// Reject requests that fail the checks (default)
+    #[default]
+    Enforce,
+    /// Run every check but let all requests through, so outcomes can be observed before
+    /// enforcing
+    ///
+    /// Failing requests reach the inner service without a
+    /// [`VerifiedTurnstile`](crate::VerifiedTurnstile), with a
+    /// [`Decision::Monitored`](crate::Decision::Monitored) outcome that handlers can extract
+    /// as a [`TurnstileOutcome`](crate::TurnstileOutcome).
+    Monitor,
+    /// Skip the checks entirely and treat every request as verified, for local development
+    /// and tests without access to Cloudflare
+    ///
+    /// Requests reach the inner service with or without a token, and Cloudflare is never
+    /// called. Handlers get a [`VerifiedTurnstile`](crate::VerifiedTurnstile) marked
+    /// [`bypassed`](crate::VerifiedTurnstile::bypassed). The first bypassed request logs a
+    /// warning, so this can't go unnoticed in production.
+    Passthrough,
+}
+
+/// Deployment environment for [`TurnstileConfig::preset`](crate::TurnstileConfig::preset)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Environment {
+    /// [`Mode::Passthrough`] with Cloudflare's always-passing test secret
+    Development,
+    /// [`Mode::Monitor`] with Cloudflare's always-passing test secret
+    Staging,
+    /// [`Mode::Enforce`] with your secret; refuses [`Mode::Passthrough`]
+    Production,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Decision, RejectionReason, TurnstileConfig, TurnstileLayer, TurnstileOutcome,
+        VerifiedTurnstile,
+    };
+    use axum::{body::Body, extract::Request, http::StatusCode, routing::post, Router};
+    use tower::ServiceExt;
+
+    #[test]
+    fn test_presets() {
+        let dev = TurnstileConfig::preset(Environment::Development, "prod-secret");
+        assert_eq!(dev.mode(), Mode::Passthrough);
+        assert_eq!(dev.secret(), crate::TEST_SECRET_PASS);
+
+        let staging = TurnstileConfig::preset(Environment::Staging, "prod-secret");
+        assert_eq!(staging.mode(), Mode::Monitor);
+        assert_eq!(staging.secret(), crate::TEST_SECRET_PASS);
+
+        let prod = TurnstileConfig::preset(Environment::Production, "prod-secret");
+        assert_eq!(prod.mode(), Mode::Enforce);
+        assert_eq!(prod.secret(), "prod-secret");
+
+        // Presets stay overridable
+        let prod = prod.with_mode(Mode::Monitor);
+        assert_eq!(prod.mode(), Mode::Monitor);
+    }
+
+    #[test]
+    #[should_panic(expected = "passthrough")]
+    fn test_production_refuses_passthrough() {
+        let _ = TurnstileConfig::preset(Environment::Production, "prod-secret")
+            .with_mode(Mode::Passthrough);
+    }
+
+    async fn send(config: TurnstileConfig) -> (StatusCode, String) {
+        let app = Router::new()
+            .route(
+                "/submit",
+                post(|req: Request| async move {
+                    let verified = req.extensions().get::<VerifiedTurnstile>().is_some();
+                    let outcome = req.extensions().get::<TurnstileOutcome>().cloned();
+                    format!(
+                        "verified={verified} outcome={:?}",
+                        outcome.map(|o| o.decision)
+                    )
+                }),
+            )
+            .layer(TurnstileLayer::new(config));
+
+        let response = app
+            .oneshot(Request::post("/submit").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_monitor_lets_failures_through() {
+        let config = TurnstileConfig::new("secret").with_mode(Mode::Monitor);
+
+        assert_eq!(
+            send(config).await,
+            (
+                StatusCode::OK,
+                "verified=false outcome=Some(Monitored(MissingToken))".to_string()
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_monitored_outcome_is_extractable() {
+        let app = Router::new()
+            .route(
+                "/submit",
+                post(|outcome: TurnstileOutcome| async move {
+                    match outcome.decision {
+                        Decision::Verified => "verified",
+                        Decision::Monitored(RejectionReason::MissingToken) => "missing",
+                        Decision::Monitored(_) => "failed",
+                        _ => "other",
+                    }
+                }),
+            )
+            .layer(TurnstileLayer::new(
+                TurnstileConfig::new("secret").with_mode(Mode::Monitor),
+            ));
+
+        let response = app
+            .oneshot(Request::post("/submit").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, "missing");
+
+        // Without the middleware there's no outcome to extract
+        let response = Router::new()
+            .route("/submit", post(|_: TurnstileOutcome| async { "OK" }))
+            .oneshot(Request::post("/submit").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[tokio::test]
+    async fn test_passthrough_marks_tokenless_requests_bypassed() {
+        let app = Router::new()
+            .route(
+                "/submit",
+                post(|verified: VerifiedTurnstile| async move {
+                    format!("bypassed={}", verified.bypassed)
+                }),
+            )
+            .layer(TurnstileLayer::new(
+                // Unreachable endpoint: passthrough must not call it
+                TurnstileConfig::new("secret")
+                    .with_verify_url("http://127.0.0.1:9/siteverify")
+                    .with_mode(Mode::Passthrough),
+            ));
+
+        let response = app
+            .oneshot(Request::post("/submit").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, "bypassed=true");
+    }
+
+    #[tokio::test]
+    async fn test_passthrough_skips_checks() {
+        let config = TurnstileConfig::new("secret").with_mode(Mode::Passthrough);
+
+        assert_eq!(
+            send(config).await,
+            (
+                StatusCode::OK,
+                "verified=true outcome=Some(Passthrough)".to_string()
+            )
+        );
+    }
+}
@@ -0,0 +1,74 @@
+/// Marker telling the middleware the connection presented a verified client certificate
+///
+/// This crate doesn't terminate TLS. With
+/// [`with_skip_on_client_cert`](crate::TurnstileConfig::with_skip_on_client_cert) enabled,
+/// whatever terminates TLS for your app must insert this into the request extensions, and
+/// only after it has verified the client's certificate chain against your CA, e.g. a
+/// rustls acceptor using `WebPkiClientVerifier` that adds it per connection. Don't insert
+/// it based on request headers unless they come from a proxy that strips client-supplied
+/// values.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct VerifiedClientCert;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_support::MockSiteverify, Decision, DecisionOverride, TurnstileConfig, TurnstileLayer,
+        VerifiedTurnstile,
+    };
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        middleware::map_request,
+        routing::post,
+        Router,
+    };
+    use std::sync::{Arc, Mutex};
+    use tower::ServiceExt;
+
+    async fn send(skip_on_client_cert: bool, with_cert: bool) -> (StatusCode, Option<Decision>) {
+        let mock = MockSiteverify::start(serde_json::json!({ "success": false })).await;
+        let seen = Arc::new(Mutex::new(None));
+        let seen_by_override = seen.clone();
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&mock.url)
+            .with_skip_on_client_cert(skip_on_client_cert)
+            .with_decision_override(move |outcome| {
+                *seen_by_override.lock().unwrap() = Some(outcome.decision.clone());
+                DecisionOverride::Keep
+            });
+
+        // Stands in for the TLS layer
+        let app = Router::new()
+            .route("/internal", post(|_: VerifiedTurnstile| async { "OK" }))
+            .layer(TurnstileLayer::new(config))
+            .layer(map_request(move |mut req: Request<Body>| async move {
+                if with_cert {
+                    req.extensions_mut().insert(VerifiedClientCert);
+                }
+                req
+            }));
+
+        let response = app
+            .oneshot(Request::post("/internal").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let decision = seen.lock().unwrap().clone();
+        (response.status(), decision)
+    }
+
+    #[tokio::test]
+    async fn test_client_cert_bypasses_verification() {
+        assert_eq!(
+            send(true, true).await,
+            (StatusCode::OK, Some(Decision::BypassedByMtls))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_client_cert_ignored_unless_enabled() {
+        assert_eq!(send(false, true).await.0, StatusCode::BAD_REQUEST);
+        assert_eq!(send(true, false).await.0, StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,187 @@
+use crate::TurnstileConfig;
+use axum::http::Extensions;
+use std::borrow::Cow;
+
+/// Per-route changes to the layer's config, read from the request's extensions
+///
+/// Lets one [`TurnstileLayer`](crate::TurnstileLayer) enforce different actions on different
+/// routes. Insert it from a layer that runs before the Turnstile layer, such as one added
+/// after it with [`Router::layer`](axum::Router::layer):
+///
+/// ```rust
+/// use axum::{extract::Request, middleware::map_request, routing::post, Router};
+/// use axum_turnstile::{TurnstileLayer, TurnstileOverride};
+///
+/// let app: Router = Router::new()
+///     .route("/login", post(|| async { "logged in" }))
+///     .route("/signup", post(|| async { "signed up" }))
+///     .layer(TurnstileLayer::from_secret("your-secret-key"))
+///     .layer(map_request(|mut req: Request| async move {
+///         let action = req.uri().path().trim_start_matches('/').to_string();
+///         req.extensions_mut()
+///             .insert(TurnstileOverride::new().with_expected_action(action));
+///         req
+///     }));
+/// ```
+///
+/// Each field that is set replaces the config's value for the request; unset fields keep
+/// it. Overrides apply after the [environment](crate::TurnstileConfig::with_env_switch)
+/// and [sitekey](crate::TurnstileConfig::with_sitekey_routing) have picked the config.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct TurnstileOverride {
+    /// Replaces [`TurnstileConfig::expected_action`]
+    pub expected_action: Option<String>,
+    /// Replaces [`TurnstileConfig::expected_hostnames`]
+    pub expected_hostnames: Option<Vec<String>>,
+    /// Replaces [`TurnstileConfig::expected_cdata`], e.g. to bind the token to the session
+    /// the request belongs to
+    pub expected_cdata: Option<String>,
+}
+
+impl TurnstileOverride {
+    /// An override that changes nothing
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Require this action instead of the config's
+    pub fn with_expected_action(mut self, action: impl Into<String>) -> Self {
+        self.expected_action = Some(action.into());
+        self
+    }
+
+    /// Accept these hostnames instead of the config's
+    pub fn with_expected_hostnames(mut self, hostnames: Vec<String>) -> Self {
+        self.expected_hostnames = Some(hostnames);
+        self
+    }
+
+    /// Require Cloudflare to report this `cdata` for the token
+    pub fn with_expected_cdata(mut self, cdata: impl Into<String>) -> Self {
+        self.expected_cdata = Some(cdata.into());
+        self
+    }
+}
+
+/// `config` with the request's [`TurnstileOverride`] applied, if it has one
+pub(crate) fn apply<'a>(
+    extensions: &Extensions,
+    config: &'a TurnstileConfig,
+) -> Cow<'a, TurnstileConfig> {
+    let Some(route) = extensions.get::<TurnstileOverride>() else {
+        return Cow::Borrowed(config);
+    };
+    let mut config = config.clone();
+    if let Some(action) = &route.expected_action {
+        config.expected_action = Some(action.clone());
+    }
+    if let Some(hostnames) = &route.expected_hostnames {
+        config.expected_hostnames = hostnames.clone();
+    }
+    if let Some(cdata) = &route.expected_cdata {
+        config.expected_cdata = Some(cdata.clone());
+    }
+    Cow::Owned(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_support::MockSiteverify, TurnstileLayer, VerifiedTurnstile};
+    use axum::{
+        body::Body, extract::Request, http::StatusCode, middleware::map_request, routing::post,
+        Router,
+    };
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_routes_sharing_a_layer_enforce_their_own_action() {
+        // The token names the action it was solved for
+        let mock = MockSiteverify::respond_with(
+            |request| serde_json::json!({ "success": true, "action": request.json()["response"] }),
+        )
+        .await;
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&mock.url)
+            .with_expected_action("default");
+        let app = Router::new()
+            .route("/login", post(|| async { "OK" }))
+            .route("/signup", post(|| async { "OK" }))
+            .route("/contact", post(|| async { "OK" }))
+            .layer(TurnstileLayer::new(config))
+            .layer(map_request(|mut req: Request| async move {
+                let action = match req.uri().path() {
+                    "/login" => "login",
+                    "/signup" => "signup",
+                    _ => return req,
+                };
+                req.extensions_mut()
+                    .insert(TurnstileOverride::new().with_expected_action(action));
+                req
+            }));
+
+        for (path, token, expected) in [
+            ("/login", "login", StatusCode::OK),
+            ("/login", "signup", StatusCode::FORBIDDEN),
+            ("/signup", "signup", StatusCode::OK),
+            ("/signup", "login", StatusCode::FORBIDDEN),
+            ("/contact", "default", StatusCode::OK),
+            ("/contact", "login", StatusCode::FORBIDDEN),
+        ] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::post(path)
+                        .header("CF-Turnstile-Token", token)
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), expected, "{token} token on {path}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_cdata_bound_to_session() {
+        // The token carries the session it was solved for
+        let mock = MockSiteverify::respond_with(
+            |request| serde_json::json!({ "success": true, "cdata": request.json()["response"] }),
+        )
+        .await;
+        let app = Router::new()
+            .route(
+                "/submit",
+                post(|verified: VerifiedTurnstile| async move { verified.cdata.unwrap() }),
+            )
+            .layer(TurnstileLayer::new(
+                TurnstileConfig::new("secret").with_verify_url(&mock.url),
+            ))
+            .layer(map_request(|mut req: Request| async move {
+                let session = req.headers()["x-session"].to_str().unwrap().to_string();
+                req.extensions_mut()
+                    .insert(TurnstileOverride::new().with_expected_cdata(session));
+                req
+            }));
+        let send = |session: &str, token: &str| {
+            app.clone().oneshot(
+                Request::post("/submit")
+                    .header("x-session", session)
+                    .header("CF-Turnstile-Token", token)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+        };
+
+        let response = send("session-1", "session-1").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], b"session-1");
+
+        let response = send("session-2", "session-1").await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+}
@@ -0,0 +1,270 @@
+use crate::{RejectionReason, TurnstileVerification, ValidatorKind};
+use std::{fmt, sync::Arc};
+
+type CheckFn = Arc<dyn Fn(&TurnstileVerification) -> Result<(), RejectionReason> + Send + Sync>;
+type ValidatorFn = Arc<dyn Fn(&TurnstileVerification) -> bool + Send + Sync>;
+
+/// The checks a token Cloudflare accepted must pass, run in order
+///
+/// Each check sees what Cloudflare reported and either passes or names the
+/// [`RejectionReason`] to reject with; the first failing check rejects the request and the
+/// rest don't run. Business rules like "the action must be one of these" then need no
+/// support from the crate.
+///
+/// The policy a request is verified with starts with the built-in checks the
+/// [`TurnstileConfig`](crate::TurnstileConfig) sets up, such as
+/// [`with_max_token_age`] and [`with_expected_action`], followed by the checks added here.
+/// The built-in validators of action, hostname, `cdata` and fingerprint sit next to each
+/// other and report every one of them that failed together, in one
+/// [`RejectionReason::ValidationFailed`].
+///
+/// ```rust
+/// use axum_turnstile::{RejectionReason, TurnstileConfig, ValidatorKind, VerificationPolicy};
+///
+/// let policy = VerificationPolicy::new()
+///     .add_check(|verification| {
+///         match verification.action.as_deref() {
+///             Some("login" | "signup") => Ok(()),
+///             _ => Err(RejectionReason::ValidationFailed(vec![ValidatorKind::Action])),
+///         }
+///     })
+///     .add_check(|verification| {
+///         let hostname = verification.hostname.as_deref().unwrap_or_default();
+///         if hostname.ends_with(".internal.example") {
+///             return Err(RejectionReason::ValidationFailed(vec![ValidatorKind::Hostname]));
+///         }
+///         Ok(())
+///     });
+///
+/// let config = TurnstileConfig::new("your-secret-key").with_policy(policy);
+/// ```
+///
+/// [`with_max_token_age`]: crate::TurnstileConfig::with_max_token_age
+/// [`with_expected_action`]: crate::TurnstileConfig::with_expected_action
+#[derive(Clone, Default)]
+pub struct VerificationPolicy {
+    checks: Vec<Check>,
+}
+
+#[derive(Clone)]
+enum Check {
+    /// A check that rejects on its own
+    Rule(CheckFn),
+    /// A built-in validator, reported together with the validators next to it
+    Validator(ValidatorKind, ValidatorFn),
+}
+
+impl VerificationPolicy {
+    /// Create a policy without checks, which accepts every verification
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `check` after the checks added before it
+    pub fn add_check(
+        mut self,
+        check: impl Fn(&TurnstileVerification) -> Result<(), RejectionReason> + Send + Sync + 'static,
+    ) -> Self {
+        self.checks.push(Check::Rule(Arc::new(check)));
+        self
+    }
+
+    /// Run the built-in validator `kind`, failing when `passes` returns false
+    pub(crate) fn add_validator(
+        mut self,
+        kind: ValidatorKind,
+        passes: impl Fn(&TurnstileVerification) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        self.checks.push(Check::Validator(kind, Arc::new(passes)));
+        self
+    }
+
+    /// Run `other`'s checks after these
+    pub(crate) fn then(mut self, other: &VerificationPolicy) -> Self {
+        self.checks.extend(other.checks.iter().cloned());
+        self
+    }
+
+    /// Run the checks in order, stopping at the first that fails
+    ///
+    /// Failed validators are collected until the next check that isn't one.
+    pub(crate) fn check(
+        &self,
+        verification: &TurnstileVerification,
+    ) -> Result<(), RejectionReason> {
+        let mut failed_validators = Vec::new();
+        for check in &self.checks {
+            match check {
+                Check::Validator(kind, passes) => {
+                    if !passes(verification) && !failed_validators.contains(kind) {
+                        failed_validators.push(*kind);
+                    }
+                }
+                Check::Rule(_) if !failed_validators.is_empty() => break,
+                Check::Rule(check) => check(verification)?,
+            }
+        }
+        if failed_validators.is_empty() {
+            Ok(())
+        } else {
+            Err(RejectionReason::ValidationFailed(failed_validators))
+        }
+    }
+}
+
+impl fmt::Debug for VerificationPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VerificationPolicy")
+            .field("checks", &self.checks.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        test_support::MockSiteverify, TurnstileConfig, TurnstileLayer, ValidationFailure,
+        ValidatorKind,
+    };
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        response::Response,
+        routing::post,
+        Router,
+    };
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tower::ServiceExt;
+
+    async fn send(action: &str, hostname: &str, policy: VerificationPolicy) -> Response {
+        let mock = MockSiteverify::start(serde_json::json!({
+            "success": true,
+            "action": action,
+            "hostname": hostname,
+        }))
+        .await;
+        Router::new()
+            .route("/submit", post(|| async { "OK" }))
+            .layer(TurnstileLayer::new(
+                TurnstileConfig::new("secret")
+                    .with_verify_url(&mock.url)
+                    .with_policy(policy),
+            ))
+            .oneshot(
+                Request::post("/submit")
+                    .header("CF-Turnstile-Token", "token")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_composed_checks_reject_on_first_failure() {
+        let hostname_checks = Arc::new(AtomicUsize::new(0));
+        let policy = {
+            let hostname_checks = hostname_checks.clone();
+            VerificationPolicy::new()
+                .add_check(|verification| match verification.action.as_deref() {
+                    Some("login" | "signup") => Ok(()),
+                    _ => Err(RejectionReason::ValidationFailed(vec![
+                        ValidatorKind::Action,
+                    ])),
+                })
+                .add_check(move |verification| {
+                    hostname_checks.fetch_add(1, Ordering::SeqCst);
+                    match verification.hostname.as_deref() {
+                        Some("myapp.example") => Ok(()),
+                        _ => Err(RejectionReason::ValidationFailed(vec![
+                            ValidatorKind::Hostname,
+                        ])),
+                    }
+                })
+        };
+
+        let response = send("signup", "myapp.example", policy.clone()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = send("signup", "evil.example", policy.clone()).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(
+            response.extensions().get::<ValidationFailure>(),
+            Some(&ValidationFailure {
+                failed_validators: vec![ValidatorKind::Hostname],
+            })
+        );
+        assert_eq!(hostname_checks.load(Ordering::SeqCst), 2);
+
+        // Both checks would fail, but the second never runs
+        let response = send("delete", "evil.example", policy).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(
+            response.extensions().get::<ValidationFailure>(),
+            Some(&ValidationFailure {
+                failed_validators: vec![ValidatorKind::Action],
+            })
+        );
+        assert_eq!(hostname_checks.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_custom_checks_follow_built_in_ones() {
+        let custom_checks = Arc::new(AtomicUsize::new(0));
+        let policy = {
+            let custom_checks = custom_checks.clone();
+            VerificationPolicy::new().add_check(move |_| {
+                custom_checks.fetch_add(1, Ordering::SeqCst);
+                Err(RejectionReason::ValidationFailed(vec![
+                    ValidatorKind::Cdata,
+                ]))
+            })
+        };
+        let mock = MockSiteverify::start(serde_json::json!({
+            "success": true,
+            "action": "login",
+            "hostname": "evil.example",
+        }))
+        .await;
+        let send = |config: TurnstileConfig| {
+            Router::new()
+                .route("/submit", post(|| async { "OK" }))
+                .layer(TurnstileLayer::new(config.with_verify_url(&mock.url)))
+                .oneshot(
+                    Request::post("/submit")
+                        .header("CF-Turnstile-Token", "token")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+        };
+
+        // Built-in validators fail together, and the custom check never runs
+        let config = TurnstileConfig::new("secret")
+            .with_expected_action("signup")
+            .with_expected_hostnames(vec!["myapp.example".to_string()])
+            .with_policy(policy.clone());
+        let response = send(config).await.unwrap();
+        assert_eq!(
+            response.extensions().get::<ValidationFailure>(),
+            Some(&ValidationFailure {
+                failed_validators: vec![ValidatorKind::Action, ValidatorKind::Hostname],
+            })
+        );
+        assert_eq!(custom_checks.load(Ordering::SeqCst), 0);
+
+        // Once they pass, the custom check decides
+        let config = TurnstileConfig::new("secret")
+            .with_expected_action("login")
+            .with_policy(policy);
+        let response = send(config).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(
+            response.extensions().get::<ValidationFailure>(),
+            Some(&ValidationFailure {
+                failed_validators: vec![ValidatorKind::Cdata],
+            })
+        );
+        assert_eq!(custom_checks.load(Ordering::SeqCst), 1);
+    }
+}
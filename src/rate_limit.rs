@@ -0,0 +1,366 @@
+use crate::{expiring::ExpiringMap, IdentityFn, RouteBudgetFn, TurnstileOutcome};
+use axum::http::request::Parts;
+use std::{fmt, sync::Mutex, time::Instant};
+
+/// What to do with a verification that would exceed the
+/// [global rate limit](crate::TurnstileConfig::with_global_rate_limit)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RateLimitPolicy {
+    /// Reject the request with `503 Service Unavailable` (default)
+    #[default]
+    Reject503,
+    /// Pass the request through unverified, without a [`VerifiedTurnstile`]
+    ///
+    /// [`VerifiedTurnstile`]: crate::VerifiedTurnstile
+    SkipVerification,
+}
+
+/// Token bucket shared by every request a config verifies
+#[derive(Debug)]
+pub(crate) struct TokenBucket {
+    per_second: f64,
+    burst: f64,
+    state: Mutex<BucketState>,
+}
+
+#[derive(Debug)]
+struct BucketState {
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl TokenBucket {
+    /// A full bucket refilling `per_second` tokens up to `burst`
+    pub fn new(per_second: u32, burst: u32) -> Self {
+        Self {
+            per_second: per_second.into(),
+            burst: burst.into(),
+            state: Mutex::new(BucketState {
+                tokens: burst.into(),
+                refilled: Instant::now(),
+            }),
+        }
+    }
+
+    /// A full bucket holding `per_minute` tokens and refilling them evenly over a minute
+    pub fn per_minute(per_minute: u32) -> Self {
+        Self {
+            per_second: f64::from(per_minute) / 60.0,
+            ..Self::new(0, per_minute)
+        }
+    }
+
+    /// Take a token if one is available
+    pub fn try_acquire(&self) -> bool {
+        self.try_acquire_at(Instant::now())
+    }
+
+    fn try_acquire_at(&self, now: Instant) -> bool {
+        let mut state = self.state.lock().unwrap();
+        self.refill(&mut state, now);
+
+        if state.tokens >= 1.0 {
+            state.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Whether the bucket would be full at `now`
+    fn is_full_at(&self, now: Instant) -> bool {
+        let mut state = self.state.lock().unwrap();
+        self.refill(&mut state, now);
+        state.tokens >= self.burst
+    }
+
+    fn refill(&self, state: &mut BucketState, now: Instant) {
+        let elapsed = now.saturating_duration_since(state.refilled).as_secs_f64();
+        state.tokens = (state.tokens + elapsed * self.per_second).min(self.burst);
+        state.refilled = now;
+    }
+}
+
+/// One token bucket per identity derived from a verified outcome
+pub(crate) struct IdentityRateLimit {
+    identity: IdentityFn,
+    per_second: u32,
+    burst: u32,
+    buckets: Mutex<ExpiringMap<String, TokenBucket>>,
+}
+
+impl fmt::Debug for IdentityRateLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IdentityRateLimit")
+            .field("per_second", &self.per_second)
+            .field("burst", &self.burst)
+            .finish_non_exhaustive()
+    }
+}
+
+impl IdentityRateLimit {
+    pub fn new(identity: IdentityFn, per_second: u32, burst: u32) -> Self {
+        Self {
+            identity,
+            per_second,
+            burst,
+            buckets: Mutex::new(ExpiringMap::new()),
+        }
+    }
+
+    /// Take a token for the outcome's identity; outcomes without one aren't limited
+    pub fn try_acquire(&self, outcome: &TurnstileOutcome) -> bool {
+        let Some(identity) = (self.identity)(outcome) else {
+            return true;
+        };
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        // Buckets that have refilled completely behave like new ones, so drop them once they pile up
+        buckets.prune(|_, bucket| !bucket.is_full_at(now));
+        buckets
+            .entry(identity)
+            .or_insert_with(|| TokenBucket::new(self.per_second, self.burst))
+            .try_acquire_at(now)
+    }
+}
+
+/// One per-minute token bucket per route key resolved from the request head
+pub(crate) struct RouteBudget {
+    resolve: RouteBudgetFn,
+    buckets: Mutex<ExpiringMap<String, TokenBucket>>,
+}
+
+impl fmt::Debug for RouteBudget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RouteBudget").finish_non_exhaustive()
+    }
+}
+
+impl RouteBudget {
+    pub fn new(resolve: RouteBudgetFn) -> Self {
+        Self {
+            resolve,
+            buckets: Mutex::new(ExpiringMap::new()),
+        }
+    }
+
+    /// Take a token from the request's route bucket; routes without one aren't limited
+    ///
+    /// A bucket keeps the limit it was created with until it has refilled completely.
+    pub fn try_acquire(&self, parts: &Parts) -> bool {
+        self.try_acquire_at(parts, Instant::now())
+    }
+
+    fn try_acquire_at(&self, parts: &Parts, now: Instant) -> bool {
+        let Some((key, per_minute)) = (self.resolve)(parts) else {
+            return true;
+        };
+        let mut buckets = self.buckets.lock().unwrap();
+        buckets.prune(|_, bucket| !bucket.is_full_at(now));
+        buckets
+            .entry(key)
+            // A full bucket may not have been pruned yet, and mustn't keep its old limit
+            .and_modify(|bucket| {
+                if bucket.is_full_at(now) {
+                    *bucket = TokenBucket::per_minute(per_minute);
+                }
+            })
+            .or_insert_with(|| TokenBucket::per_minute(per_minute))
+            .try_acquire_at(now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_support::MockSiteverify, TurnstileConfig, TurnstileLayer, VerifiedTurnstile};
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        routing::post,
+        Router,
+    };
+    use std::time::Duration;
+    use tower::ServiceExt;
+
+    #[test]
+    fn test_bucket_refills_up_to_burst() {
+        let bucket = TokenBucket::new(2, 3);
+        let start = Instant::now();
+
+        assert!((0..3).all(|_| bucket.try_acquire_at(start)));
+        assert!(!bucket.try_acquire_at(start));
+
+        // Half a second refills one token, a minute no more than the burst
+        assert!(bucket.try_acquire_at(start + Duration::from_millis(500)));
+        assert!(!bucket.try_acquire_at(start + Duration::from_millis(500)));
+        let later = start + Duration::from_secs(60);
+        assert_eq!((0..5).filter(|_| bucket.try_acquire_at(later)).count(), 3);
+    }
+
+    #[test]
+    fn test_refilled_route_bucket_takes_new_limit() {
+        let budget = RouteBudget::new(std::sync::Arc::new(|parts: &Parts| {
+            let limit = parts.headers.get("x-limit")?.to_str().ok()?.parse().ok()?;
+            Some(("route".to_string(), limit))
+        }));
+        let parts = |limit: &str| {
+            Request::post("/")
+                .header("x-limit", limit)
+                .body(())
+                .unwrap()
+                .into_parts()
+                .0
+        };
+        let start = Instant::now();
+
+        assert!(budget.try_acquire_at(&parts("1"), start));
+        assert!(!budget.try_acquire_at(&parts("3"), start));
+
+        let later = start + Duration::from_secs(60);
+        let acquired = (0..5)
+            .filter(|_| budget.try_acquire_at(&parts("3"), later))
+            .count();
+        assert_eq!(acquired, 3);
+    }
+
+    async fn saturate(policy: RateLimitPolicy) -> (Vec<StatusCode>, usize) {
+        let mock = MockSiteverify::success().await;
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&mock.url)
+            .with_global_rate_limit(1, 2)
+            .with_rate_limit_policy(policy);
+        let app = Router::new()
+            .route(
+                "/submit",
+                post(|req: axum::extract::Request| async move {
+                    let verified = req.extensions().get::<VerifiedTurnstile>().is_some();
+                    format!("verified={verified}")
+                }),
+            )
+            .layer(TurnstileLayer::new(config));
+
+        let mut statuses = Vec::new();
+        // Different clients, so only the global limit applies
+        for client in 0..4 {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::post("/submit")
+                        .header("CF-Turnstile-Token", format!("token-{client}"))
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            statuses.push(response.status());
+        }
+        (statuses, mock.requests().len())
+    }
+
+    #[tokio::test]
+    async fn test_over_limit_rejected_by_default() {
+        let (statuses, upstream_calls) = saturate(RateLimitPolicy::default()).await;
+
+        assert_eq!(
+            statuses,
+            [
+                StatusCode::OK,
+                StatusCode::OK,
+                StatusCode::SERVICE_UNAVAILABLE,
+                StatusCode::SERVICE_UNAVAILABLE
+            ]
+        );
+        assert_eq!(upstream_calls, 2);
+    }
+
+    #[tokio::test]
+    async fn test_over_limit_skips_verification() {
+        let (statuses, upstream_calls) = saturate(RateLimitPolicy::SkipVerification).await;
+
+        assert_eq!(statuses, [StatusCode::OK; 4]);
+        assert_eq!(upstream_calls, 2);
+    }
+
+    #[tokio::test]
+    async fn test_route_budget() {
+        let mock = MockSiteverify::success().await;
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&mock.url)
+            .with_route_verification_budget(|parts| {
+                (parts.uri.path() == "/expensive").then(|| ("expensive".to_string(), 2))
+            });
+        let app = Router::new()
+            .route("/expensive", post(|| async { "OK" }))
+            .route("/cheap", post(|| async { "OK" }))
+            .layer(TurnstileLayer::new(config));
+
+        let mut statuses = Vec::new();
+        for path in ["/expensive", "/expensive", "/expensive", "/cheap", "/cheap"] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::post(path)
+                        .header("CF-Turnstile-Token", "token")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            statuses.push(response.status());
+        }
+
+        assert_eq!(
+            statuses,
+            [
+                StatusCode::OK,
+                StatusCode::OK,
+                StatusCode::SERVICE_UNAVAILABLE,
+                StatusCode::OK,
+                StatusCode::OK
+            ]
+        );
+        // The shed request never reached Cloudflare
+        assert_eq!(mock.requests().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_identity_rate_limit() {
+        let mock = MockSiteverify::respond_with(|request| {
+            let token = request.json()["response"].as_str().unwrap().to_string();
+            serde_json::json!({ "success": true, "cdata": token.trim_end_matches("-token") })
+        })
+        .await;
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&mock.url)
+            .with_identity_rate_limit(|outcome| outcome.cdata.clone(), 1, 2);
+        let app = Router::new()
+            .route("/submit", post(|| async { "OK" }))
+            .layer(TurnstileLayer::new(config));
+
+        let mut statuses = Vec::new();
+        for user in ["alice", "alice", "alice", "bob"] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::post("/submit")
+                        .header("CF-Turnstile-Token", format!("{user}-token"))
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            statuses.push(response.status());
+        }
+
+        assert_eq!(
+            statuses,
+            [
+                StatusCode::OK,
+                StatusCode::OK,
+                StatusCode::TOO_MANY_REQUESTS,
+                StatusCode::OK
+            ]
+        );
+    }
+}
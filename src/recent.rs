@@ -0,0 +1,87 @@
+use crate::TurnstileOutcome;
+use std::{collections::VecDeque, sync::Mutex};
+
+/// Fixed-size buffer of the latest outcomes, oldest first
+#[derive(Debug)]
+pub(crate) struct RecentOutcomes {
+    capacity: usize,
+    outcomes: Mutex<VecDeque<TurnstileOutcome>>,
+}
+
+impl RecentOutcomes {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            outcomes: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    /// Store a redacted copy of `outcome`, dropping the oldest one if the buffer is full
+    pub fn push(&self, outcome: &TurnstileOutcome) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut outcome = outcome.clone();
+        // Either would let someone reading the buffer correlate or replay the token
+        outcome.token_hash = None;
+        outcome.idempotency_key = None;
+
+        let mut outcomes = self.outcomes.lock().unwrap();
+        if outcomes.len() == self.capacity {
+            outcomes.pop_front();
+        }
+        outcomes.push_back(outcome);
+    }
+
+    pub fn snapshot(&self) -> Vec<TurnstileOutcome> {
+        self.outcomes.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{test_support::MockSiteverify, Decision, TurnstileConfig, TurnstileLayer};
+    use axum::{body::Body, http::Request, routing::post, Router};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_keeps_latest_outcomes_redacted() {
+        let mock = MockSiteverify::respond_with(|request| {
+            let token = request.json()["response"].as_str().unwrap().to_string();
+            serde_json::json!({ "success": true, "action": token })
+        })
+        .await;
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&mock.url)
+            .with_idempotency(true)
+            .with_recent_outcomes(2);
+        let app = Router::new()
+            .route("/submit", post(|| async { "OK" }))
+            .layer(TurnstileLayer::new(config.clone()));
+
+        for token in ["first", "second", "third"] {
+            app.clone()
+                .oneshot(
+                    Request::post("/submit")
+                        .header("CF-Turnstile-Token", token)
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+        }
+
+        let recent = config.recent_outcomes();
+        let actions: Vec<_> = recent.iter().map(|o| o.action.as_deref()).collect();
+        assert_eq!(actions, [Some("second"), Some("third")]);
+        for outcome in &recent {
+            assert_eq!(outcome.decision, Decision::Verified);
+            assert_eq!(outcome.token_hash, None);
+            assert_eq!(outcome.idempotency_key, None);
+        }
+
+        let rendered = format!("{recent:?}");
+        assert!(!rendered.contains("secret"));
+        assert!(!rendered.contains(&crate::token::hash_token("third")));
+    }
+}
@@ -0,0 +1,186 @@
+use crate::TurnstileConfig;
+use axum::{extract::MatchedPath, http::request::Parts};
+use std::{collections::HashMap, fmt, sync::Arc};
+
+type SelectorFn = Arc<dyn Fn(&Parts) -> Option<String> + Send + Sync>;
+
+/// Named configs for one layer serving several widgets
+///
+/// Pages often carry several widgets, such as login, comments and a newsletter signup, each
+/// with its own secret and expected action. Instead of mounting one layer per route, put
+/// their configs in a registry and mount it once with [`TurnstileLayer::from_registry`].
+/// Each request is verified with the config its name selects.
+///
+/// A request whose name isn't registered, or that doesn't name one at all, is answered with
+/// `500 Internal Server Error`: it points at a route or frontend the registry wasn't set up
+/// for, and must not pass unverified.
+///
+/// ```rust
+/// use axum::{routing::post, Router};
+/// use axum_turnstile::{TurnstileConfig, TurnstileLayer, TurnstileRegistry};
+///
+/// let registry = TurnstileRegistry::by_route()
+///     .with_config(
+///         "/login",
+///         TurnstileConfig::new("login-secret").with_expected_action("login"),
+///     )
+///     .with_config(
+///         "/comments",
+///         TurnstileConfig::new("comment-secret").with_expected_action("comment"),
+///     );
+///
+/// let app: Router = Router::new()
+///     .route("/login", post(|| async { "Logged in" }))
+///     .route("/comments", post(|| async { "Posted" }))
+///     .layer(TurnstileLayer::from_registry(registry));
+/// ```
+///
+/// [`TurnstileLayer::from_registry`]: crate::TurnstileLayer::from_registry
+#[derive(Clone)]
+pub struct TurnstileRegistry {
+    configs: HashMap<String, TurnstileConfig>,
+    selector: SelectorFn,
+}
+
+impl TurnstileRegistry {
+    /// Create an empty registry that names the config for each request with `selector`
+    pub fn new(selector: impl Fn(&Parts) -> Option<String> + Send + Sync + 'static) -> Self {
+        Self {
+            configs: HashMap::new(),
+            selector: Arc::new(selector),
+        }
+    }
+
+    /// Create an empty registry selecting configs by the value of the request header `name`
+    pub fn by_header(name: impl Into<String>) -> Self {
+        let name = name.into();
+        Self::new(move |parts| {
+            let value = parts.headers.get(&name)?.to_str().ok()?;
+            Some(value.trim().to_string())
+        })
+    }
+
+    /// Create an empty registry selecting configs by the route the request matched, such as
+    /// `/users/{id}`
+    ///
+    /// The route is only known to layers added with `Router::layer` or
+    /// `Router::route_layer`, not to layers wrapping the whole router as a service.
+    pub fn by_route() -> Self {
+        Self::new(|parts| {
+            let path = parts.extensions.get::<MatchedPath>()?;
+            Some(path.as_str().to_string())
+        })
+    }
+
+    /// Register `config` under `name`, replacing any config registered before
+    pub fn with_config(mut self, name: impl Into<String>, config: TurnstileConfig) -> Self {
+        self.configs.insert(name.into(), config);
+        self
+    }
+
+    /// The config registered under `name`
+    pub fn get(&self, name: &str) -> Option<&TurnstileConfig> {
+        self.configs.get(name)
+    }
+
+    /// Every registered config
+    pub(crate) fn configs(&self) -> impl Iterator<Item = &TurnstileConfig> {
+        self.configs.values()
+    }
+
+    /// The config the request selects, logging requests it has none for
+    pub(crate) fn select(&self, parts: &Parts) -> Option<&TurnstileConfig> {
+        let Some(name) = (self.selector)(parts) else {
+            error!(path = %parts.uri.path(), "request names no Turnstile config");
+            return None;
+        };
+        let config = self.configs.get(&name);
+        if config.is_none() {
+            error!(name, "no Turnstile config registered under this name");
+        }
+        config
+    }
+}
+
+impl fmt::Debug for TurnstileRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TurnstileRegistry")
+            .field("configs", &self.configs)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_support::MockSiteverify, TurnstileLayer, TurnstileOutcome};
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        routing::post,
+        Extension, Router,
+    };
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_header_selects_named_config() {
+        let mock = MockSiteverify::success().await;
+        let registry = TurnstileRegistry::by_header("X-Turnstile-Widget")
+            .with_config(
+                "login",
+                TurnstileConfig::new("login-secret")
+                    .with_verify_url(&mock.url)
+                    .with_sitekey("0xLOGIN"),
+            )
+            .with_config(
+                "newsletter",
+                TurnstileConfig::new("newsletter-secret").with_verify_url(&mock.url),
+            );
+        let app = Router::new()
+            .route(
+                "/submit",
+                post(
+                    |Extension(outcome): Extension<TurnstileOutcome>| async move {
+                        outcome.sitekey.unwrap_or_default()
+                    },
+                ),
+            )
+            .layer(TurnstileLayer::from_registry(registry));
+        let send = |widget: Option<&str>| {
+            let mut request = Request::post("/submit").header("CF-Turnstile-Token", "token");
+            if let Some(widget) = widget {
+                request = request.header("X-Turnstile-Widget", widget);
+            }
+            let response = app.clone().oneshot(request.body(Body::empty()).unwrap());
+            async move {
+                let response = response.await.unwrap();
+                let status = response.status();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                (status, String::from_utf8(body.to_vec()).unwrap())
+            }
+        };
+
+        assert_eq!(
+            send(Some("login")).await,
+            (StatusCode::OK, "0xLOGIN".to_string())
+        );
+        assert_eq!(
+            send(Some("newsletter")).await,
+            (StatusCode::OK, String::new())
+        );
+        assert_eq!(
+            send(Some("comments")).await.0,
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+        assert_eq!(send(None).await.0, StatusCode::INTERNAL_SERVER_ERROR);
+
+        let secrets: Vec<_> = mock
+            .requests()
+            .iter()
+            .map(|r| r.json()["secret"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(secrets, ["login-secret", "newsletter-secret"]);
+    }
+}
@@ -0,0 +1,184 @@
+#[cfg(feature = "memory-token-store")]
+use crate::expiring::ExpiringMap;
+use futures_util::future::BoxFuture;
+#[cfg(feature = "memory-token-store")]
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Remembers which tokens were already submitted, to reject replays before asking Cloudflare
+///
+/// Cloudflare rejects a token once it's spent, but two requests racing with the same token
+/// can both reach it before that happens. Share one store between instances, e.g. backed by
+/// Redis, to close that window across them.
+pub trait TokenStore: Send + Sync {
+    /// Record the token hash, returning whether it was new
+    ///
+    /// Must check and insert atomically, or racing requests can both see the token as new.
+    fn check_and_insert<'a>(&'a self, token_hash: &'a str) -> BoxFuture<'a, bool>;
+
+    /// Forget the token hash, because its verification failed before Cloudflare could
+    /// spend the token
+    ///
+    /// Called when the request is rejected over a timeout, an HTTP error or a transport
+    /// error, so the client can retry the same token. The default keeps the hash, which
+    /// rejects such a retry as a replay.
+    fn remove<'a>(&'a self, token_hash: &'a str) -> BoxFuture<'a, ()> {
+        let _ = token_hash;
+        Box::pin(std::future::ready(()))
+    }
+}
+
+/// In-memory [`TokenStore`] for a single instance
+///
+/// Hashes are forgotten after `ttl`, which should be at least as long as tokens stay valid
+/// (300 seconds). Expired hashes are dropped together once the store has grown past 1024
+/// of them, rather than on every request.
+#[cfg(feature = "memory-token-store")]
+#[derive(Debug)]
+pub struct MemoryTokenStore {
+    ttl: Duration,
+    seen: Mutex<ExpiringMap<String, Instant>>,
+}
+
+#[cfg(feature = "memory-token-store")]
+impl MemoryTokenStore {
+    /// Create a store that remembers token hashes for `ttl`
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            seen: Mutex::new(ExpiringMap::new()),
+        }
+    }
+}
+
+#[cfg(feature = "memory-token-store")]
+impl TokenStore for MemoryTokenStore {
+    fn check_and_insert<'a>(&'a self, token_hash: &'a str) -> BoxFuture<'a, bool> {
+        let now = Instant::now();
+        let mut seen = self.seen.lock().unwrap();
+        seen.prune(|_, at| now.duration_since(*at) < self.ttl);
+        // An expired hash that wasn't pruned yet counts as new
+        let previous = seen.insert(token_hash.to_string(), now);
+        let new = previous.is_none_or(|at| now.duration_since(at) >= self.ttl);
+        Box::pin(std::future::ready(new))
+    }
+
+    fn remove<'a>(&'a self, token_hash: &'a str) -> BoxFuture<'a, ()> {
+        self.seen.lock().unwrap().remove(token_hash);
+        Box::pin(std::future::ready(()))
+    }
+}
+
+#[cfg(all(test, feature = "memory-token-store"))]
+mod tests {
+    use super::*;
+    use crate::{test_support::MockSiteverify, TurnstileConfig, TurnstileLayer};
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        response::IntoResponse,
+        routing::post,
+        Json, Router,
+    };
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_reused_token_rejected_before_cloudflare() {
+        let mock = MockSiteverify::success().await;
+        mock.delay_with(|_| Duration::from_millis(50));
+        let store = Arc::new(MemoryTokenStore::new(Duration::from_secs(300)));
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&mock.url)
+            .with_token_store(store.clone());
+        let app = Router::new()
+            .route("/submit", post(|| async { "OK" }))
+            .layer(TurnstileLayer::new(config));
+        let send = |token: &'static str| {
+            app.clone().oneshot(
+                Request::post("/submit")
+                    .header("CF-Turnstile-Token", token)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+        };
+
+        // Both race to Cloudflare before it could mark the token spent
+        let (first, second) = tokio::join!(send("token"), send("token"));
+        let mut statuses = [first.unwrap().status(), second.unwrap().status()];
+        statuses.sort();
+        assert_eq!(statuses, [StatusCode::OK, StatusCode::FORBIDDEN]);
+
+        let response = send("token").await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, "Turnstile token already used");
+
+        assert_eq!(send("other").await.unwrap().status(), StatusCode::OK);
+        assert_eq!(mock.requests().len(), 2);
+
+        // Only hashes are kept
+        let seen = store.seen.lock().unwrap();
+        assert!(seen.keys().all(|key| key.len() == 64 && key != "token"));
+    }
+
+    #[tokio::test]
+    async fn test_token_released_when_verification_call_fails() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mock = {
+            let calls = calls.clone();
+            MockSiteverify::respond_raw(move |_| {
+                if calls.fetch_add(1, Ordering::SeqCst) == 0 {
+                    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+                } else {
+                    Json(serde_json::json!({ "success": true })).into_response()
+                }
+            })
+            .await
+        };
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&mock.url)
+            .with_token_store(Arc::new(MemoryTokenStore::new(Duration::from_secs(300))));
+        let app = Router::new()
+            .route("/submit", post(|| async { "OK" }))
+            .layer(TurnstileLayer::new(config));
+        let send = || {
+            app.clone().oneshot(
+                Request::post("/submit")
+                    .header("CF-Turnstile-Token", "token")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+        };
+
+        let response = send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        // Cloudflare never saw the token, so the retry isn't a replay
+        let response = send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(mock.requests().len(), 2);
+
+        let response = send().await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(mock.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_expired_hash_is_new_again() {
+        let store = MemoryTokenStore::new(Duration::ZERO);
+        assert!(store.check_and_insert("hash").await);
+        assert!(store.check_and_insert("hash").await);
+
+        let store = MemoryTokenStore::new(Duration::from_secs(300));
+        assert!(store.check_and_insert("hash").await);
+        assert!(!store.check_and_insert("hash").await);
+    }
+}
@@ -0,0 +1,140 @@
+use crate::{timestamp, HostnameClass, TurnstileConfig, TurnstileOutcome};
+use std::time::{Duration, SystemTime};
+
+/// How far `challenge_ts` may lie in the future before the clocks are considered skewed
+pub(crate) const MAX_FUTURE_SKEW: Duration = Duration::from_secs(30);
+
+/// Age of a solved challenge from which the token counts as stale
+const STALE_AGE: Duration = Duration::from_secs(120);
+
+/// Coarse risk of a verified request, for step-up decisions in handlers
+///
+/// Set on [`TurnstileOutcome::risk_level`] for tokens Cloudflare accepted, by
+/// [`RiskLevel::classify`] or the configured
+/// [risk classifier](crate::TurnstileConfig::with_risk_classifier).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RiskLevel {
+    /// Nothing unusual about the verification
+    Low,
+    /// Something is missing, such as the `action`, or the challenge was solved a while ago
+    Medium,
+    /// The verification looks wrong, e.g. it came from a third-party hostname or its
+    /// `challenge_ts` lies in the future
+    High,
+}
+
+impl RiskLevel {
+    /// The default heuristic
+    ///
+    /// - `High` if `hostname` is [third-party](HostnameClass::ThirdParty) or `challenge_ts` is
+    ///   more than 30 seconds in the future
+    /// - `Medium` if `challenge_ts` or `action` is missing, or the challenge was solved more
+    ///   than 2 minutes ago
+    /// - `Low` otherwise
+    pub fn classify(outcome: &TurnstileOutcome) -> RiskLevel {
+        Self::classify_at(outcome, SystemTime::now())
+    }
+
+    fn classify_at(outcome: &TurnstileOutcome, now: SystemTime) -> RiskLevel {
+        if outcome.hostname_class == Some(HostnameClass::ThirdParty) {
+            return RiskLevel::High;
+        }
+        let Some(solved) = outcome
+            .challenge_ts
+            .as_deref()
+            .and_then(timestamp::parse_rfc3339)
+        else {
+            return RiskLevel::Medium;
+        };
+        match now.duration_since(solved) {
+            Err(ahead) if ahead.duration() > MAX_FUTURE_SKEW => RiskLevel::High,
+            Ok(age) if age > STALE_AGE => RiskLevel::Medium,
+            _ if outcome.action.is_none() => RiskLevel::Medium,
+            _ => RiskLevel::Low,
+        }
+    }
+}
+
+/// Rate a verified outcome with the configured classifier, or the default one
+pub(crate) fn assess(outcome: &TurnstileOutcome, config: &TurnstileConfig) -> RiskLevel {
+    match &config.risk_classifier {
+        Some(classifier) => classifier(outcome),
+        None => RiskLevel::classify(outcome),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_support::MockSiteverify, TurnstileLayer};
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        routing::post,
+        Extension, Router,
+    };
+    use tower::ServiceExt;
+
+    fn outcome(challenge_ts: &str) -> TurnstileOutcome {
+        let mut outcome = TurnstileOutcome::new(None);
+        outcome.challenge_ts = Some(challenge_ts.to_string());
+        outcome.hostname = Some("myapp.example".to_string());
+        outcome.hostname_class = Some(HostnameClass::FirstParty);
+        outcome.action = Some("login".to_string());
+        outcome
+    }
+
+    #[test]
+    fn test_default_classifier() {
+        let now = timestamp::parse_rfc3339("2024-05-01T12:00:00Z").unwrap();
+
+        let clean = outcome("2024-05-01T11:59:50Z");
+        assert_eq!(RiskLevel::classify_at(&clean, now), RiskLevel::Low);
+
+        // Solved ten minutes "from now": the clocks disagree or the timestamp is made up
+        let skewed = outcome("2024-05-01T12:10:00Z");
+        assert_eq!(RiskLevel::classify_at(&skewed, now), RiskLevel::High);
+
+        let stale = outcome("2024-05-01T11:55:00Z");
+        assert_eq!(RiskLevel::classify_at(&stale, now), RiskLevel::Medium);
+
+        let mut third_party = clean.clone();
+        third_party.hostname_class = Some(HostnameClass::ThirdParty);
+        assert_eq!(RiskLevel::classify_at(&third_party, now), RiskLevel::High);
+
+        let mut no_action = clean;
+        no_action.action = None;
+        assert_eq!(RiskLevel::classify_at(&no_action, now), RiskLevel::Medium);
+    }
+
+    #[tokio::test]
+    async fn test_custom_classifier_reaches_handler() {
+        let mock = MockSiteverify::success().await;
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&mock.url)
+            .with_risk_classifier(|_| RiskLevel::High);
+
+        let response = Router::new()
+            .route(
+                "/submit",
+                post(
+                    |Extension(outcome): Extension<TurnstileOutcome>| async move {
+                        match outcome.risk_level {
+                            Some(RiskLevel::High) => StatusCode::UNAUTHORIZED,
+                            _ => StatusCode::OK,
+                        }
+                    },
+                ),
+            )
+            .layer(TurnstileLayer::new(config))
+            .oneshot(
+                Request::post("/submit")
+                    .header("CF-Turnstile-Token", "token")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,95 @@
+use crate::TurnstileConfig;
+use axum::http::{uri::Scheme, Request};
+
+/// Whether the request arrived over HTTPS, as far as the app can tell
+///
+/// Uses [`TurnstileConfig::forwarded_proto_header`] when configured, trusting it like
+/// [`TurnstileConfig::remote_ip_header`], and otherwise the request's own scheme. Requests
+/// whose scheme is unknown don't count as HTTPS.
+pub(crate) fn is_https<B>(req: &Request<B>, config: &TurnstileConfig) -> bool {
+    match &config.forwarded_proto_header {
+        Some(name) => req
+            .headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            // Proxies append their own hop, so the first entry is the client's
+            .and_then(|v| v.split(',').next())
+            .is_some_and(|proto| proto.trim().eq_ignore_ascii_case("https")),
+        None => req.uri().scheme() == Some(&Scheme::HTTPS),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{test_support::MockSiteverify, TurnstileConfig, TurnstileLayer};
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        routing::post,
+        Router,
+    };
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_plaintext_forwarded_proto_rejected() {
+        let mock = MockSiteverify::success().await;
+        let app = Router::new()
+            .route("/submit", post(|| async { "OK" }))
+            .layer(TurnstileLayer::new(
+                TurnstileConfig::new("secret")
+                    .with_verify_url(&mock.url)
+                    .with_require_https(true)
+                    .with_forwarded_proto_header("X-Forwarded-Proto"),
+            ));
+
+        for (proto, expected) in [
+            (Some("http"), StatusCode::BAD_REQUEST),
+            (None, StatusCode::BAD_REQUEST),
+            (Some("https"), StatusCode::OK),
+            (Some("HTTPS, http"), StatusCode::OK),
+        ] {
+            let mut request = Request::post("/submit").header("CF-Turnstile-Token", "token");
+            if let Some(proto) = proto {
+                request = request.header("X-Forwarded-Proto", proto);
+            }
+            let response = app
+                .clone()
+                .oneshot(request.body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(response.status(), expected, "{proto:?}");
+        }
+        // Plaintext attempts never reach Cloudflare
+        assert_eq!(mock.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_request_scheme_without_forwarded_header() {
+        let mock = MockSiteverify::success().await;
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&mock.url)
+            .with_require_https(true);
+        let app = Router::new()
+            .route("/submit", post(|| async { "OK" }))
+            .layer(TurnstileLayer::new(config));
+
+        for (uri, expected) in [
+            ("https://myapp.example/submit", StatusCode::OK),
+            ("http://myapp.example/submit", StatusCode::BAD_REQUEST),
+        ] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::post(uri)
+                        .header("CF-Turnstile-Token", "token")
+                        // Untrusted unless configured
+                        .header("X-Forwarded-Proto", "https")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), expected, "{uri}");
+        }
+    }
+}
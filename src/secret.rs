@@ -0,0 +1,193 @@
+use futures_util::future::BoxFuture;
+use std::fmt;
+
+/// Supplies the Turnstile secret at verification time
+///
+/// Use this when the secret lives in a secrets manager and may rotate. The provider is
+/// awaited once per verification, so implementations should cache the secret themselves
+/// rather than fetching it on every call.
+pub trait SecretProvider: Send + Sync {
+    /// Resolve the current secret
+    fn secret(&self) -> BoxFuture<'_, Result<String, Box<dyn std::error::Error + Send + Sync>>>;
+}
+
+/// Shortest secret accepted by [`check_format`], well below the length of issued secrets
+const MIN_SECRET_LEN: usize = 20;
+
+/// Why a secret key was refused by [`TurnstileConfig::try_new`](crate::TurnstileConfig::try_new)
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SecretError {
+    /// The secret is empty
+    Empty,
+    /// The secret doesn't look like one issued by Cloudflare, e.g. it was truncated or
+    /// picked up surrounding whitespace
+    Malformed,
+}
+
+impl fmt::Display for SecretError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SecretError::Empty => f.write_str("Turnstile secret key is empty"),
+            SecretError::Malformed => {
+                f.write_str("Turnstile secret key doesn't look like a Cloudflare secret (`0x...`)")
+            }
+        }
+    }
+}
+
+impl std::error::Error for SecretError {}
+
+/// Check that `secret` is shaped like a Cloudflare secret key
+///
+/// Secrets start with a digit and `x`, such as `0x4AAA...` or the `1x000...AA` test keys,
+/// followed by letters, digits, `-` and `_`. This is a sanity check against typos and
+/// truncation, not a guarantee that Cloudflare will accept the secret.
+pub(crate) fn check_format(secret: &str) -> Result<(), SecretError> {
+    if secret.is_empty() {
+        return Err(SecretError::Empty);
+    }
+    let well_formed = match secret.as_bytes() {
+        [digit, b'x', rest @ ..] => {
+            digit.is_ascii_digit()
+                && secret.len() >= MIN_SECRET_LEN
+                && rest
+                    .iter()
+                    .all(|&b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+        }
+        _ => false,
+    };
+    if well_formed {
+        Ok(())
+    } else {
+        Err(SecretError::Malformed)
+    }
+}
+
+/// Whether `secret` is one of Cloudflare's documented test secrets
+pub(crate) fn is_test_key(secret: &str) -> bool {
+    matches!(
+        secret,
+        crate::TEST_SECRET_PASS | crate::TEST_SECRET_FAIL | crate::TEST_SECRET_SPENT
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_support::MockSiteverify, TurnstileConfig, TurnstileLayer};
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        routing::post,
+        Router,
+    };
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+    use tower::ServiceExt;
+
+    /// Returns a new secret on every call, as if rotated between requests
+    #[derive(Default)]
+    struct RotatingProvider(AtomicUsize);
+
+    impl SecretProvider for RotatingProvider {
+        fn secret(
+            &self,
+        ) -> BoxFuture<'_, Result<String, Box<dyn std::error::Error + Send + Sync>>> {
+            Box::pin(async move {
+                let n = self.0.fetch_add(1, Ordering::SeqCst) + 1;
+                Ok(format!("secret-{n}"))
+            })
+        }
+    }
+
+    struct FailingProvider;
+
+    impl SecretProvider for FailingProvider {
+        fn secret(
+            &self,
+        ) -> BoxFuture<'_, Result<String, Box<dyn std::error::Error + Send + Sync>>> {
+            Box::pin(async { Err("secrets manager unavailable".into()) })
+        }
+    }
+
+    fn app(config: TurnstileConfig) -> Router {
+        Router::new()
+            .route("/submit", post(|| async { "OK" }))
+            .layer(TurnstileLayer::new(config))
+    }
+
+    fn request() -> Request<Body> {
+        Request::post("/submit")
+            .header("CF-Turnstile-Token", "token")
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_secret_format() {
+        for secret in [
+            crate::TEST_SECRET_PASS,
+            crate::TEST_SECRET_FAIL,
+            crate::TEST_SECRET_SPENT,
+        ] {
+            let config = TurnstileConfig::try_new(secret).unwrap();
+            assert!(config.is_test_key());
+        }
+
+        let config = TurnstileConfig::try_new("0x4AAAAAAABkMYinukE8nzYS_Ab3-x9PqLm").unwrap();
+        assert!(!config.is_test_key());
+
+        for (secret, expected) in [
+            ("", SecretError::Empty),
+            ("0x4AAAAAAAB", SecretError::Malformed),
+            (
+                "0x4AAAAAAABkMYinukE8nzYS_Ab3-x9PqLm\n",
+                SecretError::Malformed,
+            ),
+            ("your-secret-key-goes-right-here", SecretError::Malformed),
+        ] {
+            assert_eq!(
+                TurnstileConfig::try_new(secret).unwrap_err(),
+                expected,
+                "{secret:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_provider_secret_used_per_verification() {
+        let mock = MockSiteverify::success().await;
+        let config = TurnstileConfig::new("static-secret")
+            .with_verify_url(&mock.url)
+            .with_secret_provider(Arc::new(RotatingProvider::default()));
+        let app = app(config);
+
+        for _ in 0..2 {
+            let response = app.clone().oneshot(request()).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK);
+        }
+
+        let secrets: Vec<_> = mock
+            .requests()
+            .iter()
+            .map(|r| r.json()["secret"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(secrets, ["secret-1", "secret-2"]);
+    }
+
+    #[tokio::test]
+    async fn test_provider_error_is_verification_error() {
+        let mock = MockSiteverify::success().await;
+        let config = TurnstileConfig::new("static-secret")
+            .with_verify_url(&mock.url)
+            .with_secret_provider(Arc::new(FailingProvider));
+
+        let response = app(config).oneshot(request()).await.unwrap();
+
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(mock.requests().is_empty());
+    }
+}
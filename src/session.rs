@@ -0,0 +1,266 @@
+use crate::TurnstileOutcome;
+use axum::{
+    extract::{FromRef, FromRequestParts},
+    http::{header, request::Parts, StatusCode},
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use ring::hmac;
+use serde_json::{Map, Value};
+use std::{
+    fmt,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// The only JOSE header we issue or accept
+const JWT_HEADER: &str = r#"{"alg":"HS256","typ":"JWT"}"#;
+
+/// Issues and validates HS256 session JWTs asserting a successful verification
+///
+/// Tokens carry `iat`, `exp`, the configured extra claims, and a `turnstile` claim with the
+/// `action`, `hostname`, `cdata` and `challenge_ts` Cloudflare reported.
+#[derive(Clone)]
+pub struct SessionJwt {
+    key: hmac::Key,
+    ttl: Duration,
+    claims: Map<String, Value>,
+}
+
+impl fmt::Debug for SessionJwt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SessionJwt")
+            .field("ttl", &self.ttl)
+            .field("claims", &self.claims)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SessionJwt {
+    /// Create a signer/validator from an HMAC key, a lifetime and extra claims
+    pub fn new(key: impl AsRef<[u8]>, ttl: Duration, claims: Map<String, Value>) -> Self {
+        Self {
+            key: hmac::Key::new(hmac::HMAC_SHA256, key.as_ref()),
+            ttl,
+            claims,
+        }
+    }
+
+    /// How long issued tokens are valid for
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Issue a token for a verified outcome
+    pub(crate) fn mint(&self, outcome: &TurnstileOutcome) -> String {
+        self.mint_at(outcome, SystemTime::now())
+    }
+
+    fn mint_at(&self, outcome: &TurnstileOutcome, now: SystemTime) -> String {
+        let iat = unix_secs(now);
+        let mut claims = self.claims.clone();
+        claims.insert("iat".into(), iat.into());
+        claims.insert("exp".into(), (iat + self.ttl.as_secs()).into());
+        claims.insert(
+            "turnstile".into(),
+            serde_json::json!({
+                "action": outcome.action,
+                "hostname": outcome.hostname,
+                "cdata": outcome.cdata,
+                "challenge_ts": outcome.challenge_ts,
+            }),
+        );
+
+        let signing_input = format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(JWT_HEADER),
+            URL_SAFE_NO_PAD.encode(Value::Object(claims).to_string())
+        );
+        let signature = hmac::sign(&self.key, signing_input.as_bytes());
+        format!(
+            "{signing_input}.{}",
+            URL_SAFE_NO_PAD.encode(signature.as_ref())
+        )
+    }
+
+    /// Validate a token, returning its claims if the signature is good and it hasn't expired
+    pub fn validate(&self, token: &str) -> Option<Map<String, Value>> {
+        self.validate_at(token, SystemTime::now())
+    }
+
+    fn validate_at(&self, token: &str, now: SystemTime) -> Option<Map<String, Value>> {
+        let (signing_input, signature) = token.rsplit_once('.')?;
+        let (header, payload) = signing_input.split_once('.')?;
+
+        let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+        hmac::verify(&self.key, signing_input.as_bytes(), &signature).ok()?;
+
+        // Only accept the header we issue, so the algorithm can't be swapped underneath us
+        let header: Value = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(header).ok()?).ok()?;
+        if header.get("alg")?.as_str()? != "HS256" {
+            return None;
+        }
+
+        let claims: Map<String, Value> =
+            serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload).ok()?).ok()?;
+        let exp = claims.get("exp")?.as_u64()?;
+        (unix_secs(now) < exp).then_some(claims)
+    }
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Extractor for a valid session JWT presented as `Authorization: Bearer <token>`
+///
+/// The [`SessionJwt`] used for validation is taken from the router state; rejects with
+/// `401 Unauthorized` when the token is missing, tampered with or expired.
+#[derive(Clone, Debug)]
+pub struct TurnstileSession {
+    /// Claims of the validated token
+    pub claims: Map<String, Value>,
+}
+
+impl<S> FromRequestParts<S> for TurnstileSession
+where
+    SessionJwt: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let token = parts
+            .headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .ok_or(StatusCode::UNAUTHORIZED)?;
+
+        SessionJwt::from_ref(state)
+            .validate(token)
+            .map(|claims| TurnstileSession { claims })
+            .ok_or(StatusCode::UNAUTHORIZED)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_support::MockSiteverify, verify_handler, TurnstileConfig};
+    use axum::{body::Body, http::Request, routing::get, Router};
+    use tower::ServiceExt;
+
+    const KEY: &[u8] = b"session-signing-key";
+
+    fn session_jwt(ttl: Duration) -> SessionJwt {
+        let claims = serde_json::json!({ "aud": "spa" });
+        SessionJwt::new(KEY, ttl, claims.as_object().unwrap().clone())
+    }
+
+    fn outcome() -> TurnstileOutcome {
+        let mut outcome = TurnstileOutcome::new(None);
+        outcome.action = Some("login".into());
+        outcome
+    }
+
+    #[tokio::test]
+    async fn test_verify_handler_mints_session_for_extractor() {
+        let mock = MockSiteverify::start(serde_json::json!({
+            "success": true,
+            "action": "login",
+            "hostname": "myapp.example",
+        }))
+        .await;
+        let jwt = session_jwt(Duration::from_secs(300));
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&mock.url)
+            .with_session_jwt(KEY, Duration::from_secs(300), jwt.claims.clone());
+
+        let app = Router::new()
+            .route("/verify", verify_handler(config))
+            .route(
+                "/me",
+                get(|session: TurnstileSession| async move {
+                    session.claims["turnstile"]["hostname"].to_string()
+                }),
+            )
+            .with_state(jwt);
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::post("/verify")
+                    .header("CF-Turnstile-Token", "token")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["expires_in"], 300);
+        let token = body["session"].as_str().unwrap();
+
+        let response = app
+            .clone()
+            .oneshot(
+                Request::get("/me")
+                    .header("Authorization", format!("Bearer {token}"))
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, r#""myapp.example""#);
+
+        let response = app
+            .oneshot(Request::get("/me").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn test_claims_and_expiry() {
+        let jwt = session_jwt(Duration::from_secs(60));
+        let issued = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let token = jwt.mint_at(&outcome(), issued);
+
+        let claims = jwt.validate_at(&token, issued).unwrap();
+        assert_eq!(claims["aud"], "spa");
+        assert_eq!(claims["iat"], 1_700_000_000);
+        assert_eq!(claims["exp"], 1_700_000_060);
+        assert_eq!(claims["turnstile"]["action"], "login");
+
+        let before_expiry = issued + Duration::from_secs(59);
+        assert!(jwt.validate_at(&token, before_expiry).is_some());
+        let at_expiry = issued + Duration::from_secs(60);
+        assert!(jwt.validate_at(&token, at_expiry).is_none());
+    }
+
+    #[test]
+    fn test_rejects_tampered_or_foreign_tokens() {
+        let jwt = session_jwt(Duration::from_secs(60));
+        let now = SystemTime::now();
+        let token = jwt.mint_at(&outcome(), now);
+
+        let other_key = SessionJwt::new(b"other-key", Duration::from_secs(60), Map::new());
+        assert!(other_key.validate_at(&token, now).is_none());
+
+        let (header, rest) = token.split_once('.').unwrap();
+        let (_, signature) = rest.split_once('.').unwrap();
+        let forged_claims = URL_SAFE_NO_PAD.encode(r#"{"exp":99999999999}"#);
+        let forged = format!("{header}.{forged_claims}.{signature}");
+        assert!(jwt.validate_at(&forged, now).is_none());
+
+        assert!(jwt.validate_at("not-a-jwt", now).is_none());
+    }
+}
@@ -0,0 +1,96 @@
+use crate::TurnstileConfig;
+use axum::http::HeaderMap;
+
+/// Request header naming the sitekey of the widget a token was solved on, for
+/// [`with_sitekey_routing`](crate::TurnstileConfig::with_sitekey_routing)
+pub const SITEKEY_HEADER: &str = "X-Turnstile-Sitekey";
+
+/// The config for the sitekey the request names in [`SITEKEY_HEADER`], otherwise `config`
+/// itself
+pub(crate) fn select<'a>(headers: &HeaderMap, config: &'a TurnstileConfig) -> &'a TurnstileConfig {
+    let Some(routes) = &config.sitekey_routes else {
+        return config;
+    };
+    let Some(sitekey) = headers.get(SITEKEY_HEADER).and_then(|v| v.to_str().ok()) else {
+        return config;
+    };
+    match routes.get(sitekey.trim()) {
+        Some(routed) => routed,
+        None => {
+            debug!(
+                sitekey,
+                "no config for the requested sitekey, using the default"
+            );
+            config
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_support::MockSiteverify, TurnstileLayer, TurnstileOutcome};
+    use axum::{body::Body, http::Request, routing::post, Extension, Router};
+    use std::collections::HashMap;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_sitekey_hint_selects_secret() {
+        let mock = MockSiteverify::success().await;
+        let config = TurnstileConfig::new("default-secret")
+            .with_verify_url(&mock.url)
+            .with_sitekey_routing(HashMap::from([
+                (
+                    "0xSITEKEY_A".to_string(),
+                    TurnstileConfig::new("secret-a").with_verify_url(&mock.url),
+                ),
+                (
+                    "0xSITEKEY_B".to_string(),
+                    TurnstileConfig::new("secret-b").with_verify_url(&mock.url),
+                ),
+            ]));
+        let app = Router::new()
+            .route(
+                "/submit",
+                post(
+                    |Extension(outcome): Extension<TurnstileOutcome>| async move {
+                        outcome.sitekey.unwrap_or_default()
+                    },
+                ),
+            )
+            .layer(TurnstileLayer::new(config));
+
+        let mut tagged = Vec::new();
+        for sitekey in [
+            Some("0xSITEKEY_A"),
+            Some("0xSITEKEY_B"),
+            Some("0xOTHER"),
+            None,
+        ] {
+            let mut request = Request::post("/submit").header("CF-Turnstile-Token", "token");
+            if let Some(sitekey) = sitekey {
+                request = request.header(SITEKEY_HEADER, sitekey);
+            }
+            let response = app
+                .clone()
+                .oneshot(request.body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            tagged.push(String::from_utf8(body.to_vec()).unwrap());
+        }
+
+        let secrets: Vec<_> = mock
+            .requests()
+            .iter()
+            .map(|r| r.json()["secret"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(
+            secrets,
+            ["secret-a", "secret-b", "default-secret", "default-secret"]
+        );
+        assert_eq!(tagged, ["0xSITEKEY_A", "0xSITEKEY_B", "", ""]);
+    }
+}
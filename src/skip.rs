@@ -0,0 +1,69 @@
+use crate::TurnstileConfig;
+use axum::http::Request;
+
+/// Whether the request matches one of the configured
+/// [skip rules](crate::TurnstileConfig::with_skip_paths)
+pub(crate) fn matches<B>(req: &Request<B>, config: &TurnstileConfig) -> bool {
+    config.skip_methods.contains(req.method())
+        || config
+            .skip_paths
+            .iter()
+            .any(|path| path == req.uri().path())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{test_support::MockSiteverify, Decision, TurnstileConfig, TurnstileLayer};
+    use axum::{
+        body::Body,
+        http::{Method, Request, StatusCode},
+        routing::any,
+        Extension, Router,
+    };
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_skipped_requests_reach_handler_without_token() {
+        let mock = MockSiteverify::success().await;
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&mock.url)
+            .with_skip_paths(vec!["/healthz".to_string()])
+            .with_skip_methods(vec![Method::OPTIONS]);
+        let handler = any(
+            |outcome: Option<Extension<crate::TurnstileOutcome>>| async move {
+                let decision = outcome.map(|Extension(outcome)| outcome.decision);
+                (Extension(decision), "OK")
+            },
+        );
+        let app = Router::new()
+            .route("/healthz", handler.clone())
+            .route("/submit", handler)
+            .layer(TurnstileLayer::new(config));
+
+        for (method, path, expected) in [
+            (Method::GET, "/healthz", StatusCode::OK),
+            (Method::OPTIONS, "/submit", StatusCode::OK),
+            (Method::POST, "/submit", StatusCode::BAD_REQUEST),
+            // Paths match exactly, so this one still needs a token
+            (Method::GET, "/healthz/deep", StatusCode::BAD_REQUEST),
+        ] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .method(method.clone())
+                        .uri(path)
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), expected, "{method} {path}");
+            if expected == StatusCode::OK {
+                let decision = response.extensions().get::<Option<Decision>>().cloned();
+                assert_eq!(decision, Some(Some(Decision::SkippedByRule)));
+            }
+        }
+        assert!(mock.requests().is_empty());
+    }
+}
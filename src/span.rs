@@ -0,0 +1,138 @@
+use crate::{Decision, RejectionReason, TurnstileOutcome};
+
+/// Record the outcome as `turnstile.*` fields on the caller's current span
+///
+/// `tracing` only records fields a span declared when it was created, so the caller's span
+/// needs `turnstile.result`, `turnstile.reason`, `turnstile.action`, `turnstile.hostname`
+/// and `turnstile.verification_id` (as [`Empty`](tracing::field::Empty) if they're not
+/// known yet); any it doesn't declare are skipped.
+pub(crate) fn record_current(outcome: &TurnstileOutcome) {
+    let span = tracing::Span::current();
+    span.record("turnstile.result", result(&outcome.decision));
+    if let Some(reason) = reason(&outcome.decision) {
+        span.record("turnstile.reason", reason);
+    }
+    if let Some(action) = &outcome.action {
+        span.record("turnstile.action", action.as_str());
+    }
+    if let Some(hostname) = &outcome.hostname {
+        span.record("turnstile.hostname", hostname.as_str());
+    }
+    span.record(
+        "turnstile.verification_id",
+        outcome.verification_id.as_str(),
+    );
+}
+
+fn result(decision: &Decision) -> &'static str {
+    match decision {
+        Decision::Verified => "verified",
+        Decision::Rejected(_) => "rejected",
+        Decision::Monitored(_) => "monitored",
+        _ => "bypassed",
+    }
+}
+
+/// Why the request was rejected or bypassed verification
+fn reason(decision: &Decision) -> Option<&'static str> {
+    let reason = match decision {
+        Decision::Rejected(reason) | Decision::Monitored(reason) => reason,
+        decision => return decision.bypass_reason(),
+    };
+    Some(match reason {
+        RejectionReason::OriginNotAllowed => "origin_not_allowed",
+        RejectionReason::HttpsRequired => "https_required",
+        RejectionReason::CountryBlocked => "country_blocked",
+        RejectionReason::MissingToken => "missing_token",
+        RejectionReason::MalformedToken => "malformed_token",
+        RejectionReason::PayloadTooLarge => "payload_too_large",
+        RejectionReason::Replay => "replay",
+        RejectionReason::Stale => "stale",
+        RejectionReason::VerificationFailed(_) => "verification_failed",
+        RejectionReason::ExpiredOrUsed => "expired_or_used",
+        RejectionReason::ValidationFailed(_) => "validation_failed",
+        RejectionReason::MalformedRequest(_) => "malformed_request",
+        RejectionReason::ApiError => "api_error",
+        RejectionReason::EmptyResponse => "empty_response",
+        RejectionReason::Timeout => "timeout",
+        RejectionReason::RateLimited => "rate_limited",
+        RejectionReason::UpstreamRateLimited => "upstream_rate_limited",
+        RejectionReason::RouteBudgetExhausted => "route_budget_exhausted",
+        RejectionReason::IdentityRateLimited => "identity_rate_limited",
+        RejectionReason::Overridden => "overridden",
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        test_support::{MockSiteverify, SpanRecorder},
+        TurnstileConfig, TurnstileLayer,
+    };
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        routing::post,
+        Router,
+    };
+    use tower::ServiceExt;
+    use tracing::{field::Empty, Instrument};
+
+    #[tokio::test]
+    async fn test_outcome_recorded_on_current_span() {
+        let recorder = SpanRecorder::default();
+        let _default = tracing::subscriber::set_default(recorder.clone());
+
+        let mock = MockSiteverify::start(serde_json::json!({
+            "success": true,
+            "action": "login",
+            "hostname": "myapp.example",
+        }))
+        .await;
+        let app = Router::new()
+            .route("/submit", post(|| async { "OK" }))
+            .layer(TurnstileLayer::new(
+                TurnstileConfig::new("secret")
+                    .with_verify_url(&mock.url)
+                    .with_current_span_fields(true),
+            ));
+
+        let span = tracing::info_span!(
+            "request",
+            "turnstile.result" = Empty,
+            "turnstile.reason" = Empty,
+            "turnstile.action" = Empty,
+            "turnstile.hostname" = Empty,
+        );
+        let response = app
+            .oneshot(
+                Request::post("/submit")
+                    .header("CF-Turnstile-Token", "token")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .instrument(span)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let fields = recorder.fields("request");
+        assert_eq!(
+            fields,
+            [
+                ("turnstile.result".to_string(), "verified".to_string()),
+                ("turnstile.action".to_string(), "login".to_string()),
+                (
+                    "turnstile.hostname".to_string(),
+                    "myapp.example".to_string()
+                ),
+            ]
+        );
+        // The outcome went to the caller's span, not to the verify call's own span
+        assert_eq!(recorder.span_names(), ["request", "turnstile.verify"]);
+        assert_eq!(
+            recorder.fields("turnstile.verify"),
+            [("header".to_string(), "CF-Turnstile-Token".to_string())]
+        );
+    }
+}
@@ -0,0 +1,280 @@
+use crate::{Decision, RejectionReason, TurnstileOutcome};
+use std::{
+    fmt::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+/// Upper bounds of the verification latency histogram, in seconds
+const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Running counters of the middleware's decisions
+///
+/// Create one, pass a clone to [`with_stats`](crate::TurnstileConfig::with_stats), and keep
+/// the other to read the counters or serve them with
+/// [`render_prometheus`](Self::render_prometheus). Clones share the same counters.
+#[derive(Clone, Debug, Default)]
+pub struct TurnstileStats {
+    inner: Arc<Counters>,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    verified: AtomicU64,
+    rejected: AtomicU64,
+    monitored: AtomicU64,
+    bypassed: AtomicU64,
+    upstream_errors: AtomicU64,
+    slow_verifications: AtomicU64,
+    latency_buckets: [AtomicU64; LATENCY_BUCKETS.len()],
+    latency_count: AtomicU64,
+    latency_sum_micros: AtomicU64,
+}
+
+impl TurnstileStats {
+    /// Create a handle with every counter at zero
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requests whose token was verified
+    pub fn verified(&self) -> u64 {
+        self.inner.verified.load(Ordering::Relaxed)
+    }
+
+    /// Requests that were rejected
+    pub fn rejected(&self) -> u64 {
+        self.inner.rejected.load(Ordering::Relaxed)
+    }
+
+    /// Requests that would have been rejected but were let through by [`Mode::Monitor`](crate::Mode::Monitor)
+    pub fn monitored(&self) -> u64 {
+        self.inner.monitored.load(Ordering::Relaxed)
+    }
+
+    /// Requests let through without verification, e.g. by an e2e bypass token or a client
+    /// certificate
+    pub fn bypassed(&self) -> u64 {
+        self.inner.bypassed.load(Ordering::Relaxed)
+    }
+
+    /// Requests whose verification failed because Cloudflare couldn't be reached or gave an
+    /// unusable answer
+    pub fn upstream_errors(&self) -> u64 {
+        self.inner.upstream_errors.load(Ordering::Relaxed)
+    }
+
+    /// Verification calls slower than
+    /// [`with_slow_verification_threshold`](crate::TurnstileConfig::with_slow_verification_threshold)
+    pub fn slow_verifications(&self) -> u64 {
+        self.inner.slow_verifications.load(Ordering::Relaxed)
+    }
+
+    /// Count a verification call over the latency threshold
+    pub(crate) fn record_slow_verification(&self) {
+        self.inner
+            .slow_verifications
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count a single decision
+    pub(crate) fn record(&self, outcome: &TurnstileOutcome) {
+        let counters = &self.inner;
+        let (counter, reason) = match &outcome.decision {
+            Decision::Verified => (&counters.verified, None),
+            Decision::Rejected(reason) => (&counters.rejected, Some(reason)),
+            Decision::Monitored(reason) => (&counters.monitored, Some(reason)),
+            _ => (&counters.bypassed, None),
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+
+        if let Some(
+            RejectionReason::ApiError | RejectionReason::EmptyResponse | RejectionReason::Timeout,
+        ) = reason
+        {
+            counters.upstream_errors.fetch_add(1, Ordering::Relaxed);
+        }
+        if outcome.decision == Decision::Degraded {
+            counters.upstream_errors.fetch_add(1, Ordering::Relaxed);
+        }
+
+        if let Some(timings) = &outcome.timings {
+            let seconds = timings.total.as_secs_f64();
+            for (bound, bucket) in LATENCY_BUCKETS.iter().zip(&counters.latency_buckets) {
+                if seconds <= *bound {
+                    bucket.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            counters.latency_count.fetch_add(1, Ordering::Relaxed);
+            counters
+                .latency_sum_micros
+                .fetch_add(timings.total.as_micros() as u64, Ordering::Relaxed);
+        }
+    }
+
+    /// Render the counters in the Prometheus text exposition format
+    ///
+    /// Exposes `turnstile_requests_total` by `decision`, `turnstile_upstream_errors_total`,
+    /// `turnstile_slow_verifications_total` and the `turnstile_verify_duration_seconds` histogram of calls to Cloudflare.
+    pub fn render_prometheus(&self) -> String {
+        let counters = &self.inner;
+        let mut out = String::new();
+
+        out.push_str(
+            "# HELP turnstile_requests_total Requests seen by the Turnstile middleware, by decision\n\
+             # TYPE turnstile_requests_total counter\n",
+        );
+        for (decision, count) in [
+            ("verified", self.verified()),
+            ("rejected", self.rejected()),
+            ("monitored", self.monitored()),
+            ("bypassed", self.bypassed()),
+        ] {
+            let _ = writeln!(
+                out,
+                "turnstile_requests_total{{decision=\"{decision}\"}} {count}"
+            );
+        }
+
+        out.push_str(
+            "# HELP turnstile_upstream_errors_total Verifications that failed to get a usable answer from Cloudflare\n\
+             # TYPE turnstile_upstream_errors_total counter\n",
+        );
+        let _ = writeln!(
+            out,
+            "turnstile_upstream_errors_total {}",
+            self.upstream_errors()
+        );
+
+        out.push_str(
+            "# HELP turnstile_slow_verifications_total Verification calls slower than the configured threshold\n\
+             # TYPE turnstile_slow_verifications_total counter\n",
+        );
+        let _ = writeln!(
+            out,
+            "turnstile_slow_verifications_total {}",
+            self.slow_verifications()
+        );
+
+        out.push_str(
+            "# HELP turnstile_verify_duration_seconds Duration of calls to Cloudflare's siteverify API\n\
+             # TYPE turnstile_verify_duration_seconds histogram\n",
+        );
+        for (bound, bucket) in LATENCY_BUCKETS.iter().zip(&counters.latency_buckets) {
+            let _ = writeln!(
+                out,
+                "turnstile_verify_duration_seconds_bucket{{le=\"{bound}\"}} {}",
+                bucket.load(Ordering::Relaxed)
+            );
+        }
+        let count = counters.latency_count.load(Ordering::Relaxed);
+        let sum = counters.latency_sum_micros.load(Ordering::Relaxed) as f64 / 1e6;
+        let _ = writeln!(
+            out,
+            "turnstile_verify_duration_seconds_bucket{{le=\"+Inf\"}} {count}"
+        );
+        let _ = writeln!(out, "turnstile_verify_duration_seconds_sum {sum}");
+        let _ = writeln!(out, "turnstile_verify_duration_seconds_count {count}");
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_support::MockSiteverify, TurnstileConfig, TurnstileLayer};
+    use axum::{body::Body, http::Request, routing::post, Router};
+    use std::time::Duration;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_render_prometheus() {
+        let mock = MockSiteverify::respond_with(
+            |request| serde_json::json!({ "success": request.json()["response"] == "good-token" }),
+        )
+        .await;
+        let stats = TurnstileStats::new();
+        let app = Router::new()
+            .route("/submit", post(|| async { "OK" }))
+            .layer(TurnstileLayer::new(
+                TurnstileConfig::new("secret")
+                    .with_verify_url(&mock.url)
+                    .with_stats(stats.clone()),
+            ));
+
+        for token in [
+            Some("good-token"),
+            Some("good-token"),
+            Some("bad-token"),
+            None,
+        ] {
+            let mut request = Request::post("/submit");
+            if let Some(token) = token {
+                request = request.header("CF-Turnstile-Token", token);
+            }
+            app.clone()
+                .oneshot(request.body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+        }
+
+        let rendered = stats.render_prometheus();
+        for line in [
+            "# TYPE turnstile_requests_total counter",
+            "turnstile_requests_total{decision=\"verified\"} 2",
+            "turnstile_requests_total{decision=\"rejected\"} 2",
+            "turnstile_requests_total{decision=\"bypassed\"} 0",
+            "turnstile_upstream_errors_total 0",
+            "# TYPE turnstile_verify_duration_seconds histogram",
+            "turnstile_verify_duration_seconds_bucket{le=\"10\"} 3",
+            "turnstile_verify_duration_seconds_bucket{le=\"+Inf\"} 3",
+            "turnstile_verify_duration_seconds_count 3",
+        ] {
+            assert!(
+                rendered.lines().any(|l| l == line),
+                "missing {line:?} in:\n{rendered}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_slow_verification_counted() {
+        let mock = MockSiteverify::success().await;
+        mock.delay_with(|index| match index {
+            0 => Duration::from_millis(300),
+            _ => Duration::ZERO,
+        });
+        let stats = TurnstileStats::new();
+        let app = Router::new()
+            .route("/submit", post(|| async { "OK" }))
+            .layer(TurnstileLayer::new(
+                TurnstileConfig::new("secret")
+                    .with_verify_url(&mock.url)
+                    .with_slow_verification_threshold(Duration::from_millis(150))
+                    .with_stats(stats.clone()),
+            ));
+        let send = || {
+            app.clone().oneshot(
+                Request::post("/submit")
+                    .header("CF-Turnstile-Token", "token")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+        };
+
+        send().await.unwrap();
+        assert_eq!(stats.slow_verifications(), 1);
+
+        send().await.unwrap();
+        assert_eq!(stats.slow_verifications(), 1);
+        assert_eq!(stats.verified(), 2);
+        assert!(stats
+            .render_prometheus()
+            .contains("turnstile_slow_verifications_total 1\n"));
+    }
+}
@@ -0,0 +1,142 @@
+use crate::expiring::ExpiringMap;
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// How long a client IP's failures count towards its next delay
+const FAILURE_WINDOW: Duration = Duration::from_secs(15 * 60);
+
+/// Delays responses to repeated verification failures from the same client IP
+#[derive(Debug)]
+pub(crate) struct Tarpit {
+    base: Duration,
+    max: Duration,
+    failures: Mutex<ExpiringMap<String, Failures>>,
+}
+
+#[derive(Debug)]
+struct Failures {
+    count: u32,
+    last: Instant,
+}
+
+impl Tarpit {
+    pub fn new(base: Duration, max: Duration) -> Self {
+        Self {
+            base,
+            max,
+            failures: Mutex::new(ExpiringMap::new()),
+        }
+    }
+
+    /// Count a failure from `ip` and return how long to hold its response
+    ///
+    /// The delay doubles with every failure inside the window, up to `max`.
+    pub fn failure(&self, ip: &str) -> Duration {
+        self.failure_at(ip, Instant::now())
+    }
+
+    fn failure_at(&self, ip: &str, now: Instant) -> Duration {
+        let mut failures = self.failures.lock().unwrap();
+        failures.prune(|_, failures| now.duration_since(failures.last) < FAILURE_WINDOW);
+
+        let failures = failures.entry(ip.to_string()).or_insert(Failures {
+            count: 0,
+            last: now,
+        });
+        // Failures outside the window may not have been pruned yet
+        if now.duration_since(failures.last) >= FAILURE_WINDOW {
+            failures.count = 0;
+        }
+        failures.count = failures.count.saturating_add(1);
+        failures.last = now;
+
+        let factor = 2u32.saturating_pow(failures.count - 1);
+        self.base.saturating_mul(factor).min(self.max)
+    }
+
+    /// Forget the failures of `ip` after it verified successfully
+    pub fn success(&self, ip: &str) {
+        self.failures.lock().unwrap().remove(ip);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_support::MockSiteverify, TurnstileConfig, TurnstileLayer};
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        routing::post,
+        Router,
+    };
+    use tower::ServiceExt;
+
+    #[test]
+    fn test_delay_doubles_up_to_max() {
+        let tarpit = Tarpit::new(Duration::from_millis(100), Duration::from_millis(300));
+        let delays: Vec<_> = (0..4).map(|_| tarpit.failure("203.0.113.7")).collect();
+        assert_eq!(
+            delays,
+            [100, 200, 300, 300].map(Duration::from_millis).to_vec()
+        );
+
+        assert_eq!(tarpit.failure("198.51.100.4"), Duration::from_millis(100));
+        tarpit.success("203.0.113.7");
+        assert_eq!(tarpit.failure("203.0.113.7"), Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_failures_outside_window_no_longer_count() {
+        let tarpit = Tarpit::new(Duration::from_millis(100), Duration::from_secs(10));
+        let start = Instant::now();
+        tarpit.failure_at("203.0.113.7", start);
+        tarpit.failure_at("203.0.113.7", start);
+
+        let later = start + FAILURE_WINDOW;
+        assert_eq!(
+            tarpit.failure_at("203.0.113.7", later),
+            Duration::from_millis(100)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_repeated_failures_are_delayed_longer() {
+        let mock = MockSiteverify::start(serde_json::json!({
+            "success": false,
+            "error-codes": ["invalid-input-response"],
+        }))
+        .await;
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&mock.url)
+            .with_remote_ip_header("CF-Connecting-IP")
+            .with_failure_tarpit(Duration::from_millis(100), Duration::from_secs(1));
+        let app = Router::new()
+            .route("/submit", post(|| async { "OK" }))
+            .layer(TurnstileLayer::new(config));
+
+        let mut elapsed = Vec::new();
+        for _ in 0..2 {
+            let started = Instant::now();
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::post("/submit")
+                        .header("CF-Turnstile-Token", "guess")
+                        .header("CF-Connecting-IP", "203.0.113.7")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+            elapsed.push(started.elapsed());
+        }
+
+        assert!(elapsed[0] >= Duration::from_millis(100));
+        assert!(elapsed[1] >= Duration::from_millis(200));
+        assert!(elapsed[1] > elapsed[0]);
+    }
+}
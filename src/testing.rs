@@ -0,0 +1,225 @@
+//! A local stand-in for Cloudflare's siteverify endpoint, for testing handlers behind the
+//! layer
+//!
+//! Enabled by the `testing` feature, meant for your dev-dependencies:
+//!
+//! ```toml
+//! [dev-dependencies]
+//! axum-turnstile = { version = "0.2", features = ["testing"] }
+//! ```
+//!
+//! [`MockTurnstile`] answers every verification with a [`VerifierResponse`] you choose and
+//! records the tokens it was asked about. [`MockTurnstile::config`] builds a
+//! [`TurnstileConfig`] pointed at it, so the whole middleware runs as in production,
+//! including the HTTP call. To skip the network entirely, use a
+//! [`Verifier`](crate::Verifier) instead.
+//!
+//! ```rust
+//! use axum::{body::Body, http::{Request, StatusCode}, routing::post, Router};
+//! use axum_turnstile::{testing::MockTurnstile, TurnstileLayer, VerifierResponse};
+//! use tower::ServiceExt;
+//!
+//! # #[tokio::main]
+//! # async fn main() {
+//! let mock = MockTurnstile::start(VerifierResponse::success()).await;
+//! let app = Router::new()
+//!     .route("/submit", post(|| async { "OK" }))
+//!     .layer(TurnstileLayer::new(mock.config()));
+//!
+//! let request = Request::post("/submit")
+//!     .header("CF-Turnstile-Token", "token")
+//!     .body(Body::empty())
+//!     .unwrap();
+//! let response = app.oneshot(request).await.unwrap();
+//! assert_eq!(response.status(), StatusCode::OK);
+//! assert_eq!(mock.tokens(), ["token"]);
+//! # }
+//! ```
+
+use crate::{TurnstileConfig, VerifierResponse};
+use axum::{body::Bytes, extract::State, http::HeaderMap, routing::post, Json, Router};
+use std::sync::{Arc, Mutex};
+use tokio::task::JoinHandle;
+
+/// Secret of the configs built by [`MockTurnstile::config`]
+const MOCK_SECRET: &str = "mock-secret";
+
+#[derive(Clone, Default)]
+struct MockState {
+    response: Arc<Mutex<VerifierResponse>>,
+    tokens: Arc<Mutex<Vec<String>>>,
+}
+
+/// A mock siteverify server listening on a random local port
+///
+/// The server stops when the mock is dropped.
+pub struct MockTurnstile {
+    url: String,
+    state: MockState,
+    server: JoinHandle<()>,
+}
+
+impl MockTurnstile {
+    /// Start a mock that answers every verification with `response`
+    pub async fn start(response: VerifierResponse) -> Self {
+        let state = MockState {
+            response: Arc::new(Mutex::new(response)),
+            tokens: Arc::default(),
+        };
+        let app = Router::new()
+            .route("/siteverify", post(handle))
+            .with_state(state.clone());
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("bind mock siteverify server");
+        let addr = listener.local_addr().expect("mock siteverify address");
+        let server = tokio::spawn(async move {
+            let _ = axum::serve(listener, app).await;
+        });
+
+        Self {
+            url: format!("http://{addr}/siteverify"),
+            state,
+            server,
+        }
+    }
+
+    /// Start a mock that accepts every token
+    pub async fn passing() -> Self {
+        Self::start(VerifierResponse::success()).await
+    }
+
+    /// Start a mock that rejects every token with `invalid-input-response`
+    pub async fn failing() -> Self {
+        Self::start(VerifierResponse::failure(["invalid-input-response"])).await
+    }
+
+    /// URL of the mock's siteverify endpoint
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// A config verifying against this mock, to customize like any other
+    pub fn config(&self) -> TurnstileConfig {
+        TurnstileConfig::new(MOCK_SECRET).with_verify_url(&self.url)
+    }
+
+    /// Answer later verifications with `response` instead
+    pub fn set_response(&self, response: VerifierResponse) {
+        *self.state.response.lock().unwrap() = response;
+    }
+
+    /// Tokens received so far, in arrival order
+    pub fn tokens(&self) -> Vec<String> {
+        self.state.tokens.lock().unwrap().clone()
+    }
+}
+
+impl Drop for MockTurnstile {
+    fn drop(&mut self) {
+        self.server.abort();
+    }
+}
+
+async fn handle(
+    State(state): State<MockState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Json<serde_json::Value> {
+    let form = headers
+        .get("content-type")
+        .is_some_and(|value| value == "application/x-www-form-urlencoded");
+    let token = if form {
+        form_urlencoded::parse(&body)
+            .find(|(name, _)| name == "response")
+            .map(|(_, value)| value.into_owned())
+    } else {
+        serde_json::from_slice::<serde_json::Value>(&body)
+            .ok()
+            .and_then(|body| body["response"].as_str().map(str::to_owned))
+    };
+    state.tokens.lock().unwrap().extend(token);
+
+    let response = state.response.lock().unwrap().clone();
+    let mut json = serde_json::json!({
+        "success": response.success,
+        "error-codes": response.error_codes,
+    });
+    for (name, value) in [
+        ("challenge_ts", response.challenge_ts),
+        ("hostname", response.hostname),
+        ("action", response.action),
+        ("cdata", response.cdata),
+    ] {
+        if let Some(value) = value {
+            json[name] = value.into();
+        }
+    }
+    if let Some(metadata) = response.metadata {
+        json["metadata"] = serde_json::json!({ "ephemeral_id": metadata.ephemeral_id });
+    }
+    Json(json)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{RequestEncoding, TurnstileLayer, VerifiedTurnstile};
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+    };
+    use tower::ServiceExt;
+
+    fn app(config: TurnstileConfig) -> Router {
+        Router::new()
+            .route(
+                "/login",
+                post(|verified: VerifiedTurnstile| async move {
+                    verified.action.unwrap_or_default()
+                }),
+            )
+            .layer(TurnstileLayer::new(config))
+    }
+
+    fn login(token: &str) -> Request<Body> {
+        Request::post("/login")
+            .header("CF-Turnstile-Token", token)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_mock_success_and_failure_flows() {
+        let mock = MockTurnstile::start(VerifierResponse {
+            action: Some("login".to_string()),
+            ..VerifierResponse::success()
+        })
+        .await;
+        let app = app(mock.config().with_expected_action("login"));
+
+        let response = app.clone().oneshot(login("good")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], b"login");
+
+        mock.set_response(VerifierResponse::failure(["timeout-or-duplicate"]));
+        let response = app.oneshot(login("spent")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        assert_eq!(mock.tokens(), ["good", "spent"]);
+    }
+
+    #[tokio::test]
+    async fn test_mock_reads_json_requests() {
+        let mock = MockTurnstile::failing().await;
+        let config = mock.config().with_request_encoding(RequestEncoding::Json);
+
+        let response = app(config).oneshot(login("token")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(mock.tokens(), ["token"]);
+    }
+}
@@ -0,0 +1,843 @@
+use crate::{
+    body::{self, BufferError},
+    TurnstileConfig,
+};
+use axum::{
+    body::Body,
+    http::{header::AUTHORIZATION, HeaderMap, Request},
+};
+
+/// Where the middleware looks for the Turnstile token
+///
+/// Body sources hand on the exact bytes they buffered, with the request's `Content-Length`
+/// and `Transfer-Encoding` headers as they arrived, so the inner service can't tell the body
+/// was read.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum TokenSource {
+    /// Read the token from the configured header (default)
+    ///
+    /// The token is verified before any of the body is read, and the body is then streamed
+    /// to the inner service untouched, so large uploads are never buffered and a failing
+    /// token costs no upload bandwidth.
+    #[default]
+    Header,
+    /// Read the token from an `Authorization: Bearer <token>` header
+    ///
+    /// The scheme is matched case-insensitively. An `Authorization` header with another
+    /// scheme, or no token after it, makes the token malformed rather than missing. Like
+    /// [`Header`](Self::Header), the body is streamed untouched.
+    AuthorizationBearer,
+    /// Read the token from a parameter of the URL query, such as `cf-turnstile-response` in
+    /// `/ws?cf-turnstile-response=...`
+    ///
+    /// Suits WebSocket upgrades, where browsers can't set custom headers: the body is never
+    /// touched, so the upgrade goes through untouched as well. The value is percent-decoded,
+    /// and a parameter that is there but empty makes the token malformed rather than missing.
+    QueryParam(String),
+    /// Read the token from a JSON request body using an
+    /// [RFC 6901](https://www.rfc-editor.org/rfc/rfc6901) pointer such as `/meta/captcha/token`
+    ///
+    /// The body is buffered to find the token and handed to the inner service unchanged, so
+    /// handlers can still extract it with `Json`. A top-level field is a pointer like
+    /// `/cf-turnstile-response`.
+    JsonPointer(String),
+    /// Read the token from a field of an `application/x-www-form-urlencoded` body, like the
+    /// `cf-turnstile-response` field the widget adds to HTML forms
+    ///
+    /// The body is buffered to find the token and handed to the inner service unchanged, so
+    /// handlers can still extract it with `Form`.
+    FormField(String),
+    /// Read the token from a request trailer, as sent after a chunked body
+    ///
+    /// The body is buffered, up to [`TurnstileConfig::max_body_bytes`], to reach the trailers
+    /// and handed to the inner service unchanged, trailers included. A request without the
+    /// trailer is treated like one without a token.
+    Trailer(String),
+}
+
+/// Hex-encoded SHA-256 of a token, so raw tokens are never kept around
+pub(crate) fn hash_token(token: &str) -> String {
+    let digest = ring::digest::digest(&ring::digest::SHA256, token.as_bytes());
+    digest.as_ref().iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Undo common templating mistakes: surrounding whitespace, one pair of surrounding quotes
+/// and percent-encoding
+pub(crate) fn sanitize(token: &str) -> String {
+    let token = token.trim();
+    let token = ['"', '\'']
+        .iter()
+        .find_map(|quote| token.strip_prefix(*quote)?.strip_suffix(*quote))
+        .unwrap_or(token);
+    percent_decode(token)
+}
+
+/// Decode `%XX` escapes, leaving malformed ones as they are
+fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| input.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).unwrap_or_else(|_| input.to_string())
+}
+
+/// Compare two byte strings without short-circuiting on the first difference
+///
+/// Only the length can leak through timing.
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Why no token could be extracted
+pub(crate) enum TokenError {
+    /// The source didn't contain a token
+    Missing,
+    /// The token was there, but empty or not valid UTF-8
+    Malformed,
+    /// The body exceeded [`TurnstileConfig::max_body_bytes`]; the request body is intact
+    TooLarge,
+}
+
+/// Extract the token from the request according to the configured [`TokenSource`]
+///
+/// Returns the request (with its body restored if it had to be buffered) and the token.
+pub(crate) async fn extract_token(
+    req: Request<Body>,
+    config: &TurnstileConfig,
+) -> (Request<Body>, Result<String, TokenError>) {
+    match &config.token_source {
+        TokenSource::Header => {
+            let names = std::iter::once(&config.header_name).chain(&config.fallback_header_names);
+            let token = from_headers(req.headers(), names);
+            (req, token)
+        }
+        TokenSource::AuthorizationBearer => {
+            let token = from_bearer(req.headers());
+            (req, token)
+        }
+        TokenSource::QueryParam(name) => {
+            let token = from_query(req.uri().query(), name);
+            (req, token)
+        }
+        TokenSource::JsonPointer(pointer) => {
+            from_body(req, config, |bytes| {
+                let json = serde_json::from_slice::<serde_json::Value>(bytes).ok()?;
+                json.pointer(pointer)?.as_str().map(str::to_owned)
+            })
+            .await
+        }
+        TokenSource::FormField(name) => {
+            from_body(req, config, |bytes| {
+                form_urlencoded::parse(bytes)
+                    .find(|(field, _)| field == name)
+                    .map(|(_, value)| value.into_owned())
+            })
+            .await
+        }
+        TokenSource::Trailer(name) => {
+            let (mut parts, body) = req.into_parts();
+            let (bytes, trailers) =
+                match body::buffer_with_trailers(&parts.headers, body, config.max_body_bytes).await
+                {
+                    Ok(buffered) => buffered,
+                    Err(BufferError::TooLarge(body)) => {
+                        return (Request::from_parts(parts, body), Err(TokenError::TooLarge));
+                    }
+                    Err(BufferError::Read) => {
+                        return (
+                            Request::from_parts(parts, Body::empty()),
+                            Err(TokenError::Missing),
+                        );
+                    }
+                };
+
+            let token = match &trailers {
+                Some(trailers) => from_headers(trailers, [name]),
+                None => Err(TokenError::Missing),
+            };
+
+            let body = body::restore(&mut parts.headers, bytes, trailers, config.consume_body);
+            (Request::from_parts(parts, body), token)
+        }
+    }
+}
+
+/// The first non-empty, valid UTF-8 value of the named headers
+///
+/// Headers that are there but unusable make the token malformed rather than missing.
+fn from_headers<'a>(
+    headers: &HeaderMap,
+    names: impl IntoIterator<Item = &'a String>,
+) -> Result<String, TokenError> {
+    let mut error = TokenError::Missing;
+    for value in names.into_iter().filter_map(|name| headers.get(name)) {
+        match value.to_str() {
+            Ok(token) if !token.is_empty() => return Ok(token.to_owned()),
+            _ => error = TokenError::Malformed,
+        }
+    }
+    Err(error)
+}
+
+/// The token of an `Authorization: Bearer` header
+fn from_bearer(headers: &HeaderMap) -> Result<String, TokenError> {
+    let value = headers.get(AUTHORIZATION).ok_or(TokenError::Missing)?;
+    let (scheme, token) = value
+        .to_str()
+        .ok()
+        .and_then(|value| value.split_once(' '))
+        .ok_or(TokenError::Malformed)?;
+    let token = token.trim();
+    if !scheme.eq_ignore_ascii_case("bearer") || token.is_empty() {
+        return Err(TokenError::Malformed);
+    }
+    Ok(token.to_owned())
+}
+
+/// The first value of the named query parameter
+fn from_query(query: Option<&str>, name: &str) -> Result<String, TokenError> {
+    let (_, token) = form_urlencoded::parse(query.unwrap_or_default().as_bytes())
+        .find(|(param, _)| param == name)
+        .ok_or(TokenError::Missing)?;
+    if token.is_empty() {
+        return Err(TokenError::Malformed);
+    }
+    Ok(token.into_owned())
+}
+
+/// Buffer the body and find the token in it
+///
+/// An empty token, e.g. from a form submitted before the widget was solved, is malformed.
+async fn from_body(
+    req: Request<Body>,
+    config: &TurnstileConfig,
+    find: impl FnOnce(&[u8]) -> Option<String>,
+) -> (Request<Body>, Result<String, TokenError>) {
+    let (mut parts, body) = req.into_parts();
+    let bytes = match body::buffer(&parts.headers, body, config.max_body_bytes).await {
+        Ok(bytes) => bytes,
+        Err(BufferError::TooLarge(body)) => {
+            return (Request::from_parts(parts, body), Err(TokenError::TooLarge));
+        }
+        Err(BufferError::Read) => {
+            return (
+                Request::from_parts(parts, Body::empty()),
+                Err(TokenError::Missing),
+            );
+        }
+    };
+
+    let token = match find(&bytes) {
+        Some(token) if token.is_empty() => Err(TokenError::Malformed),
+        Some(token) => Ok(token),
+        None => Err(TokenError::Missing),
+    };
+    let body = body::restore(&mut parts.headers, bytes, None, config.consume_body);
+    (Request::from_parts(parts, body), token)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{test_support::MockSiteverify, TokenSource, TurnstileConfig, TurnstileLayer};
+    use axum::{
+        body::Body,
+        http::{HeaderValue, Request, StatusCode, Version},
+        routing::{get, post},
+        Form, Json, Router,
+    };
+    use futures_util::StreamExt;
+    use std::{
+        collections::HashMap,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+    };
+    use tower::ServiceExt;
+
+    const CHUNK: usize = 64 * 1024;
+    const CHUNKS: usize = 128;
+
+    /// An 8 MiB body that counts how many chunks have been pulled from it
+    fn counted_body(pulled: Arc<AtomicUsize>) -> Body {
+        let chunks = futures_util::stream::iter(0..CHUNKS).map(move |_| {
+            pulled.fetch_add(1, Ordering::SeqCst);
+            Ok::<_, std::io::Error>(axum::body::Bytes::from(vec![b'x'; CHUNK]))
+        });
+        Body::from_stream(chunks)
+    }
+
+    async fn upload(mock: &MockSiteverify, pulled: Arc<AtomicUsize>) -> (StatusCode, String) {
+        let pulled_by_handler = pulled.clone();
+        let app = Router::new()
+            .route(
+                "/upload",
+                post(|req: axum::extract::Request| async move {
+                    // Nothing was read before the handler got the body
+                    let before = pulled_by_handler.load(Ordering::SeqCst);
+                    let mut stream = req.into_body().into_data_stream();
+                    let mut len = 0;
+                    while let Some(chunk) = stream.next().await {
+                        len += chunk.unwrap().len();
+                    }
+                    format!("before={before} len={len}")
+                }),
+            )
+            .layer(TurnstileLayer::new(
+                TurnstileConfig::new("test-secret").with_verify_url(&mock.url),
+            ));
+
+        let response = app
+            .oneshot(
+                Request::post("/upload")
+                    .header("CF-Turnstile-Token", "token")
+                    .body(counted_body(pulled))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[test]
+    fn test_sanitize() {
+        assert_eq!(super::sanitize("  \"0.abc-DEF_1\"\n"), "0.abc-DEF_1");
+        assert_eq!(super::sanitize("'0.abc'"), "0.abc");
+        assert_eq!(super::sanitize("0.abc%2Bdef%3D%3D"), "0.abc+def==");
+        // Only one matching pair of quotes is stripped, and malformed escapes are kept
+        assert_eq!(super::sanitize("\"'0.abc'\""), "'0.abc'");
+        assert_eq!(super::sanitize("\"0.abc'"), "\"0.abc'");
+        assert_eq!(super::sanitize("0.abc%2"), "0.abc%2");
+    }
+
+    #[tokio::test]
+    async fn test_sanitizer_fixes_quoted_and_encoded_tokens() {
+        let mock = MockSiteverify::respond_with(
+            |request| serde_json::json!({ "success": request.json()["response"] == "0.abc+def=" }),
+        )
+        .await;
+
+        for (sanitize, token, expected) in [
+            (true, "\"0.abc+def=\"", StatusCode::OK),
+            (true, "0.abc%2Bdef%3D", StatusCode::OK),
+            (false, "\"0.abc+def=\"", StatusCode::FORBIDDEN),
+        ] {
+            let config = TurnstileConfig::new("test-secret")
+                .with_verify_url(&mock.url)
+                .with_token_sanitizer(sanitize);
+            let response = app(config)
+                .oneshot(
+                    Request::post("/submit")
+                        .header("CF-Turnstile-Token", token)
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), expected, "token {token}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_header_token_streams_body_unbuffered() {
+        let mock = MockSiteverify::success().await;
+        let pulled = Arc::new(AtomicUsize::new(0));
+
+        let (status, body) = upload(&mock, pulled.clone()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, format!("before=0 len={}", CHUNK * CHUNKS));
+        assert_eq!(pulled.load(Ordering::SeqCst), CHUNKS);
+    }
+
+    #[tokio::test]
+    async fn test_header_token_failure_reads_no_body() {
+        let mock = MockSiteverify::start(serde_json::json!({ "success": false })).await;
+        let pulled = Arc::new(AtomicUsize::new(0));
+
+        let (status, _) = upload(&mock, pulled.clone()).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(pulled.load(Ordering::SeqCst), 0);
+    }
+
+    fn app(config: TurnstileConfig) -> Router {
+        Router::new()
+            .route("/submit", post(|body: String| async move { body }))
+            .layer(TurnstileLayer::new(config))
+    }
+
+    #[tokio::test]
+    async fn test_sec_prefixed_header_name() {
+        let mock = MockSiteverify::success().await;
+        let config = TurnstileConfig::new("test-secret")
+            .with_verify_url(&mock.url)
+            .with_header_name("Sec-Turnstile");
+
+        for (header, expected) in [
+            ("sec-turnstile", StatusCode::OK),
+            ("CF-Turnstile-Token", StatusCode::BAD_REQUEST),
+        ] {
+            let response = app(config.clone())
+                .oneshot(
+                    Request::post("/submit")
+                        .header(header, "extension-token")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), expected, "header {header}");
+        }
+        assert_eq!(mock.requests()[0].json()["response"], "extension-token");
+    }
+
+    #[tokio::test]
+    async fn test_fallback_header_names() {
+        let mock = MockSiteverify::success().await;
+        let config = TurnstileConfig::new("test-secret")
+            .with_verify_url(&mock.url)
+            .with_header_names(vec!["CF-Turnstile-Token".into(), "X-Captcha".into()]);
+
+        for (headers, expected) in [
+            (vec![("X-Captcha", "legacy-token")], StatusCode::OK),
+            // An empty primary header falls through to the next one
+            (
+                vec![("CF-Turnstile-Token", ""), ("X-Captcha", "legacy-token")],
+                StatusCode::OK,
+            ),
+            (vec![("X-Other", "token")], StatusCode::BAD_REQUEST),
+        ] {
+            let mut request = Request::post("/submit");
+            for (name, value) in &headers {
+                request = request.header(*name, *value);
+            }
+            let response = app(config.clone())
+                .oneshot(request.body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(response.status(), expected, "{headers:?}");
+        }
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests
+            .iter()
+            .all(|request| request.json()["response"] == "legacy-token"));
+    }
+
+    #[tokio::test]
+    async fn test_unusable_header_is_malformed_not_missing() {
+        let config = TurnstileConfig::new("test-secret");
+
+        for (value, expected) in [
+            (
+                Some(HeaderValue::from_static("")),
+                "Malformed Turnstile token",
+            ),
+            (
+                Some(HeaderValue::from_bytes(b"tok\xffen").unwrap()),
+                "Malformed Turnstile token",
+            ),
+            (None, "Missing Turnstile token"),
+        ] {
+            let mut request = Request::post("/submit");
+            if let Some(value) = &value {
+                request = request.header("CF-Turnstile-Token", value);
+            }
+            let response = app(config.clone())
+                .oneshot(request.body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert_eq!(body, expected, "{value:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_authorization_bearer_token() {
+        let mock = MockSiteverify::success().await;
+        let config = TurnstileConfig::new("test-secret")
+            .with_verify_url(&mock.url)
+            .with_token_source(TokenSource::AuthorizationBearer);
+
+        for (value, expected) in [
+            (Some("Bearer bearer-token"), "OK"),
+            (Some("bearer  bearer-token"), "OK"),
+            (Some("Basic dXNlcjpwYXNz"), "Malformed Turnstile token"),
+            (Some("Bearer"), "Malformed Turnstile token"),
+            (Some("Bearer "), "Malformed Turnstile token"),
+            (None, "Missing Turnstile token"),
+        ] {
+            let mut request = Request::post("/submit");
+            if let Some(value) = value {
+                request = request.header("Authorization", value);
+            }
+            let response = app(config.clone())
+                .oneshot(request.body(Body::from("OK")).unwrap())
+                .await
+                .unwrap();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert_eq!(body, expected, "{value:?}");
+        }
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests
+            .iter()
+            .all(|r| r.json()["response"] == "bearer-token"));
+    }
+
+    #[tokio::test]
+    async fn test_query_param_token_on_websocket_upgrade() {
+        let mock = MockSiteverify::success().await;
+        let app = Router::new()
+            .route("/ws", get(|| async { "OK" }))
+            .layer(TurnstileLayer::new(
+                TurnstileConfig::new("test-secret")
+                    .with_verify_url(&mock.url)
+                    .with_token_source(TokenSource::QueryParam("cf-turnstile-response".into())),
+            ));
+
+        for (uri, expected) in [
+            ("/ws?cf-turnstile-response=query%2Ftoken+1", "OK"),
+            (
+                "/ws?room=1&cf-turnstile-response=second&cf-turnstile-response=x",
+                "OK",
+            ),
+            ("/ws?cf-turnstile-response=", "Malformed Turnstile token"),
+            ("/ws?room=1", "Missing Turnstile token"),
+            ("/ws", "Missing Turnstile token"),
+        ] {
+            let request = Request::get(uri)
+                .header("Connection", "upgrade")
+                .header("Upgrade", "websocket")
+                .header("Sec-WebSocket-Version", "13")
+                .header("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ==")
+                .body(Body::empty())
+                .unwrap();
+            let response = app.clone().oneshot(request).await.unwrap();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert_eq!(body, expected, "{uri}");
+        }
+        let tokens: Vec<_> = mock
+            .requests()
+            .iter()
+            .map(|r| r.json()["response"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(tokens, ["query/token 1", "second"]);
+    }
+
+    #[tokio::test]
+    async fn test_json_pointer_nested_token() {
+        let mock = MockSiteverify::success().await;
+        let config = TurnstileConfig::new("test-secret")
+            .with_verify_url(&mock.url)
+            .with_token_source(TokenSource::JsonPointer("/meta/captcha/token".into()));
+
+        let payload = r#"{"meta":{"captcha":{"token":"nested-token"}},"name":"ferris"}"#;
+        let response = app(config)
+            .oneshot(
+                Request::post("/submit")
+                    .header("content-type", "application/json")
+                    .body(Body::from(payload))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, payload.as_bytes());
+        assert_eq!(mock.requests()[0].json()["response"], "nested-token");
+    }
+
+    #[tokio::test]
+    async fn test_form_field_token() {
+        let mock = MockSiteverify::success().await;
+        let config = TurnstileConfig::new("test-secret")
+            .with_verify_url(&mock.url)
+            .with_token_source(TokenSource::FormField("cf-turnstile-response".into()));
+
+        let payload = "name=ferris+crab&cf-turnstile-response=form%2Ftoken&email=a%40b.c";
+        let response = app(config)
+            .oneshot(
+                Request::post("/submit")
+                    .header("content-type", "application/x-www-form-urlencoded")
+                    .body(Body::from(payload))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, payload.as_bytes());
+        assert_eq!(mock.requests()[0].json()["response"], "form/token");
+    }
+
+    #[tokio::test]
+    async fn test_handler_reparses_body_the_token_came_from() {
+        let mock = MockSiteverify::success().await;
+        let layer = |source| {
+            TurnstileLayer::new(
+                TurnstileConfig::new("test-secret")
+                    .with_verify_url(&mock.url)
+                    .with_token_source(source),
+            )
+        };
+        let app = Router::new()
+            .route(
+                "/form",
+                post(|Form(form): Form<HashMap<String, String>>| async move {
+                    format!("{} {}", form["name"], form["cf-turnstile-response"])
+                })
+                .layer(layer(TokenSource::FormField(
+                    "cf-turnstile-response".into(),
+                ))),
+            )
+            .route(
+                "/json",
+                post(|Json(json): Json<serde_json::Value>| async move {
+                    format!("{} {}", json["name"], json["token"])
+                })
+                .layer(layer(TokenSource::JsonPointer("/token".into()))),
+            );
+
+        for (path, content_type, payload, expected) in [
+            (
+                "/form",
+                "application/x-www-form-urlencoded",
+                "name=ferris&cf-turnstile-response=form-token",
+                "ferris form-token",
+            ),
+            (
+                "/json",
+                "application/json",
+                r#"{"name":"ferris","token":"json-token"}"#,
+                r#""ferris" "json-token""#,
+            ),
+        ] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::post(path)
+                        .header("content-type", content_type)
+                        .body(Body::from(payload))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::OK, "{path}");
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert_eq!(body, expected.as_bytes());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_consume_body_hands_on_empty_body() {
+        let mock = MockSiteverify::success().await;
+        let config = TurnstileConfig::new("test-secret")
+            .with_verify_url(&mock.url)
+            .with_token_source(TokenSource::JsonPointer("/token".into()))
+            .with_consume_body(true);
+
+        let response = app(config)
+            .oneshot(
+                Request::post("/submit")
+                    .body(Body::from(r#"{"token":"body-token","name":"ferris"}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(body.is_empty());
+        assert_eq!(mock.requests()[0].json()["response"], "body-token");
+    }
+
+    #[tokio::test]
+    async fn test_header_token_over_http2() {
+        use hyper_util::rt::{TokioExecutor, TokioIo};
+
+        let mock = MockSiteverify::success().await;
+        let app = Router::new()
+            .route("/submit", post(|| async { "OK" }))
+            .layer(TurnstileLayer::new(
+                TurnstileConfig::new("test-secret").with_verify_url(&mock.url),
+            ));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let (mut sender, connection) =
+            hyper::client::conn::http2::handshake(TokioExecutor::new(), TokioIo::new(stream))
+                .await
+                .unwrap();
+        tokio::spawn(connection);
+
+        // HTTP/2 sends every header name lowercased
+        let request = Request::post(format!("http://{addr}/submit"))
+            .header("cf-turnstile-token", "h2-token")
+            .body(Body::empty())
+            .unwrap();
+        let response = sender.send_request(request).await.unwrap();
+
+        assert_eq!(response.version(), Version::HTTP_2);
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(mock.requests()[0].json()["response"], "h2-token");
+    }
+
+    /// Send a raw HTTP/1.1 request to a server running `app`, returning the status line
+    async fn send_raw(app: Router, request: &str) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn test_trailer_token_after_chunked_body() {
+        let mock = MockSiteverify::success().await;
+        let config = TurnstileConfig::new("test-secret")
+            .with_verify_url(&mock.url)
+            .with_token_source(TokenSource::Trailer("CF-Turnstile-Token".into()));
+        let app = Router::new()
+            .route(
+                "/submit",
+                post(|req: axum::extract::Request| async move {
+                    use http_body_util::BodyExt;
+                    let collected = req.into_body().collect().await.unwrap();
+                    let trailer = collected.trailers().unwrap()["cf-turnstile-token"].clone();
+                    let body = collected.to_bytes();
+                    format!(
+                        "{} {}",
+                        String::from_utf8_lossy(&body),
+                        trailer.to_str().unwrap()
+                    )
+                }),
+            )
+            .layer(TurnstileLayer::new(config));
+
+        let head = "POST /submit HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\
+                    Transfer-Encoding: chunked\r\nTrailer: CF-Turnstile-Token\r\n\r\n\
+                    5\r\nhello\r\n6\r\n world\r\n";
+        let response = send_raw(
+            app.clone(),
+            &format!("{head}0\r\nCF-Turnstile-Token: trailer-token\r\n\r\n"),
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+        assert!(
+            response.ends_with("hello world trailer-token"),
+            "{response}"
+        );
+        assert_eq!(mock.requests()[0].json()["response"], "trailer-token");
+
+        let response = send_raw(app, &format!("{head}0\r\n\r\n")).await;
+        assert!(response.starts_with("HTTP/1.1 400"), "{response}");
+        assert_eq!(mock.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_json_pointer_missing_or_non_string() {
+        for payload in [
+            r#"{"meta":{}}"#,
+            r#"{"meta":{"captcha":{"token":42}}}"#,
+            "not json",
+        ] {
+            let config = TurnstileConfig::new("test-secret")
+                .with_token_source(TokenSource::JsonPointer("/meta/captcha/token".into()));
+
+            let response = app(config)
+                .oneshot(Request::post("/submit").body(Body::from(payload)).unwrap())
+                .await
+                .unwrap();
+
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_empty_body_token_is_malformed() {
+        let cases = [
+            (
+                TokenSource::FormField("cf-turnstile-response".into()),
+                "application/x-www-form-urlencoded",
+                "name=a&cf-turnstile-response=",
+            ),
+            (
+                TokenSource::JsonPointer("/token".into()),
+                "application/json",
+                r#"{"token": ""}"#,
+            ),
+        ];
+        for (source, content_type, payload) in cases {
+            let mock = MockSiteverify::start(serde_json::json!({
+                "success": false,
+                "error-codes": ["missing-input-response"],
+            }))
+            .await;
+            let config = TurnstileConfig::new("test-secret")
+                .with_verify_url(&mock.url)
+                .with_token_source(source);
+
+            let response = app(config)
+                .oneshot(
+                    Request::post("/submit")
+                        .header("content-type", content_type)
+                        .body(Body::from(payload))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert_eq!(body, "Malformed Turnstile token");
+            assert!(mock.requests().is_empty());
+        }
+    }
+}
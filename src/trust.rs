@@ -0,0 +1,106 @@
+use crate::expiring::ExpiringMap;
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Remembers client IPs that recently passed verification
+#[derive(Debug)]
+pub(crate) struct IpTrust {
+    window: Duration,
+    verified_at: Mutex<ExpiringMap<String, Instant>>,
+}
+
+impl IpTrust {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            verified_at: Mutex::new(ExpiringMap::new()),
+        }
+    }
+
+    /// Trust `ip` for the window, starting now
+    pub fn trust(&self, ip: &str) {
+        let now = Instant::now();
+        let mut verified_at = self.verified_at.lock().unwrap();
+        verified_at.prune(|_, at| now.duration_since(*at) < self.window);
+        verified_at.insert(ip.to_string(), now);
+    }
+
+    /// Whether `ip` passed verification within the window
+    pub fn is_trusted(&self, ip: &str) -> bool {
+        self.verified_at
+            .lock()
+            .unwrap()
+            .get(ip)
+            .is_some_and(|at| at.elapsed() < self.window)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        test_support::MockSiteverify, Decision, TurnstileConfig, TurnstileLayer, TurnstileOutcome,
+    };
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        routing::post,
+        Extension, Router,
+    };
+    use std::time::Duration;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_repeat_visitor_within_window_skips_verification() {
+        let mock = MockSiteverify::success().await;
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&mock.url)
+            .with_remote_ip_header("CF-Connecting-IP")
+            .with_ip_trust_window(Duration::from_millis(300));
+        let app = Router::new()
+            .route(
+                "/submit",
+                post(
+                    |Extension(outcome): Extension<TurnstileOutcome>| async move {
+                        (Extension(outcome.decision), "OK")
+                    },
+                ),
+            )
+            .layer(TurnstileLayer::new(config));
+        let send = |ip: &str, token: Option<&str>| {
+            let mut request = Request::post("/submit").header("CF-Connecting-IP", ip);
+            if let Some(token) = token {
+                request = request.header("CF-Turnstile-Token", token);
+            }
+            let response = app.clone().oneshot(request.body(Body::empty()).unwrap());
+            async move {
+                let response = response.await.unwrap();
+                let decision = response.extensions().get::<Decision>().cloned();
+                (response.status(), decision)
+            }
+        };
+
+        assert_eq!(
+            send("203.0.113.7", Some("token")).await,
+            (StatusCode::OK, Some(Decision::Verified))
+        );
+        assert_eq!(
+            send("203.0.113.7", None).await,
+            (StatusCode::OK, Some(Decision::TrustedIp))
+        );
+        assert_eq!(mock.requests().len(), 1);
+
+        // Other IPs still need a token
+        assert_eq!(
+            send("198.51.100.4", None).await,
+            (StatusCode::BAD_REQUEST, None)
+        );
+
+        tokio::time::sleep(Duration::from_millis(400)).await;
+        assert_eq!(
+            send("203.0.113.7", None).await,
+            (StatusCode::BAD_REQUEST, None)
+        );
+    }
+}
@@ -0,0 +1,448 @@
+use crate::{
+    risk::MAX_FUTURE_SKEW, RejectionReason, TurnstileConfig, VerificationPolicy, VerifyResponse,
+};
+use std::time::{Duration, SystemTime};
+
+/// A check applied to a successful Cloudflare response
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ValidatorKind {
+    /// The returned `action` didn't match [`TurnstileConfig::expected_action`], or was
+    /// missing while [`TurnstileConfig::require_action_present`] is set
+    Action,
+    /// The returned `hostname` wasn't in [`TurnstileConfig::expected_hostnames`]
+    Hostname,
+    /// The returned `cdata` didn't match [`TurnstileConfig::expected_cdata`] or didn't start
+    /// with [`TurnstileConfig::cdata_prefix`]
+    Cdata,
+    /// The returned `cdata` didn't match the
+    /// [request fingerprint](TurnstileConfig::with_request_fingerprint)
+    Fingerprint,
+}
+
+impl ValidatorKind {
+    /// Short lowercase name, as used in rejection bodies
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ValidatorKind::Action => "action",
+            ValidatorKind::Hostname => "hostname",
+            ValidatorKind::Cdata => "cdata",
+            ValidatorKind::Fingerprint => "fingerprint",
+        }
+    }
+}
+
+/// Details attached to a `403` response when validators rejected a successful token
+///
+/// Available from the rejection response's extensions, e.g. for logging in an outer layer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ValidationFailure {
+    /// Every configured validator that failed, in the order they ran
+    pub failed_validators: Vec<ValidatorKind>,
+}
+
+/// The policy a token Cloudflare accepted is checked with: the configured built-in checks,
+/// then the config's own [`VerificationPolicy`]
+///
+/// `expected_action` is usually [`TurnstileConfig::expected_action`], but widgets carry
+/// their own. `fingerprint` is the submitted request's, if it was computed.
+pub(crate) fn policy(
+    config: &TurnstileConfig,
+    expected_action: Option<&str>,
+    fingerprint: Option<&str>,
+) -> VerificationPolicy {
+    let mut policy = VerificationPolicy::new();
+
+    if let Some(max_age) = config.max_token_age {
+        policy = policy.add_check(move |verification| {
+            if is_stale(verification.challenge_ts, max_age, SystemTime::now()) {
+                return Err(RejectionReason::Stale);
+            }
+            Ok(())
+        });
+    }
+
+    let expected_action = expected_action.map(str::to_owned);
+    let require_action_present = config.require_action_present;
+    policy = policy.add_validator(ValidatorKind::Action, move |verification| {
+        let action = verification.action.as_deref();
+        let action_matches = match &expected_action {
+            Some(expected) => action == Some(expected.as_str()),
+            None => true,
+        };
+        action_matches && (!require_action_present || action.is_some_and(|a| !a.is_empty()))
+    });
+
+    if !config.expected_hostnames.is_empty() {
+        let expected_hostnames = config.expected_hostnames.clone();
+        policy = policy.add_validator(ValidatorKind::Hostname, move |verification| {
+            verification.hostname.as_deref().is_some_and(|hostname| {
+                expected_hostnames
+                    .iter()
+                    .any(|expected| expected.eq_ignore_ascii_case(hostname))
+            })
+        });
+    }
+
+    if let Some(expected) = config.expected_cdata.clone() {
+        policy = policy.add_validator(ValidatorKind::Cdata, move |verification| {
+            verification.cdata.as_deref() == Some(expected.as_str())
+        });
+    }
+
+    if let Some(prefix) = config.cdata_prefix.clone() {
+        policy = policy.add_validator(ValidatorKind::Cdata, move |verification| {
+            strip_cdata_prefix(verification.cdata.as_deref(), Some(&prefix)).is_some()
+        });
+    }
+
+    if let Some(fingerprint) = fingerprint.map(str::to_owned) {
+        let prefix = config.cdata_prefix.clone();
+        policy = policy.add_validator(ValidatorKind::Fingerprint, move |verification| {
+            let cdata = verification.cdata.as_deref();
+            let bound = match &prefix {
+                Some(prefix) => strip_cdata_prefix(cdata, Some(prefix)),
+                None => cdata,
+            };
+            bound == Some(fingerprint.as_str())
+        });
+    }
+
+    policy.then(&config.policy)
+}
+
+/// Whether the challenge was solved more than `max_age` before `now`
+///
+/// A missing or unreadable `challenge_ts`, or one further ahead than clock skew explains,
+/// counts as stale.
+fn is_stale(solved: Option<SystemTime>, max_age: Duration, now: SystemTime) -> bool {
+    let Some(solved) = solved else {
+        return true;
+    };
+    match now.duration_since(solved) {
+        Ok(age) => age > max_age,
+        Err(ahead) => ahead.duration() > MAX_FUTURE_SKEW,
+    }
+}
+
+/// The rest of the returned `cdata` after [`TurnstileConfig::cdata_prefix`], if it has it
+pub(crate) fn cdata_suffix<'a>(
+    response: &'a VerifyResponse,
+    config: &TurnstileConfig,
+) -> Option<&'a str> {
+    strip_cdata_prefix(response.cdata.as_deref(), config.cdata_prefix.as_deref())
+}
+
+fn strip_cdata_prefix<'a>(cdata: Option<&'a str>, prefix: Option<&str>) -> Option<&'a str> {
+    cdata?.strip_prefix(prefix?)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        test_support::MockSiteverify, timestamp, TurnstileConfig, TurnstileLayer, TurnstileOutcome,
+        ValidationFailure, ValidatorKind,
+    };
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        response::Response,
+        routing::post,
+        Extension, Router,
+    };
+    use std::time::{Duration, SystemTime};
+    use tower::ServiceExt;
+
+    async fn send(config: TurnstileConfig) -> Response {
+        Router::new()
+            .route("/login", post(|| async { "OK" }))
+            .layer(TurnstileLayer::new(config))
+            .oneshot(
+                Request::post("/login")
+                    .header("CF-Turnstile-Token", "token")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+    }
+
+    async fn mock() -> MockSiteverify {
+        MockSiteverify::start(serde_json::json!({
+            "success": true,
+            "action": "login",
+            "hostname": "attacker.example",
+            "cdata": "session-1",
+        }))
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_all_validators_pass() {
+        let mock = mock().await;
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&mock.url)
+            .with_expected_action("login")
+            .with_expected_hostnames(vec!["Attacker.Example".to_string()])
+            .with_expected_cdata("session-1");
+
+        assert_eq!(send(config).await.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_reports_only_failed_validators() {
+        let mock = mock().await;
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&mock.url)
+            .with_expected_action("login")
+            .with_expected_hostnames(vec!["myapp.example".to_string()]);
+
+        let response = send(config).await;
+
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(
+            response.extensions().get::<ValidationFailure>(),
+            Some(&ValidationFailure {
+                failed_validators: vec![ValidatorKind::Hostname],
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_expected_action() {
+        let mock = mock().await;
+        let config = || TurnstileConfig::new("secret").with_verify_url(&mock.url);
+
+        let response = send(config().with_expected_action("login")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = send(config().with_expected_action("signup")).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(
+            response
+                .extensions()
+                .get::<ValidationFailure>()
+                .unwrap()
+                .failed_validators,
+            vec![ValidatorKind::Action]
+        );
+
+        // Nothing to compare against, so whatever action Cloudflare reports is fine
+        assert_eq!(send(config()).await.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_expected_hostnames() {
+        let mock = mock().await;
+        let config = |hostnames: &[&str]| {
+            TurnstileConfig::new("secret")
+                .with_verify_url(&mock.url)
+                .with_expected_hostnames(hostnames.iter().map(|h| h.to_string()).collect())
+        };
+
+        let allowed = config(&["myapp.example", "ATTACKER.example"]);
+        assert_eq!(send(allowed).await.status(), StatusCode::OK);
+
+        let response = send(config(&["myapp.example"])).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(
+            response
+                .extensions()
+                .get::<ValidationFailure>()
+                .unwrap()
+                .failed_validators,
+            vec![ValidatorKind::Hostname]
+        );
+
+        assert_eq!(send(config(&[])).await.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_require_action_present() {
+        for action in [serde_json::Value::Null, "".into()] {
+            let mock = MockSiteverify::start(serde_json::json!({
+                "success": true,
+                "action": action,
+            }))
+            .await;
+            let config = TurnstileConfig::new("secret")
+                .with_verify_url(&mock.url)
+                .with_require_action_present(true);
+
+            let response = send(config).await;
+
+            assert_eq!(response.status(), StatusCode::FORBIDDEN);
+            assert_eq!(
+                response
+                    .extensions()
+                    .get::<ValidationFailure>()
+                    .unwrap()
+                    .failed_validators,
+                vec![ValidatorKind::Action]
+            );
+        }
+
+        let mock = mock().await;
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&mock.url)
+            .with_require_action_present(true);
+        assert_eq!(send(config).await.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_collects_every_failure() {
+        let mock = mock().await;
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&mock.url)
+            .with_expected_action("signup")
+            .with_expected_hostnames(vec!["myapp.example".to_string()])
+            .with_expected_cdata("session-2");
+
+        let response = send(config).await;
+        let failure = response.extensions().get::<ValidationFailure>().unwrap();
+
+        assert_eq!(
+            failure.failed_validators,
+            vec![
+                ValidatorKind::Action,
+                ValidatorKind::Hostname,
+                ValidatorKind::Cdata
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cdata_prefix() {
+        let mock = MockSiteverify::start(serde_json::json!({
+            "success": true,
+            "cdata": "order:12345",
+        }))
+        .await;
+        let app = |prefix: &str| {
+            Router::new()
+                .route(
+                    "/login",
+                    post(
+                        |Extension(outcome): Extension<TurnstileOutcome>| async move {
+                            outcome.cdata_suffix.unwrap_or_default()
+                        },
+                    ),
+                )
+                .layer(TurnstileLayer::new(
+                    TurnstileConfig::new("secret")
+                        .with_verify_url(&mock.url)
+                        .with_cdata_prefix(prefix),
+                ))
+        };
+        let request = || {
+            Request::post("/login")
+                .header("CF-Turnstile-Token", "token")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = app("order:").oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, "12345");
+
+        let response = app("invoice:").oneshot(request()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(
+            response
+                .extensions()
+                .get::<ValidationFailure>()
+                .unwrap()
+                .failed_validators,
+            vec![ValidatorKind::Cdata]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_token_replayed_on_other_request_is_rejected() {
+        let mock = MockSiteverify::start(serde_json::json!({
+            "success": true,
+            "cdata": "POST /a",
+        }))
+        .await;
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&mock.url)
+            .with_request_fingerprint(|parts| format!("{} {}", parts.method, parts.uri.path()));
+        let app = Router::new()
+            .route("/a", post(|| async { "OK" }))
+            .route("/b", post(|| async { "OK" }))
+            .layer(TurnstileLayer::new(config));
+
+        for (path, expected) in [("/a", StatusCode::OK), ("/b", StatusCode::FORBIDDEN)] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::post(path)
+                        .header("CF-Turnstile-Token", "token")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), expected);
+            if expected == StatusCode::FORBIDDEN {
+                let failure = response.extensions().get::<ValidationFailure>().unwrap();
+                assert_eq!(failure.failed_validators, [ValidatorKind::Fingerprint]);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_max_token_age() {
+        let solved_ago =
+            |secs| timestamp::format_rfc3339(SystemTime::now() - Duration::from_secs(secs));
+        for (challenge_ts, expected) in [
+            (Some(solved_ago(10)), StatusCode::OK),
+            (Some(solved_ago(3600)), StatusCode::FORBIDDEN),
+            // A little ahead of our clock is skew, a lot ahead is made up
+            (
+                Some(timestamp::format_rfc3339(
+                    SystemTime::now() + Duration::from_secs(5),
+                )),
+                StatusCode::OK,
+            ),
+            (
+                Some(timestamp::format_rfc3339(
+                    SystemTime::now() + Duration::from_secs(600),
+                )),
+                StatusCode::FORBIDDEN,
+            ),
+            (None, StatusCode::FORBIDDEN),
+        ] {
+            let mock = MockSiteverify::start(serde_json::json!({
+                "success": true,
+                "challenge_ts": challenge_ts,
+            }))
+            .await;
+            let config = TurnstileConfig::new("secret")
+                .with_verify_url(&mock.url)
+                .with_max_token_age(Duration::from_secs(60));
+
+            let response = send(config).await;
+            assert_eq!(response.status(), expected, "{challenge_ts:?}");
+            if expected == StatusCode::FORBIDDEN {
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                assert_eq!(&body[..], b"Turnstile token is stale");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_old_token_accepted_without_max_age() {
+        let mock = MockSiteverify::start(serde_json::json!({
+            "success": true,
+            "challenge_ts": "2020-01-01T00:00:00Z",
+        }))
+        .await;
+        let config = TurnstileConfig::new("secret").with_verify_url(&mock.url);
+        assert_eq!(send(config).await.status(), StatusCode::OK);
+    }
+}
@@ -1,28 +1,1048 @@
-use crate::{TurnstileConfig, VerifyRequest, VerifyResponse};
+use crate::{
+    TurnstileConfig, TurnstileErrorCode, TurnstileMetadata, VerifyRequest, VerifyResponse,
+    TEST_SECRET_FAIL,
+};
+use futures_util::future::BoxFuture;
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
 
-/// Verify a Turnstile token with Cloudflare
-pub async fn verify_token(
+/// Answers verification calls in place of Cloudflare's siteverify endpoint
+///
+/// Set one with [`TurnstileConfig::with_verifier`] to run handlers behind the layer against
+/// canned answers in tests, or to verify through a backend other than reqwest. Without one,
+/// the endpoint is called over HTTP. The verifier replaces a single call: the secret
+/// provider, rate limits, retries, validators and rejection responses work as usual.
+pub trait Verifier: Send + Sync {
+    /// Verify `token`, answering like siteverify would
+    fn verify<'a>(
+        &'a self,
+        token: &'a str,
+        ctx: VerifyContext,
+    ) -> BoxFuture<'a, Result<VerifierResponse, VerifyError>>;
+}
+
+/// What a [`Verifier`] is asked to verify a token with
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct VerifyContext {
+    /// The secret, resolved through the [`SecretProvider`](crate::SecretProvider) if one is set
+    pub secret: String,
+    /// Client IP resolved for `remoteip`, if any
+    pub remote_ip: Option<String>,
+    /// `idempotency_key` of the verification, if any
+    pub idempotency_key: Option<String>,
+}
+
+/// A [`Verifier`]'s answer, with the fields of a siteverify response
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct VerifierResponse {
+    /// Whether the token is valid
+    pub success: bool,
+    /// `error-codes`, such as `invalid-input-response`
+    pub error_codes: Vec<String>,
+    /// When the challenge was solved (RFC 3339)
+    pub challenge_ts: Option<String>,
+    /// Hostname of the site the challenge was solved on
+    pub hostname: Option<String>,
+    /// `action` the widget was rendered with
+    pub action: Option<String>,
+    /// `cdata` the widget was rendered with
+    pub cdata: Option<String>,
+    /// Enterprise `metadata`, such as the ephemeral ID
+    pub metadata: Option<TurnstileMetadata>,
+}
+
+impl VerifierResponse {
+    /// A valid token, with no other fields set
+    pub fn success() -> Self {
+        Self {
+            success: true,
+            ..Self::default()
+        }
+    }
+
+    /// An invalid token, rejected with these error codes
+    pub fn failure(error_codes: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            success: false,
+            error_codes: error_codes.into_iter().map(Into::into).collect(),
+            ..Self::default()
+        }
+    }
+}
+
+impl From<VerifierResponse> for VerifyResponse {
+    fn from(response: VerifierResponse) -> Self {
+        VerifyResponse {
+            success: response.success,
+            error_codes: (!response.error_codes.is_empty()).then_some(response.error_codes),
+            challenge_ts: response.challenge_ts,
+            hostname: response.hostname,
+            action: response.action,
+            cdata: response.cdata,
+            metadata: response.metadata,
+            cf_ray: None,
+        }
+    }
+}
+
+/// Error returned when a token couldn't be verified with Cloudflare
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum VerifyError {
+    /// The configured [`SecretProvider`](crate::SecretProvider) failed
+    Secret(Box<dyn std::error::Error + Send + Sync>),
+    /// The request to the verification endpoint failed
+    Request(reqwest::Error),
+    /// The verification endpoint didn't answer within
+    /// [`TurnstileConfig::timeout`](crate::TurnstileConfig::timeout)
+    Timeout,
+    /// The verification endpoint answered with a non-success HTTP status, with the start
+    /// of the response body
+    ///
+    /// A `429 Too Many Requests` is answered with `503 Service Unavailable` and
+    /// `Retry-After` by the middleware.
+    Http(reqwest::StatusCode, String),
+    /// The verification endpoint answered with an empty body
+    EmptyResponse,
+    /// The verification endpoint's response wasn't valid siteverify JSON
+    Decode(serde_json::Error),
+    /// Cloudflare reported a failure on its side, such as `internal-error`, on every attempt
+    ServerFault(Vec<String>),
+    /// The [global rate limit](crate::TurnstileConfig::with_global_rate_limit) was reached,
+    /// so Cloudflare wasn't called
+    RateLimited,
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyError::Secret(e) => write!(f, "failed to resolve secret: {e}"),
+            VerifyError::Request(e) => write!(f, "verification request failed: {e}"),
+            VerifyError::Timeout => f.write_str("verification request timed out"),
+            VerifyError::Http(status, body) if body.is_empty() => {
+                write!(f, "verification endpoint returned HTTP {status}")
+            }
+            VerifyError::Http(status, body) => {
+                write!(f, "verification endpoint returned HTTP {status}: {body}")
+            }
+            VerifyError::EmptyResponse => {
+                f.write_str("verification endpoint returned an empty body")
+            }
+            VerifyError::Decode(e) => write!(f, "invalid verification response: {e}"),
+            VerifyError::ServerFault(codes) => {
+                write!(f, "verification failed on Cloudflare's side: {codes:?}")
+            }
+            VerifyError::RateLimited => f.write_str("global verification rate limit reached"),
+        }
+    }
+}
+
+impl std::error::Error for VerifyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            VerifyError::Secret(e) => Some(e.as_ref()),
+            VerifyError::Request(e) => Some(e),
+            VerifyError::Timeout
+            | VerifyError::Http(..)
+            | VerifyError::EmptyResponse
+            | VerifyError::ServerFault(_)
+            | VerifyError::RateLimited => None,
+            VerifyError::Decode(e) => Some(e),
+        }
+    }
+}
+
+impl From<reqwest::Error> for VerifyError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            VerifyError::Timeout
+        } else {
+            VerifyError::Request(e)
+        }
+    }
+}
+
+/// How the verification call's body is encoded
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RequestEncoding {
+    /// `application/x-www-form-urlencoded`, as in Cloudflare's examples (default)
+    #[default]
+    Form,
+    /// `application/json`
+    Json,
+}
+
+/// Which of Cloudflare's siteverify endpoints verification calls go to
+///
+/// Cloudflare documents a single global endpoint so far; regional ones will be added here as
+/// they're published, so the URL never has to be copied into configuration.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Region {
+    /// `https://challenges.cloudflare.com/turnstile/v0/siteverify` (default)
+    #[default]
+    Global,
+}
+
+impl Region {
+    /// The siteverify URL of this region
+    pub fn verify_url(self) -> &'static str {
+        match self {
+            Region::Global => "https://challenges.cloudflare.com/turnstile/v0/siteverify",
+        }
+    }
+}
+
+/// How long the phases of a verification call took
+///
+/// reqwest doesn't report DNS resolution and connection setup separately, so `dns` and
+/// `connect` are `None` for now; `ttfb` includes them.
+///
+/// Requests the middleware lets through after calling Cloudflare carry these in their
+/// extensions, so handlers and inner layers can extract them with
+/// `Extension<VerifyTimings>`, e.g. for SLO tracking. They are also on
+/// [`TurnstileOutcome::timings`](crate::TurnstileOutcome::timings).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct VerifyTimings {
+    /// DNS resolution, when known
+    pub dns: Option<Duration>,
+    /// TCP and TLS connection setup, when known
+    pub connect: Option<Duration>,
+    /// From sending the request until the response headers arrived
+    pub ttfb: Duration,
+    /// The whole call, including resolving the secret and reading the body
+    pub total: Duration,
+}
+
+/// Wait before the first retry of a failed call, doubled for each further retry
+const RETRY_BACKOFF: Duration = Duration::from_millis(50);
+
+/// Most time spent waiting between retries of one verification
+const MAX_RETRY_BACKOFF: Duration = Duration::from_millis(500);
+
+/// How much of an error response's body [`VerifyError::Http`] keeps
+const MAX_BODY_SNIPPET: usize = 256;
+
+impl VerifyError {
+    /// Whether the call failed in transit, so the token may not have reached Cloudflare
+    fn is_transient(&self) -> bool {
+        match self {
+            VerifyError::Timeout => true,
+            VerifyError::Request(e) => e.is_connect() || e.is_request(),
+            _ => false,
+        }
+    }
+}
+
+/// Verify a Turnstile token with Cloudflare, timing the call
+///
+/// Server-side failures are retried up to [`TurnstileConfig::internal_error_retries`] times,
+/// with the same `idempotency_key`, and then reported as [`VerifyError::ServerFault`], never
+/// as a rejected token. Failures in transit are retried up to [`TurnstileConfig::retries`]
+/// times, and then tried once against [`TurnstileConfig::fallback_verify_url`] if set.
+pub(crate) async fn verify_token(
     token: &str,
+    remote_ip: Option<String>,
+    idempotency_key: Option<&str>,
     config: &TurnstileConfig,
-) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
-    let client = reqwest::Client::new();
-
-    let response = client
-        .post(&config.verify_url)
-        .json(&VerifyRequest {
-            secret: config.secret.clone(),
-            response: token.to_string(),
+) -> Result<(VerifyResponse, VerifyTimings), VerifyError> {
+    let call = call(token, remote_ip, idempotency_key, config);
+    #[cfg(feature = "tracing")]
+    let call = tracing::Instrument::instrument(
+        call,
+        tracing::info_span!("turnstile.verify", header = %config.header_name),
+    );
+    call.await
+}
+
+async fn call(
+    token: &str,
+    remote_ip: Option<String>,
+    idempotency_key: Option<&str>,
+    config: &TurnstileConfig,
+) -> Result<(VerifyResponse, VerifyTimings), VerifyError> {
+    let started = Instant::now();
+    let secret = match &config.secret_provider {
+        Some(provider) => provider.secret().await.map_err(VerifyError::Secret)?,
+        None => config.secret.clone(),
+    };
+
+    let mut retries_left = config.internal_error_retries;
+    let mut transient_retries_left = config.retries;
+    let mut backoff = RETRY_BACKOFF;
+    let mut backoff_left = MAX_RETRY_BACKOFF;
+    let mut url = config.verify_url();
+    let mut fallback = config.fallback_verify_url.as_deref();
+
+    loop {
+        if let Some(limit) = &config.global_rate_limit
+            && !limit.try_acquire()
+        {
+            return Err(VerifyError::RateLimited);
+        }
+
+        let attempt = siteverify(
+            url,
+            &secret,
+            token,
+            remote_ip.as_deref(),
+            idempotency_key,
+            config,
+        )
+        .await;
+        let (result, ttfb) = match attempt {
+            Err(e) if e.is_transient() && transient_retries_left > 0 => {
+                transient_retries_left -= 1;
+                warn!(error = %e, "Turnstile verification call failed, retrying");
+                back_off(&mut backoff, &mut backoff_left).await;
+                continue;
+            }
+            Err(e) if e.is_transient() && fallback.is_some() => {
+                warn!(error = %e, "Turnstile verification call failed, trying the fallback endpoint");
+                url = fallback.take().unwrap_or(url);
+                continue;
+            }
+            attempt => attempt?,
+        };
+
+        if !result.success
+            && let Some(errors) = &result.error_codes
+        {
+            if errors.iter().any(|code| {
+                TurnstileErrorCode::from(code.as_str()) == TurnstileErrorCode::InternalError
+            }) {
+                if retries_left > 0 {
+                    retries_left -= 1;
+                    warn!(
+                        error_codes = ?errors,
+                        cf_ray = ?result.cf_ray,
+                        "Turnstile verification failed on Cloudflare's side, retrying"
+                    );
+                    back_off(&mut backoff, &mut backoff_left).await;
+                    continue;
+                }
+                return Err(VerifyError::ServerFault(errors.clone()));
+            }
+            warn!(
+                error_codes = ?errors,
+                cf_ray = ?result.cf_ray,
+                "Turnstile verification failed"
+            );
+        }
+
+        let timings = VerifyTimings {
+            dns: None,
+            connect: None,
+            ttfb,
+            total: started.elapsed(),
+        };
+        return Ok((result, timings));
+    }
+}
+
+/// Wait before a retry, doubling the wait each time while keeping the total within
+/// [`MAX_RETRY_BACKOFF`]
+async fn back_off(backoff: &mut Duration, backoff_left: &mut Duration) {
+    let wait = (*backoff).min(*backoff_left);
+    *backoff_left -= wait;
+    *backoff = backoff.saturating_mul(2);
+    tokio::time::sleep(wait).await;
+}
+
+/// Make a single call to the verification endpoint at `url`, returning its answer and how
+/// long the response headers took to arrive
+async fn siteverify(
+    url: &str,
+    secret: &str,
+    token: &str,
+    remote_ip: Option<&str>,
+    idempotency_key: Option<&str>,
+    config: &TurnstileConfig,
+) -> Result<(VerifyResponse, Duration), VerifyError> {
+    if let Some(verifier) = &config.verifier {
+        let ctx = VerifyContext {
+            secret: secret.to_string(),
+            remote_ip: remote_ip.map(str::to_owned),
+            idempotency_key: idempotency_key.map(str::to_owned),
+        };
+        let sent = Instant::now();
+        let result = tokio::time::timeout(config.timeout, verifier.verify(token, ctx))
+            .await
+            .map_err(|_| VerifyError::Timeout)??;
+        return Ok((result.into(), sent.elapsed()));
+    }
+
+    #[cfg(feature = "test-faults")]
+    if let Some(spec) = &config.fault_injection {
+        let sent = Instant::now();
+        let result = tokio::time::timeout(config.timeout, spec.respond())
+            .await
+            .map_err(|_| VerifyError::Timeout)??;
+        return Ok((result, sent.elapsed()));
+    }
+
+    let client = shared_client(config)?;
+    let request = client
+        .post(url)
+        .header(reqwest::header::USER_AGENT, &config.user_agent)
+        .timeout(config.timeout);
+    let body = VerifyRequest {
+        secret: secret.to_string(),
+        response: token.to_string(),
+        remoteip: remote_ip.map(str::to_owned),
+        idempotency_key: idempotency_key.map(str::to_owned),
+    };
+    let request = match config.request_encoding {
+        RequestEncoding::Form => request.form(&body),
+        RequestEncoding::Json => request.json(&body),
+    };
+    let sent = Instant::now();
+    let response = request.send().await?;
+    let ttfb = sent.elapsed();
+    // Cloudflare answers rejected tokens with 200 too, so anything else is the endpoint
+    // or a proxy in front of it failing
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.bytes().await.unwrap_or_default();
+        return Err(VerifyError::Http(status, body_snippet(&body)));
+    }
+    let cf_ray = response
+        .headers()
+        .get("cf-ray")
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_owned);
+
+    // Some proxies answer errors with an empty 200, which deserves a clearer error than
+    // a JSON parse failure
+    let body = response.bytes().await?;
+    if body.trim_ascii().is_empty() {
+        return Err(VerifyError::EmptyResponse);
+    }
+
+    let mut result: VerifyResponse = serde_json::from_slice(&body).map_err(VerifyError::Decode)?;
+    result.cf_ray = cf_ray;
+    Ok((result, ttfb))
+}
+
+/// Open a pooled connection to the verification endpoint ahead of the first request, by
+/// verifying a dummy token with Cloudflare's always-failing test secret
+///
+/// Failures are logged and otherwise ignored; the first verification then connects as usual.
+/// Configs that don't call out over HTTP have nothing to warm up.
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) async fn warm_up(config: &TurnstileConfig) {
+    if config.verifier.is_some() {
+        return;
+    }
+    #[cfg(feature = "test-faults")]
+    if config.fault_injection.is_some() {
+        return;
+    }
+
+    let url = config.verify_url();
+    match siteverify(url, TEST_SECRET_FAIL, "warm-up", None, None, config).await {
+        Ok(_) => debug!(url, "warmed up connection to Turnstile"),
+        Err(e) => warn!(url, error = %e, "Turnstile warm-up failed"),
+    }
+}
+
+/// The start of an error response's body, for logs
+fn body_snippet(body: &[u8]) -> String {
+    let body = String::from_utf8_lossy(&body[..body.len().min(MAX_BODY_SNIPPET)]);
+    body.trim().to_string()
+}
+
+/// The HTTP client for verification calls, built on first use and then shared by every
+/// clone of the config so connections are pooled
+fn shared_client(config: &TurnstileConfig) -> Result<reqwest::Client, reqwest::Error> {
+    if let Some(client) = config.http_client.get() {
+        return Ok(client.clone());
+    }
+    let client = build_client(config)?;
+    Ok(config.http_client.get_or_init(|| client).clone())
+}
+
+/// Build the HTTP client used for verification calls
+#[cfg_attr(
+    not(any(feature = "rustls-tls", feature = "native-tls")),
+    allow(unused_variables)
+)]
+fn build_client(config: &TurnstileConfig) -> Result<reqwest::Client, reqwest::Error> {
+    let builder = reqwest::Client::builder();
+    // Both backends may be compiled in, e.g. `native-tls` on top of the default features
+    #[cfg(feature = "native-tls")]
+    let builder = builder.use_native_tls();
+    #[cfg(all(feature = "rustls-tls", not(feature = "native-tls")))]
+    let builder = builder.use_rustls_tls();
+    #[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
+    let builder = match config.min_tls_version {
+        Some(version) => builder.min_tls_version(version),
+        None => builder,
+    };
+    builder.build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_support::MockSiteverify, DecisionOverride, TurnstileLayer};
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        response::IntoResponse,
+        routing::post,
+        Extension, Json, Router,
+    };
+    use std::sync::{Arc, Mutex};
+    use tower::ServiceExt;
+
+    /// Accepts one token without any network call and remembers what it was asked
+    #[derive(Default)]
+    struct StubVerifier {
+        seen: Mutex<Vec<VerifyContext>>,
+    }
+
+    impl Verifier for StubVerifier {
+        fn verify<'a>(
+            &'a self,
+            token: &'a str,
+            ctx: VerifyContext,
+        ) -> BoxFuture<'a, Result<VerifierResponse, VerifyError>> {
+            Box::pin(async move {
+                self.seen.lock().unwrap().push(ctx);
+                Ok(match token {
+                    "good" => VerifierResponse {
+                        action: Some("login".to_string()),
+                        ..VerifierResponse::success()
+                    },
+                    _ => VerifierResponse::failure(["invalid-input-response"]),
+                })
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_custom_verifier_replaces_endpoint() {
+        let verifier = Arc::new(StubVerifier::default());
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url("http://127.0.0.1:9/siteverify")
+            .with_verifier(verifier.clone())
+            .with_expected_action("login")
+            .with_remote_ip_header("CF-Connecting-IP");
+        let app = Router::new()
+            .route("/submit", post(|| async { "OK" }))
+            .layer(TurnstileLayer::new(config));
+
+        for (token, expected) in [("good", StatusCode::OK), ("bad", StatusCode::FORBIDDEN)] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::post("/submit")
+                        .header("CF-Turnstile-Token", token)
+                        .header("CF-Connecting-IP", "203.0.113.7")
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), expected, "{token}");
+        }
+
+        let seen = verifier.seen.lock().unwrap();
+        assert_eq!(seen.len(), 2);
+        assert_eq!(seen[0].secret, "secret");
+        assert_eq!(seen[0].remote_ip.as_deref(), Some("203.0.113.7"));
+    }
+
+    #[tokio::test]
+    async fn test_empty_body_is_empty_response_error() {
+        let mock = MockSiteverify::respond_raw(|_| StatusCode::OK.into_response()).await;
+        let config = TurnstileConfig::new("secret").with_verify_url(&mock.url);
+
+        let result = verify_token("token", None, None, &config).await;
+        assert!(matches!(result, Err(VerifyError::EmptyResponse)));
+
+        let response = Router::new()
+            .route("/submit", post(|| async { "OK" }))
+            .layer(TurnstileLayer::new(config))
+            .oneshot(
+                Request::post("/submit")
+                    .header("CF-Turnstile-Token", "token")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+    }
+
+    #[tokio::test]
+    async fn test_client_is_reused_across_calls() {
+        let mock = MockSiteverify::success().await;
+        let config = TurnstileConfig::new("secret").with_verify_url(&mock.url);
+
+        verify_token("token", None, None, &config).await.unwrap();
+        verify_token("token", None, None, &config.clone())
+            .await
+            .unwrap();
+
+        // A shared client keeps the connection alive, so both calls come from one port
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].peer, requests[1].peer);
+    }
+
+    #[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
+    #[tokio::test]
+    async fn test_custom_client_reaches_endpoint() {
+        let mock = MockSiteverify::success().await;
+        let mut headers = reqwest::header::HeaderMap::new();
+        headers.insert("X-Corp-Proxy", "on".parse().unwrap());
+        let client = reqwest::Client::builder()
+            .default_headers(headers)
+            .build()
+            .unwrap();
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&mock.url)
+            .with_client(client)
+            // Has no effect on a custom client
+            .with_min_tls_version(reqwest::tls::Version::TLS_1_3);
+
+        verify_token("token", None, None, &config).await.unwrap();
+        assert_eq!(mock.requests()[0].headers["X-Corp-Proxy"], "on");
+    }
+
+    #[cfg(feature = "tracing")]
+    #[tokio::test]
+    async fn test_call_runs_in_span_with_header_name() {
+        let recorder = crate::test_support::SpanRecorder::default();
+        let _default = tracing::subscriber::set_default(recorder.clone());
+
+        let mock = MockSiteverify::success().await;
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&mock.url)
+            .with_header_name("X-Captcha");
+        verify_token("token", None, None, &config).await.unwrap();
+
+        assert_eq!(
+            recorder.fields("turnstile.verify"),
+            [("header".to_string(), "X-Captcha".to_string())]
+        );
+    }
+
+    #[cfg(all(feature = "rustls-tls", not(feature = "native-tls")))]
+    #[tokio::test]
+    async fn test_min_tls_version() {
+        use crate::test_support::MockTlsServer;
+        use tokio_rustls::rustls::{self, AlertDescription};
+
+        let server = MockTlsServer::start(&[&rustls::version::TLS12]).await;
+        let config = TurnstileConfig::new("secret").with_verify_url(&server.url);
+
+        // Without a minimum the handshake gets as far as the untrusted certificate
+        let result = verify_token("token", None, None, &config).await;
+        assert!(matches!(result, Err(VerifyError::Request(_))));
+        assert!(matches!(
+            server.handshake_errors(1).await[..],
+            [rustls::Error::AlertReceived(
+                AlertDescription::CertificateUnknown
+            )]
+        ));
+
+        // With TLS 1.3 required the client never agrees on a version with the server
+        let config = config.with_min_tls_version(reqwest::tls::Version::TLS_1_3);
+        let result = verify_token("token", None, None, &config).await;
+        assert!(matches!(result, Err(VerifyError::Request(_))));
+        assert!(matches!(
+            server.handshake_errors(2).await[1..],
+            [rustls::Error::PeerIncompatible(_)]
+        ));
+
+        // A server that speaks TLS 1.3 passes the version check again
+        let server = MockTlsServer::start(&[&rustls::version::TLS13]).await;
+        let config = config.with_verify_url(&server.url);
+        let result = verify_token("token", None, None, &config).await;
+        assert!(matches!(result, Err(VerifyError::Request(_))));
+        assert!(matches!(
+            server.handshake_errors(1).await[..],
+            [rustls::Error::AlertReceived(
+                AlertDescription::CertificateUnknown
+            )]
+        ));
+    }
+
+    #[cfg(feature = "native-tls")]
+    #[tokio::test]
+    async fn test_unsupported_min_tls_version_is_request_error() {
+        // native-tls can't enforce a TLS 1.3 minimum
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url("http://127.0.0.1:9/siteverify")
+            .with_min_tls_version(reqwest::tls::Version::TLS_1_3);
+
+        let result = verify_token("token", None, None, &config).await;
+        assert!(matches!(result, Err(VerifyError::Request(e)) if e.is_builder()));
+    }
+
+    #[tokio::test]
+    async fn test_timings_reach_outcome() {
+        let mock = MockSiteverify::success().await;
+        let seen = Arc::new(Mutex::new(None));
+        let seen_by_override = seen.clone();
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&mock.url)
+            .with_decision_override(move |outcome| {
+                *seen_by_override.lock().unwrap() = Some(outcome.timings);
+                DecisionOverride::Keep
+            });
+
+        let response = Router::new()
+            .route("/submit", post(|| async { "OK" }))
+            .layer(TurnstileLayer::new(config))
+            .oneshot(
+                Request::post("/submit")
+                    .header("CF-Turnstile-Token", "token")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let timings = seen.lock().unwrap().unwrap().unwrap();
+        assert!(timings.total > Duration::ZERO);
+        assert!(timings.ttfb > Duration::ZERO);
+        assert!(timings.ttfb <= timings.total);
+    }
+
+    #[tokio::test]
+    async fn test_timings_reach_handler() {
+        let mock = MockSiteverify::success().await;
+        mock.delay_with(|_| Duration::from_millis(50));
+        let response = Router::new()
+            .route(
+                "/submit",
+                post(|Extension(timings): Extension<VerifyTimings>| async move {
+                    timings.total.as_millis().to_string()
+                }),
+            )
+            .layer(TurnstileLayer::new(
+                TurnstileConfig::new("secret").with_verify_url(&mock.url),
+            ))
+            .oneshot(
+                Request::post("/submit")
+                    .header("CF-Turnstile-Token", "token")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let total_ms: u128 = std::str::from_utf8(&body).unwrap().parse().unwrap();
+        assert!(total_ms >= 50, "{total_ms}ms");
+    }
+
+    #[tokio::test]
+    async fn test_custom_upstream_error_response() {
+        let mock = MockSiteverify::respond_raw(|_| {
+            (StatusCode::SERVICE_UNAVAILABLE, "<html>down</html>").into_response()
         })
-        .send()
-        .await?;
+        .await;
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&mock.url)
+            .with_upstream_error_response(|e| {
+                let kind = match e {
+                    VerifyError::Http(status, _) => status.as_str(),
+                    _ => "other",
+                };
+                (
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    format!("captcha unavailable ({kind})"),
+                )
+                    .into_response()
+            });
+
+        let response = Router::new()
+            .route("/submit", post(|| async { "OK" }))
+            .layer(TurnstileLayer::new(config))
+            .oneshot(
+                Request::post("/submit")
+                    .header("CF-Turnstile-Token", "token")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
 
-    let result: VerifyResponse = response.json().await?;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, "captcha unavailable (503)");
+    }
+
+    #[tokio::test]
+    async fn test_slow_endpoint_times_out() {
+        let mock = MockSiteverify::success().await;
+        mock.delay_with(|_| Duration::from_secs(5));
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&mock.url)
+            .with_timeout(Duration::from_millis(100));
+
+        let started = Instant::now();
+        let result = verify_token("token", None, None, &config).await;
+        assert!(matches!(result, Err(VerifyError::Timeout)));
+        assert!(started.elapsed() < Duration::from_secs(5));
 
-    if !result.success
-        && let Some(errors) = result.error_codes
-    {
-        eprintln!("Turnstile verification failed: {:?}", errors);
+        assert_eq!(send(config).await, StatusCode::GATEWAY_TIMEOUT);
     }
 
-    Ok(result.success)
+    #[tokio::test]
+    async fn test_transient_failure_is_retried_with_same_key() {
+        let mock = MockSiteverify::success().await;
+        mock.delay_with(|index| match index {
+            0 => Duration::from_secs(5),
+            _ => Duration::ZERO,
+        });
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&mock.url)
+            .with_timeout(Duration::from_millis(100))
+            .with_retries(2);
+
+        assert_eq!(send(config.clone()).await, StatusCode::OK);
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 2);
+        let key = requests[0].json()["idempotency_key"].clone();
+        assert!(key.is_string());
+        assert_eq!(requests[1].json()["idempotency_key"], key);
+
+        // Without retries the same hiccup fails the request
+        mock.delay_with(|_| Duration::from_secs(5));
+        assert_eq!(
+            send(config.with_retries(0)).await,
+            StatusCode::GATEWAY_TIMEOUT
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rejected_token_is_not_retried() {
+        let mock = MockSiteverify::start(serde_json::json!({
+            "success": false,
+            "error-codes": ["invalid-input-response"],
+        }))
+        .await;
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&mock.url)
+            .with_retries(3);
+
+        assert_eq!(send(config).await, StatusCode::FORBIDDEN);
+        assert_eq!(mock.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_primary_fails_over() {
+        let fallback = MockSiteverify::success().await;
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url("http://127.0.0.1:9/siteverify")
+            .with_fallback_verify_url(&fallback.url);
+
+        assert_eq!(send(config).await, StatusCode::OK);
+        let requests = fallback.requests();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0].json()["idempotency_key"].is_string());
+    }
+
+    #[tokio::test]
+    async fn test_clean_failure_does_not_fail_over() {
+        let primary = MockSiteverify::start(serde_json::json!({ "success": false })).await;
+        let fallback = MockSiteverify::success().await;
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&primary.url)
+            .with_fallback_verify_url(&fallback.url);
+
+        assert_eq!(send(config).await, StatusCode::FORBIDDEN);
+        assert_eq!(primary.requests().len(), 1);
+        assert!(fallback.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_user_agent() {
+        let mock = MockSiteverify::success().await;
+        let config = TurnstileConfig::new("secret").with_verify_url(&mock.url);
+
+        verify_token("token", None, None, &config).await.unwrap();
+        let config = config.with_user_agent("myapp/1.2");
+        verify_token("token", None, None, &config).await.unwrap();
+
+        let user_agents: Vec<_> = mock
+            .requests()
+            .iter()
+            .map(|r| r.headers["user-agent"].to_str().unwrap().to_string())
+            .collect();
+        assert_eq!(
+            user_agents,
+            [
+                format!("axum-turnstile/{}", env!("CARGO_PKG_VERSION")),
+                "myapp/1.2".to_string()
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_request_encoding() {
+        let mock = MockSiteverify::success().await;
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&mock.url)
+            .with_remote_ip_header("CF-Connecting-IP");
+
+        for encoding in [RequestEncoding::Form, RequestEncoding::Json] {
+            verify_token(
+                "to&ken",
+                Some("203.0.113.7".to_string()),
+                None,
+                &config.clone().with_request_encoding(encoding),
+            )
+            .await
+            .unwrap();
+        }
+
+        let requests = mock.requests();
+        assert_eq!(
+            requests[0].headers["content-type"],
+            "application/x-www-form-urlencoded"
+        );
+        assert_eq!(
+            requests[0].body,
+            "secret=secret&response=to%26ken&remoteip=203.0.113.7"
+        );
+        assert_eq!(requests[1].headers["content-type"], "application/json");
+        for request in &requests {
+            assert_eq!(request.json()["response"], "to&ken");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_malformed_body_is_decode_error() {
+        let mock = MockSiteverify::respond_raw(|_| "<html>oops</html>".into_response()).await;
+        let config = TurnstileConfig::new("secret").with_verify_url(&mock.url);
+
+        let result = verify_token("token", None, None, &config).await;
+        assert!(matches!(result, Err(VerifyError::Decode(_))));
+    }
+
+    #[tokio::test]
+    async fn test_cf_ray_reaches_outcome() {
+        let mock = MockSiteverify::respond_raw(|_| {
+            (
+                [("cf-ray", "8a1b2c3d4e5f6a7b-AMS")],
+                Json(serde_json::json!({ "success": true })),
+            )
+                .into_response()
+        })
+        .await;
+        let seen = Arc::new(Mutex::new(None));
+        let seen_by_override = seen.clone();
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&mock.url)
+            .with_decision_override(move |outcome| {
+                *seen_by_override.lock().unwrap() = Some(outcome.cf_ray.clone());
+                DecisionOverride::Keep
+            });
+
+        assert_eq!(send(config).await, StatusCode::OK);
+        assert_eq!(
+            seen.lock().unwrap().clone().unwrap().as_deref(),
+            Some("8a1b2c3d4e5f6a7b-AMS")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_error_status_is_http_error() {
+        let mock = MockSiteverify::respond_raw(|_| {
+            let body = Json(serde_json::json!({ "success": false }));
+            (StatusCode::TOO_MANY_REQUESTS, body).into_response()
+        })
+        .await;
+        let config = TurnstileConfig::new("secret").with_verify_url(&mock.url);
+
+        let result = verify_token("token", None, None, &config).await;
+        match result {
+            Err(VerifyError::Http(StatusCode::TOO_MANY_REQUESTS, body)) => {
+                assert_eq!(body, r#"{"success":false}"#);
+            }
+            other => panic!("expected an HTTP error, got {other:?}"),
+        }
+    }
+
+    /// A mock answering `internal-error` to the first `failures` calls, and success after
+    async fn flaky(failures: usize) -> MockSiteverify {
+        let calls = std::sync::atomic::AtomicUsize::new(0);
+        MockSiteverify::respond_with(move |_| {
+            if calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst) < failures {
+                serde_json::json!({ "success": false, "error-codes": ["internal-error"] })
+            } else {
+                serde_json::json!({ "success": true })
+            }
+        })
+        .await
+    }
+
+    async fn send(config: TurnstileConfig) -> StatusCode {
+        Router::new()
+            .route("/submit", post(|| async { "OK" }))
+            .layer(TurnstileLayer::new(config))
+            .oneshot(
+                Request::post("/submit")
+                    .header("CF-Turnstile-Token", "token")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+            .status()
+    }
+
+    #[tokio::test]
+    async fn test_internal_error_is_retried() {
+        let mock = flaky(1).await;
+        let config = TurnstileConfig::new("secret").with_verify_url(&mock.url);
+
+        assert_eq!(send(config).await, StatusCode::OK);
+        assert_eq!(mock.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_persistent_internal_error_is_not_a_rejection() {
+        let mock = flaky(usize::MAX).await;
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&mock.url)
+            .with_internal_error_retries(2);
+
+        let started = Instant::now();
+        let result = verify_token("token", None, None, &config).await;
+        assert!(
+            matches!(result, Err(VerifyError::ServerFault(codes)) if codes == ["internal-error"])
+        );
+        assert_eq!(mock.requests().len(), 3);
+        // The retries backed off like those after failures in transit
+        assert!(
+            started.elapsed() >= RETRY_BACKOFF * 3,
+            "{:?}",
+            started.elapsed()
+        );
+
+        assert_eq!(
+            send(config.with_internal_error_retries(0)).await,
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+        assert_eq!(mock.requests().len(), 4);
+    }
 }
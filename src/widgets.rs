@@ -0,0 +1,182 @@
+use crate::{
+    body::{self, BufferError},
+    middleware::{check_token, take_route_budget, token_error},
+    token::TokenError,
+    Decision, RejectionReason, TurnstileConfig, TurnstileOutcome, VerifyError,
+};
+use axum::{body::Body, http::Request};
+use futures_util::future::join_all;
+use std::collections::BTreeMap;
+
+/// Per-widget outcomes of a request carrying several widgets' tokens
+///
+/// Inserted into the request extensions when [`TurnstileConfig::widgets`] is configured and
+/// every widget passes, so handlers can use `Extension<WidgetOutcomes>`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WidgetOutcomes {
+    /// Outcome of each widget, keyed by its body field
+    pub outcomes: BTreeMap<String, TurnstileOutcome>,
+}
+
+/// Verify every configured widget's token, passing only if all of them pass
+pub(crate) async fn run_widget_checks(
+    req: Request<Body>,
+    outcome: TurnstileOutcome,
+    config: &TurnstileConfig,
+) -> (Request<Body>, TurnstileOutcome, Option<VerifyError>) {
+    let (req, tokens) = extract_fields(req, config).await;
+    let tokens = match tokens {
+        Ok(tokens) => tokens,
+        Err(e) => return (req, token_error(outcome, e, config), None),
+    };
+    let (mut req, within_budget) = take_route_budget(req, config);
+    if !within_budget {
+        let outcome = outcome.reject(RejectionReason::RouteBudgetExhausted);
+        return (req, outcome, None);
+    }
+
+    let checks = config.widgets.iter().zip(&tokens).map(|(widget, token)| {
+        let (field, expected_action) = widget;
+        let mut widget_outcome = TurnstileOutcome::new(outcome.remote_ip.clone());
+        widget_outcome.request_fingerprint = outcome.request_fingerprint.clone();
+        async move {
+            let (widget_outcome, error) =
+                check_token(token, widget_outcome, Some(expected_action), config).await;
+            (field.clone(), widget_outcome, error)
+        }
+    });
+
+    let mut outcomes = BTreeMap::new();
+    let mut rejected = None;
+    for (field, widget_outcome, error) in join_all(checks).await {
+        // Report the first failing widget, in field order
+        if let Decision::Rejected(reason) = &widget_outcome.decision
+            && rejected.is_none()
+        {
+            rejected = Some((reason.clone(), error));
+        }
+        outcomes.insert(field, widget_outcome);
+    }
+    if let Some((reason, error)) = rejected {
+        return (req, outcome.reject(reason), error);
+    }
+
+    req.extensions_mut().insert(WidgetOutcomes { outcomes });
+    (req, outcome, None)
+}
+
+/// Read each widget's token from its top-level field of a JSON body
+async fn extract_fields(
+    req: Request<Body>,
+    config: &TurnstileConfig,
+) -> (Request<Body>, Result<Vec<String>, TokenError>) {
+    let (mut parts, body) = req.into_parts();
+    let bytes = match body::buffer(&parts.headers, body, config.max_body_bytes).await {
+        Ok(bytes) => bytes,
+        Err(BufferError::TooLarge(body)) => {
+            return (Request::from_parts(parts, body), Err(TokenError::TooLarge));
+        }
+        Err(BufferError::Read) => {
+            return (
+                Request::from_parts(parts, Body::empty()),
+                Err(TokenError::Missing),
+            );
+        }
+    };
+
+    let tokens = serde_json::from_slice::<serde_json::Value>(&bytes)
+        .ok()
+        .and_then(|json| {
+            config
+                .widgets
+                .keys()
+                .map(|field| json.get(field)?.as_str().map(str::to_owned))
+                .collect::<Option<Vec<_>>>()
+        })
+        .ok_or(TokenError::Missing);
+
+    let body = body::restore(&mut parts.headers, bytes, None, config.consume_body);
+    (Request::from_parts(parts, body), tokens)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_support::MockSiteverify, TurnstileLayer};
+    use axum::{http::StatusCode, response::Response, routing::post, Extension, Router};
+    use tower::ServiceExt;
+
+    async fn send(body: serde_json::Value) -> Response {
+        // Each token is minted for the action it's named after
+        let mock = MockSiteverify::respond_with(|request| {
+            let token = request.json()["response"].as_str().unwrap().to_string();
+            let action = token.trim_end_matches("-token");
+            serde_json::json!({ "success": true, "action": action })
+        })
+        .await;
+        let config = TurnstileConfig::new("secret")
+            .with_verify_url(&mock.url)
+            .with_widget("address_token", "address")
+            .with_widget("payment_token", "payment");
+
+        Router::new()
+            .route(
+                "/checkout",
+                post(|Extension(widgets): Extension<WidgetOutcomes>| async move {
+                    let actions: Vec<_> = widgets
+                        .outcomes
+                        .iter()
+                        .map(|(field, outcome)| {
+                            format!("{field}={}", outcome.action.as_deref().unwrap_or_default())
+                        })
+                        .collect();
+                    actions.join(",")
+                }),
+            )
+            .layer(TurnstileLayer::new(config))
+            .oneshot(
+                Request::post("/checkout")
+                    .header("Content-Type", "application/json")
+                    .body(Body::from(body.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_all_widgets_pass() {
+        let response = send(serde_json::json!({
+            "address_token": "address-token",
+            "payment_token": "payment-token",
+        }))
+        .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, "address_token=address,payment_token=payment");
+    }
+
+    #[tokio::test]
+    async fn test_one_action_mismatch_rejects() {
+        let response = send(serde_json::json!({
+            "address_token": "address-token",
+            "payment_token": "address-token",
+        }))
+        .await;
+
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, "Turnstile validation failed: action");
+    }
+
+    #[tokio::test]
+    async fn test_missing_widget_token() {
+        let response = send(serde_json::json!({ "address_token": "address-token" })).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}